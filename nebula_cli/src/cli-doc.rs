@@ -1,11 +1,259 @@
-//! [Clap](https://docs.rs/clap/latest/clap/) Version 5 will have a 
-//! [better help format api](https://github.com/clap-rs/clap/issues/2914), 
-//! since then I want to work with [handlebars](https://docs.rs/handlebars/latest/handlebars/) 
-//! to auto generated the help output of nebula cli with markdown.
+//! Generates the documentation of the nebula command line from the [clap] definitions
+//!
+//! Writes a markdown reference of all commands, that is checked in as `docs/cli.md`, and roff man
+//! pages for `nebula` and each subcommand. The `tui` feature adds options, its reference is checked
+//! in as `docs/cli-tui.md`. Run both after changing the command line:
+//!
+//! ```shell
+//! cargo run --bin nebula-cli-doc
+//! cargo run --bin nebula-cli-doc --features tui
+//! ```
 
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use clap::{Arg, Command, CommandFactory as _, Parser};
+use clap_mangen::Man;
 use color_eyre::eyre::Report;
+use nebula_cli::cli::Cli;
+
+#[cfg(not(feature = "tui"))]
+const MARKDOWN_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../docs/cli.md");
+#[cfg(feature = "tui")]
+const MARKDOWN_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../docs/cli-tui.md");
+const MAN_FOLDER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../target/man");
+
+#[derive(Parser, Debug)]
+#[command(about = "Generates the markdown reference and the man pages of the nebula cli")]
+struct DocArgs {
+    /// file of the markdown reference
+    #[arg(long, default_value = MARKDOWN_FILE)]
+    markdown: PathBuf,
+
+    /// folder of the roff man pages
+    #[arg(long, default_value = MAN_FOLDER)]
+    man: PathBuf,
+}
 
 fn main() -> Result<(), Report> {
-    println!("Will create markdown documentation for CLI usage");
+    color_eyre::install()?;
+    let args = DocArgs::parse();
+
+    if let Some(parent) = args.markdown.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&args.markdown, markdown(nebula_command()))?;
+    println!("Wrote markdown reference to {}", args.markdown.display());
+
+    write_man_pages(nebula_command(), &args.man)?;
+    println!("Wrote man pages to {}", args.man.display());
     Ok(())
-}
\ No newline at end of file
+}
+
+/// The command line of nebula independent of the machine that generates the documentation
+fn nebula_command() -> Command {
+    Cli::command().name("nebula").bin_name("nebula").disable_help_subcommand(true)
+}
+
+/// Writes `nebula.1` and `nebula-<subcommand>.1` into the given folder
+fn write_man_pages(mut cmd: Command, folder: &Path) -> Result<(), std::io::Error> {
+    fn generate(cmd: &Command, folder: &Path) -> Result<(), std::io::Error> {
+        for sub in cmd.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+            generate(sub, folder)?;
+        }
+        Man::new(cmd.clone())
+            .source(format!("nebula {}", env!("CARGO_PKG_VERSION")))
+            .manual("Nebula Manual")
+            .generate_to(folder)
+            .map(|_| ())
+    }
+
+    std::fs::create_dir_all(folder)?;
+    cmd.build();
+    generate(&cmd, folder)
+}
+
+/// Renders the reference of the command and all of its subcommands as markdown
+fn markdown(mut cmd: Command) -> String {
+    cmd.build();
+    let mut buf = String::new();
+    writeln!(buf, "# Command-Line Reference of `{}`\n", cmd.get_name()).unwrap();
+    writeln!(
+        buf,
+        "This document is generated by `{}`, do not edit it.\n",
+        if cfg!(feature = "tui") {
+            "cargo run --bin nebula-cli-doc --features tui"
+        } else {
+            "cargo run --bin nebula-cli-doc"
+        }
+    )
+    .unwrap();
+
+    let mut commands = vec![];
+    collect_commands(&cmd, vec![], &mut commands);
+    for (path, _) in commands.iter() {
+        writeln!(buf, "* [`{}`](#{})", path.join(" "), path.join("-")).unwrap();
+    }
+    for (path, cmd) in commands.iter() {
+        blank_line(&mut buf);
+        write_command(&mut buf, path, cmd);
+    }
+    buf.truncate(buf.trim_end().len());
+    buf.push('\n');
+    buf
+}
+
+fn collect_commands<'a>(
+    cmd: &'a Command,
+    mut path: Vec<&'a str>,
+    commands: &mut Vec<(Vec<&'a str>, &'a Command)>,
+) {
+    path.push(cmd.get_name());
+    commands.push((path.clone(), cmd));
+    for sub in cmd.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+        collect_commands(sub, path.clone(), commands);
+    }
+}
+
+fn write_command(buf: &mut String, path: &[&str], cmd: &Command) {
+    writeln!(buf, "## {}\n", path.join(" ")).unwrap();
+    if let Some(about) = cmd.get_about().or(cmd.get_long_about()) {
+        writeln!(buf, "{}\n", about).unwrap();
+    }
+
+    let usage = cmd.clone().render_usage().to_string();
+    let usage = usage.strip_prefix("Usage: ").unwrap_or(&usage);
+    writeln!(buf, "**Usage:** `{}`", usage).unwrap();
+
+    let subcommands: Vec<&Command> =
+        cmd.get_subcommands().filter(|sub| !sub.is_hide_set()).collect();
+    if !subcommands.is_empty() {
+        section(buf, "Commands");
+        for sub in subcommands {
+            let anchor = format!("{}-{}", path.join("-"), sub.get_name());
+            let about = sub.get_about().map(|about| format!(" — {}", about)).unwrap_or_default();
+            writeln!(buf, "* [`{}`](#{}){}", sub.get_name(), anchor, about).unwrap();
+        }
+    }
+
+    let (positionals, options): (Vec<&Arg>, Vec<&Arg>) =
+        cmd.get_arguments().filter(|arg| !arg.is_hide_set()).partition(|arg| arg.is_positional());
+    if !positionals.is_empty() {
+        section(buf, "Arguments");
+        positionals.into_iter().for_each(|arg| write_arg(buf, arg));
+    }
+    if !options.is_empty() {
+        section(buf, "Options");
+        options.into_iter().for_each(|arg| write_arg(buf, arg));
+    }
+}
+
+fn section(buf: &mut String, title: &str) {
+    blank_line(buf);
+    writeln!(buf, "###### {}:\n", title).unwrap();
+}
+
+/// Separates markdown blocks by exactly one empty line
+fn blank_line(buf: &mut String) {
+    while !buf.ends_with("\n\n") {
+        buf.push('\n');
+    }
+}
+
+fn write_arg(buf: &mut String, arg: &Arg) {
+    let value_names = || -> String {
+        match arg.get_value_names() {
+            Some(names) => {
+                names.iter().map(|name| format!("<{}>", name)).collect::<Vec<_>>().join(" ")
+            }
+            None => format!("<{}>", arg.get_id().as_str().to_uppercase()),
+        }
+    };
+
+    let mut flags = vec![];
+    if let Some(short) = arg.get_short() {
+        flags.push(format!("`-{}`", short));
+    }
+    if let Some(long) = arg.get_long() {
+        flags.push(format!("`--{}`", long));
+    }
+    let takes_value = arg.get_action().takes_values();
+    let name = if flags.is_empty() {
+        format!("`{}`", value_names())
+    } else if takes_value {
+        let last = flags.pop().unwrap();
+        flags.push(format!("{} `{}`", last, value_names()));
+        flags.join(", ")
+    } else {
+        flags.join(", ")
+    };
+
+    write!(buf, "* {}", name).unwrap();
+    if let Some(help) = arg.get_help().or(arg.get_long_help()) {
+        write!(buf, " — {}", help).unwrap();
+    }
+    buf.push('\n');
+
+    let mut details = vec![];
+    let defaults: Vec<String> = arg
+        .get_default_values()
+        .iter()
+        .map(|value| format!("`{}`", value.to_string_lossy()))
+        .collect();
+    if takes_value && !defaults.is_empty() {
+        details.push(format!("  Default value: {}", defaults.join(", ")));
+    }
+    let possible_values: Vec<_> =
+        arg.get_possible_values().into_iter().filter(|value| !value.is_hide_set()).collect();
+    if takes_value && !possible_values.is_empty() {
+        let mut values = String::from("  Possible values:");
+        for value in possible_values {
+            let help = value.get_help().map(|help| format!(": {}", help)).unwrap_or_default();
+            write!(values, "\n  - `{}`{}", value.get_name(), help).unwrap();
+        }
+        details.push(values);
+    }
+    for detail in details.iter() {
+        writeln!(buf, "\n{}", detail).unwrap();
+    }
+    if !details.is_empty() {
+        buf.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Checks the reference of the enabled features, the `tui` feature adds options
+    #[test]
+    fn test_markdown_reference_is_up_to_date() {
+        let checked_in = std::fs::read_to_string(MARKDOWN_FILE).unwrap_or_default();
+        let regenerate = if cfg!(feature = "tui") {
+            "docs/cli-tui.md is stale, regenerate it with 'cargo run --bin nebula-cli-doc --features tui'"
+        } else {
+            "docs/cli.md is stale, regenerate it with 'cargo run --bin nebula-cli-doc'"
+        };
+        assert!(checked_in == markdown(nebula_command()), "{}", regenerate);
+    }
+
+    #[test]
+    fn test_man_pages() {
+        let folder = PathBuf::from("tmp_man");
+        write_man_pages(nebula_command(), &folder).unwrap();
+
+        let pages = std::fs::read_dir(&folder).unwrap().count();
+        assert_eq!(pages, nebula_command().get_subcommands().count() + 1);
+        let nebula = std::fs::read_to_string(folder.join("nebula.1")).unwrap();
+        assert!(nebula.contains(".TH nebula 1"));
+        assert!(nebula.contains("nebula\\-install(1)"));
+        let install = std::fs::read_to_string(folder.join("nebula-install.1")).unwrap();
+        assert!(install.contains(".TH nebula-install 1"));
+        assert!(install.contains(concat!("nebula ", env!("CARGO_PKG_VERSION"))));
+        assert!(install.contains("PACKAGE_NAME"));
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
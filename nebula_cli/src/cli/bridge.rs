@@ -12,7 +12,9 @@
 //! The output path uses an implememtation of [PostCommandHandler] which is different for the legacy commaand line
 //! and the [ratatui] based terminal user interface. See [crate::cli::LegacyPostCommandHandler] and [crate::tui::app::RatatuiPostCommandHandler]
 
+use chrono::NaiveDate;
 use clap::Args;
+use clap_complete::ArgValueCompleter;
 
 use color_eyre::{
     Section as _,
@@ -20,10 +22,31 @@ use color_eyre::{
 };
 use nebula_common::{
     NebulaCliState,
-    api::{self, ListArgs, SearchArgs, SyncArgs},
+    api::{
+        self, InfoArgs, InstallArgs, ListArgs, SearchArgs, StatusArgs, SyncArgs, TreeArgs,
+        UninstallArgs,
+    },
+    model::{DateRange, SortLevel, SortSettings},
 };
+use tokio::sync::mpsc;
 
-use super::{PackageStatus, PackageType, PostCommandHandler};
+use super::{
+    PackageStatus, PackageType, PostCommandHandler, SearchKind,
+    complete::{CompletionShell, installed_packages, known_packages},
+};
+
+//---
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct ClapCompletionsArgs {
+    /// shell the completion script is written for
+    shell: CompletionShell,
+}
+
+pub async fn completions(args: ClapCompletionsArgs) -> Result<(), Report> {
+    args.shell.write_registration(&mut std::io::stdout())?;
+    Ok(())
+}
 
 //---
 
@@ -36,16 +59,69 @@ pub async fn init(_args: ClapInitArgs, _state: &mut NebulaCliState) -> Result<()
 
 //---
 
+#[derive(Args, Debug, Clone, Default)]
+pub struct ClapInfoArgs {
+    /// name of the package, optionally with a semver requirement, e.g. 'iris-classical@^1.0'
+    #[arg(add = ArgValueCompleter::new(known_packages))]
+    package_name: String,
+
+    /// ask the registry instead of the local cache, needs network access
+    #[arg(short, long, default_value_t = false)]
+    pub(super) remote: bool,
+}
+
+impl From<ClapInfoArgs> for InfoArgs {
+    fn from(value: ClapInfoArgs) -> Self {
+        InfoArgs { package: value.package_name, remote: value.remote }
+    }
+}
+
+pub async fn info<E: PostCommandHandler>(
+    args: ClapInfoArgs,
+    state: &mut NebulaCliState,
+    pch: &mut E,
+) -> Result<(), Report> {
+    let info_result = api::package_info(args.into(), state).await?;
+
+    pch.on_info(info_result);
+
+    Ok(())
+}
+
+//---
+
 #[derive(Args, Debug, Clone, Default)]
 pub struct ClapInstallArgs {
+    /// name of the package, optionally with a semver requirement, e.g. 'iris-classical@^1.0'
+    #[arg(add = ArgValueCompleter::new(known_packages))]
     package_name: String,
 }
 
-pub async fn install_package(
-    _args: ClapInstallArgs,
-    _state: &mut NebulaCliState,
+impl From<ClapInstallArgs> for InstallArgs {
+    fn from(value: ClapInstallArgs) -> Self {
+        InstallArgs { package: value.package_name, events: None }
+    }
+}
+
+pub async fn install_package<E: PostCommandHandler>(
+    args: ClapInstallArgs,
+    state: &mut NebulaCliState,
+    pch: &mut E,
 ) -> Result<(), Report> {
-    Err(eyre!("not implemented"))
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let args = InstallArgs { events: Some(tx), ..args.into() };
+
+    // the channel closes when the installation is done and all events are handled
+    let (install_result, _) = tokio::join!(api::install_package(args, state), async {
+        while let Some(event) = rx.recv().await {
+            pch.on_download(event);
+        }
+    });
+    let install_result = install_result?;
+
+    pch.on_install(install_result);
+
+    Ok(())
 }
 
 //---
@@ -87,13 +163,83 @@ pub async fn list_packages<E: PostCommandHandler>(
 
 #[derive(Args, Debug, Clone, Default)]
 pub struct ClapSearchArgs {
+    /// text that is searched for, how it is matched depends on '--kind'
     query: String,
-    // todo
+
+    /// how the query is matched: relaxed (default), name or author
+    #[arg(short, long, default_value = "relaxed")]
+    kind: SearchKind,
+
+    /// only packages by this author, can be given several times
+    #[arg(short, long = "author", value_name = "AUTHOR")]
+    authors: Vec<String>,
+
+    /// filter by type of package: dataset, model, both(default)
+    #[arg(short('t'), long("type"), default_value = "both")]
+    package_type: PackageType,
+
+    /// sort levels by created, downloads, name or author, e.g. 'downloads:desc,name'
+    #[arg(short, long, value_delimiter = ',', value_name = "FIELD[:asc|:desc]")]
+    sort: Vec<SortLevel>,
+
+    /// maximal number of packages
+    #[arg(long)]
+    limit: Option<u32>,
+
+    /// number of packages that are skipped
+    #[arg(long)]
+    offset: Option<u32>,
+
+    /// only packages created on or after the day
+    #[arg(long, value_name = "YYYY-MM-DD")]
+    created_after: Option<NaiveDate>,
+
+    /// only packages created on or before the day
+    #[arg(long, value_name = "YYYY-MM-DD")]
+    created_before: Option<NaiveDate>,
+
+    /// only packages updated on or after the day
+    #[arg(long, value_name = "YYYY-MM-DD")]
+    updated_after: Option<NaiveDate>,
+
+    /// only packages updated on or before the day
+    #[arg(long, value_name = "YYYY-MM-DD")]
+    updated_before: Option<NaiveDate>,
+
+    /// minimal number of downloads, needs the registry
+    #[arg(long)]
+    min_downloads: Option<u32>,
+
+    /// maximal number of downloads, needs the registry
+    #[arg(long)]
+    max_downloads: Option<u32>,
+
+    /// category of the delta metadata, e.g. 'classification'
+    #[arg(long)]
+    category: Option<String>,
+
+    /// number of classes of the delta metadata
+    #[arg(long)]
+    classes: Option<u32>,
 }
 
 impl From<ClapSearchArgs> for SearchArgs {
     fn from(value: ClapSearchArgs) -> Self {
-        Self { query: value.query }
+        Self {
+            query: value.query,
+            kind: value.kind.into(),
+            authors: value.authors,
+            package_type: value.package_type.into(),
+            sort: SortSettings { levels: value.sort },
+            limit: value.limit,
+            offset: value.offset,
+            created: DateRange { start: value.created_after, end: value.created_before },
+            updated: DateRange { start: value.updated_after, end: value.updated_before },
+            min_downloads: value.min_downloads,
+            max_downloads: value.max_downloads,
+            category: value.category,
+            classes: value.classes,
+        }
     }
 }
 
@@ -119,8 +265,22 @@ pub async fn search_packages<E: PostCommandHandler>(
 #[derive(Args, Debug, Clone, Default)]
 pub struct ClapStatusArgs {}
 
-pub async fn status(_args: ClapStatusArgs, _state: &mut NebulaCliState) -> Result<(), Report> {
-    Err(eyre!("not implemented"))
+impl From<ClapStatusArgs> for StatusArgs {
+    fn from(_value: ClapStatusArgs) -> Self {
+        StatusArgs {}
+    }
+}
+
+pub async fn status<E: PostCommandHandler>(
+    args: ClapStatusArgs,
+    state: &mut NebulaCliState,
+    pch: &mut E,
+) -> Result<(), Report> {
+    let status_result = api::status(args.into(), state).await?;
+
+    pch.on_status(status_result);
+
+    Ok(())
 }
 
 //---
@@ -128,8 +288,42 @@ pub async fn status(_args: ClapStatusArgs, _state: &mut NebulaCliState) -> Resul
 #[derive(Args, Debug, Clone, Default)]
 pub struct ClapSyncArgs {}
 
-pub async fn sync(_args: ClapSyncArgs, state: &mut NebulaCliState) -> Result<(), Report> {
-    let _reval = api::sync_packages(SyncArgs { last_sync: None }, state).await?;
+pub async fn sync<E: PostCommandHandler>(
+    _args: ClapSyncArgs,
+    state: &mut NebulaCliState,
+    pch: &mut E,
+) -> Result<(), Report> {
+    let sync_result = api::sync_packages(SyncArgs { last_sync: None }, state).await?;
+
+    pch.on_sync(sync_result);
+
+    Ok(())
+}
+
+//---
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct ClapTreeArgs {
+    /// name of the package, optionally with a semver requirement, e.g. 'mobilenet_v3_tf2@^1'
+    #[arg(add = ArgValueCompleter::new(known_packages))]
+    package_name: String,
+}
+
+impl From<ClapTreeArgs> for TreeArgs {
+    fn from(value: ClapTreeArgs) -> Self {
+        TreeArgs { package: value.package_name }
+    }
+}
+
+pub async fn tree<E: PostCommandHandler>(
+    args: ClapTreeArgs,
+    state: &mut NebulaCliState,
+    pch: &mut E,
+) -> Result<(), Report> {
+    let tree = api::dependency_tree(args.into(), state).await?;
+
+    pch.on_tree(tree);
+
     Ok(())
 }
 
@@ -137,18 +331,33 @@ pub async fn sync(_args: ClapSyncArgs, state: &mut NebulaCliState) -> Result<(),
 
 #[derive(Args, Debug, Clone, Default)]
 pub struct ClapUninstallArgs {
+    /// uninstall all packages
+    #[arg(short, long, default_value_t = false)]
     all: bool,
 
-    package_name: String,
+    /// name of the package, optionally with a semver requirement to select versions
+    #[arg(required_unless_present = "all", add = ArgValueCompleter::new(installed_packages))]
+    package_name: Option<String>,
+}
+
+impl From<ClapUninstallArgs> for UninstallArgs {
+    fn from(value: ClapUninstallArgs) -> Self {
+        UninstallArgs { package: value.package_name, all: value.all }
+    }
 }
 
 //---
 
-pub async fn uninstall_package(
-    _args: ClapUninstallArgs,
-    _state: &mut NebulaCliState,
+pub async fn uninstall_package<E: PostCommandHandler>(
+    args: ClapUninstallArgs,
+    state: &mut NebulaCliState,
+    pch: &mut E,
 ) -> Result<(), Report> {
-    Err(eyre!("not implemented"))
+    let uninstall_result = api::uninstall_package(args.into(), state).await?;
+
+    pch.on_uninstall(uninstall_result);
+
+    Ok(())
 }
 
 //---
@@ -160,7 +369,7 @@ pub struct ClapUpdateArgs {
     all: bool,
 
     /// name of the package that shall be updated
-    #[arg(short, long)]
+    #[arg(short, long, add = ArgValueCompleter::new(installed_packages))]
     package_name: Option<String>,
 }
 
@@ -0,0 +1,179 @@
+//! Dynamic shell completions based on [clap_complete]
+//!
+//! The registration script printed by `nebula completions <shell>` calls nebula with the environment
+//! variable `COMPLETE=<shell>` for every completion. Package names and versions are read from the
+//! folders of the local cache and of the installed packages, the descriptors are not loaded.
+//! `--registry` completes the address of the configured `remote_registry`.
+
+use std::{collections::BTreeMap, ffi::OsStr, io::Write, path::Path};
+
+use clap::ValueEnum;
+use clap_complete::{
+    CompletionCandidate,
+    env::{Bash, Elvish, EnvCompleter, Fish, Powershell, Zsh},
+};
+use nebula_common::{
+    api::{INSTALLED_FOLDER, LOCAL_REGISTRY_FOLDER},
+    configuration::cli::get_configuration,
+};
+
+use crate::get_data_dir;
+
+/// Environment variable that switches nebula into completion mode
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompletionShell {
+    #[default]
+    Bash,
+
+    Zsh,
+
+    Fish,
+
+    Elvish,
+
+    Powershell,
+}
+
+impl CompletionShell {
+    fn completer(self) -> &'static dyn EnvCompleter {
+        match self {
+            CompletionShell::Bash => &Bash,
+            CompletionShell::Zsh => &Zsh,
+            CompletionShell::Fish => &Fish,
+            CompletionShell::Elvish => &Elvish,
+            CompletionShell::Powershell => &Powershell,
+        }
+    }
+
+    /// Writes the script that registers the dynamic completions of the binary in the shell
+    pub fn write_registration(self, buf: &mut dyn Write) -> Result<(), std::io::Error> {
+        let completer = std::env::current_exe()
+            .map(|path| path.display().to_string())
+            .unwrap_or("nebula".into());
+        self.completer().write_registration(COMPLETE_VAR, "nebula", "nebula", &completer, buf)
+    }
+}
+
+/// Packages of the local cache and installed packages
+pub fn known_packages(current: &OsStr) -> Vec<CompletionCandidate> {
+    let data = get_data_dir();
+    candidates(current, &data.join(LOCAL_REGISTRY_FOLDER), &data.join(INSTALLED_FOLDER), true)
+}
+
+/// Installed packages only
+pub fn installed_packages(current: &OsStr) -> Vec<CompletionCandidate> {
+    let data = get_data_dir();
+    candidates(current, &data.join(LOCAL_REGISTRY_FOLDER), &data.join(INSTALLED_FOLDER), false)
+}
+
+/// The address of the `remote_registry` of the configuration, the only registry it holds
+pub fn configured_registries(current: &OsStr) -> Vec<CompletionCandidate> {
+    let current = current.to_string_lossy();
+    get_configuration()
+        .into_iter()
+        .map(|cfg| format!("{}:{}", cfg.remote_registry.host, cfg.remote_registry.port))
+        .filter(|address| address.starts_with(current.as_ref()))
+        .map(|address| CompletionCandidate::new(address).help(Some("remote_registry".into())))
+        .collect()
+}
+
+/// Completes `name` or `name@version`, the descriptors are stored in `<folder>/<name>/<version>`
+fn candidates(
+    current: &OsStr,
+    cache: &Path,
+    installed: &Path,
+    include_cache: bool,
+) -> Vec<CompletionCandidate> {
+    let current = current.to_string_lossy();
+    let folders: Vec<&Path> = if include_cache { vec![installed, cache] } else { vec![installed] };
+
+    // value -> installed
+    let mut found: BTreeMap<String, bool> = BTreeMap::new();
+    match current.split_once('@') {
+        Some((name, version)) => {
+            let (exact, version) = match version.strip_prefix('=') {
+                Some(version) => ("=", version),
+                None => ("", version),
+            };
+            for folder in folders.iter() {
+                for entry in sub_folders(&folder.join(name)).filter(|el| el.starts_with(version)) {
+                    let is_installed = *folder == installed;
+                    *found.entry(format!("{}@{}{}", name, exact, entry)).or_default() |=
+                        is_installed;
+                }
+            }
+        }
+        None => {
+            for folder in folders.iter() {
+                for entry in sub_folders(folder).filter(|el| el.starts_with(current.as_ref())) {
+                    *found.entry(entry).or_default() |= *folder == installed;
+                }
+            }
+        }
+    }
+
+    found
+        .into_iter()
+        .map(|(value, is_installed)| {
+            CompletionCandidate::new(value).help(is_installed.then(|| "installed".into()))
+        })
+        .collect()
+}
+
+fn sub_folders(folder: &Path) -> impl Iterator<Item = String> {
+    std::fs::read_dir(folder)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|ft| ft.is_dir()))
+        .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+        .filter(|name| !name.starts_with('.'))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn values(candidates: Vec<CompletionCandidate>) -> Vec<String> {
+        candidates.iter().map(|c| c.get_value().to_string_lossy().to_string()).collect()
+    }
+
+    #[test]
+    fn test_package_candidates() {
+        let root = PathBuf::from("tmp_completions");
+        let _ = std::fs::remove_dir_all(&root);
+        let (cache, installed) = (root.join(LOCAL_REGISTRY_FOLDER), root.join(INSTALLED_FOLDER));
+        for folder in ["cifar-10-binary/1.0.0", "cifar-100/2.1.0", "cifar-100/2.0.0", "iris/1.0.0"]
+        {
+            std::fs::create_dir_all(cache.join(folder)).unwrap();
+        }
+        std::fs::create_dir_all(installed.join("iris/1.0.0")).unwrap();
+
+        let complete = |current: &str, include_cache| {
+            candidates(OsStr::new(current), &cache, &installed, include_cache)
+        };
+        assert_eq!(values(complete("cif", true)), vec!["cifar-10-binary", "cifar-100"]);
+        assert_eq!(values(complete("", false)), vec!["iris"]);
+        assert_eq!(values(complete("cifar-100@2.1", true)), vec!["cifar-100@2.1.0"]);
+        assert_eq!(
+            values(complete("cifar-100@=", true)),
+            vec!["cifar-100@=2.0.0", "cifar-100@=2.1.0"]
+        );
+        let iris = complete("iris", true);
+        assert_eq!(iris[0].get_help().map(|help| help.to_string()), Some("installed".into()));
+
+        // the configuration of the crate is found relative to the working directory
+        assert_eq!(values(configured_registries(OsStr::new("127."))), vec!["127.0.0.1:12345"]);
+        assert!(configured_registries(OsStr::new("localhost")).is_empty());
+
+        let mut script = vec![];
+        CompletionShell::Bash.write_registration(&mut script).unwrap();
+        assert!(String::from_utf8(script).unwrap().contains("COMPLETE"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -2,16 +2,24 @@
 //!
 //! Contains the text-command interface based on clap for the nebula command line tool.
 
-use std::ffi::OsString;
+use std::{cell::RefCell, ffi::OsString};
 
 use clap::{Parser, Subcommand, ValueEnum};
+use clap_complete::ArgValueCompleter;
 
 use color_eyre::{Section, eyre::Report};
 use nebula_common::{
     NebulaCliState,
-    api::ListResult,
+    api::{
+        InfoResult, InstallResult, ListResult, NetworkError, StatusResult, SyncResult,
+        UninstallResult,
+    },
+    client::download::DownloadEvent,
     datapackage::DataPackage,
-    model::{PackageStatus as ApiPackageStatus, PackageType as ApiPackageType},
+    model::{
+        PackageStatus as ApiPackageStatus, PackageType as ApiPackageType,
+        SearchKind as ApiSearchKind, resolver::DependencyTree,
+    },
     nebula_proto::PackageInfo,
 };
 
@@ -20,6 +28,24 @@ use crate::version;
 mod bridge;
 use bridge::*;
 
+mod complete;
+pub use complete::COMPLETE_VAR;
+use complete::configured_registries;
+
+mod output;
+use output::Table;
+pub use output::{
+    ExitStatus, JsonPostCommandHandler, OutputFormat, TablePostCommandHandler,
+    YamlPostCommandHandler,
+};
+
+mod progress;
+use progress::ProgressLine;
+pub(crate) use progress::format_bytes;
+
+mod repl;
+pub(crate) use repl::split_words;
+
 mod run;
 pub use run::run_legacy_cmd;
 
@@ -39,6 +65,18 @@ pub struct Cli {
     #[arg(short, long, default_value_t = false)]
     pub verbose: bool,
 
+    /// forbid network access and work with the local cache only
+    #[arg(long, default_value_t = false)]
+    pub offline: bool,
+
+    /// address of the remote registry as <HOST>:<PORT>, overrides the configured remote_registry
+    #[arg(long, value_name = "HOST:PORT", add = ArgValueCompleter::new(configured_registries))]
+    pub registry: Option<String>,
+
+    /// format of the command output, json and yaml are meant for scripts
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
     #[command(subcommand)]
     /// command that is executed
     pub cmd: Option<Command>,
@@ -96,6 +134,27 @@ impl From<PackageType> for ApiPackageType {
     }
 }
 
+/// This enum is the same as [nebula_common::model::SearchKind] but extends it with [clap::ValueEnum]
+#[repr(u8)]
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchKind {
+    /// every word is searched in name, title, description, keywords and authors
+    #[default]
+    Relaxed,
+
+    /// substring of the package name
+    Name,
+
+    /// substring of an author
+    Author,
+}
+
+impl From<SearchKind> for ApiSearchKind {
+    fn from(value: SearchKind) -> Self {
+        ApiSearchKind::try_from(value as u8).unwrap()
+    }
+}
+
 #[derive(Debug, Parser)]
 pub struct CmdArgs {
     #[command(subcommand)]
@@ -116,43 +175,68 @@ pub enum Command {
     /// init a virtual environment in the given folder (not yet)
     Init(ClapInitArgs),
 
-    /// prints status information (not yet)
+    /// prints status information
     Status(ClapStatusArgs),
 
-    /// Installs a package (not yet)
+    /// Shows the details of a package, uses the local cache unless '--remote' is given
+    Info(ClapInfoArgs),
+
+    /// Installs a package with its dependencies, needs network access
     Install(ClapInstallArgs),
 
-    /// Updates a specific package or all packages (not yet)
+    /// Updates a specific package or all packages, needs network access (not yet)
     Update(ClapUpdateArgs),
 
-    /// Uninstall a specific package or all packages (not yet)
+    /// Uninstall a specific package or all packages, warns about packages that depend on it
     Uninstall(ClapUninstallArgs),
 
-    /// Searches packages by complex criteria (not yet)
+    /// Searches packages by complex criteria, uses the local cache if offline
     Search(ClapSearchArgs),
 
     /// List packages that fit simple criteria e.g.(non)-installed,
     List(ClapListArgs),
 
-    /// Sync the local cache with the remote registry
+    /// Sync the local cache with the remote registry, needs network access
     Sync(ClapSyncArgs),
+
+    /// Shows the dependency graph of a package as resolved against the local cache
+    Tree(ClapTreeArgs),
+
+    /// Prints the script for dynamic shell completions, e.g. 'source <(nebula completions bash)'
+    Completions(ClapCompletionsArgs),
+}
+
+impl Command {
+    /// true if the command cannot be executed without access to the remote registry
+    pub fn needs_network(&self) -> bool {
+        match self {
+            Command::Info(args) => args.remote,
+            cmd => matches!(cmd, Command::Install(_) | Command::Update(_) | Command::Sync(_)),
+        }
+    }
 }
 
 #[allow(dead_code)]
 pub trait PostCommandHandler {
     fn on_init(&self) {}
-    fn on_status(&self) {}
-    fn on_install(&self) {}
+    fn on_status(&self, _res: StatusResult) {}
+    fn on_info(&self, _res: InfoResult) {}
+    fn on_install(&self, _res: InstallResult) {}
+    fn on_download(&self, _event: DownloadEvent) {}
     fn on_update(&self) {}
-    fn on_uninstall(&self) {}
+    fn on_uninstall(&self, _res: UninstallResult) {}
     fn on_search_packages(&self, _packages: Vec<PackageInfo>) {}
     fn on_list(&self, _res: ListResult) {}
-    fn on_sync(&self) {}
+    fn on_sync(&self, _res: SyncResult) {}
+    fn on_tree(&self, _tree: DependencyTree) {}
     fn on_cli_error(&self, _rep: &Report) {}
     fn on_clap_error(&self, _rep: &Report) {}
 }
 
-pub struct LegacyPostCommandHandler;
+#[derive(Debug, Default)]
+pub struct LegacyPostCommandHandler {
+    downloads: RefCell<ProgressLine>,
+}
 
 impl LegacyPostCommandHandler {
     fn print_package_info(&self, pi: &PackageInfo) {
@@ -162,14 +246,35 @@ impl LegacyPostCommandHandler {
             line.push('-')
         }
         println!("{}", line);
+        if let Some(title) = pi.title.as_ref() {
+            println!("{}", title);
+        }
         println!("Desc: {}", pi.description);
+        if !pi.licenses.is_empty() {
+            let licenses: Vec<&str> = pi.licenses.iter().map(|l| l.name.as_str()).collect();
+            println!("License: {}", licenses.join(", "));
+        }
+        let authors: Vec<String> = pi
+            .authors
+            .iter()
+            .filter_map(|a| match (a.given_name.as_ref(), a.family_name.as_ref()) {
+                (Some(given), Some(family)) => Some(format!("{} {}", given, family)),
+                _ => a.title.clone().or_else(|| a.organization.clone()),
+            })
+            .collect();
+        if !authors.is_empty() {
+            println!("Authors: {}", authors.join(", "));
+        }
+        if !pi.keywords.is_empty() {
+            println!("Keywords: {}", pi.keywords.join(", "));
+        }
         println!("{} bytes download, {} bytes installed", pi.download_size, pi.installed_size);
     }
 
     fn print_datapackage_info(&self, dp: &DataPackage) {
-        let name = dp.name.as_ref().unwrap();
-        let version = dp.version.as_ref().unwrap();
-        let id = dp.id.as_ref().unwrap();
+        let name = dp.name.as_deref().unwrap_or("<unnamed>");
+        let version = dp.version.as_deref().unwrap_or("?");
+        let id = dp.id.as_deref().unwrap_or("no id");
 
         println!("{}-{} | {}", name, version, id);
 
@@ -178,6 +283,25 @@ impl LegacyPostCommandHandler {
         }
         //println!("{} bytes download, {} bytes installed", dp., pi.installed_size);
     }
+
+    fn print_tree(&self, tree: &DependencyTree, prefix: &str) {
+        for (i, dep) in tree.dependencies.iter().enumerate() {
+            let last = i + 1 == tree.dependencies.len();
+            let (branch, indent) =
+                if last { ("└── ", "    ") } else { ("├── ", "│   ") };
+            let repeated = if dep.repeated { " (*)" } else { "" };
+            println!(
+                "{}{}{} {} ({}){}",
+                prefix,
+                branch,
+                dep.name,
+                dep.version,
+                dep.requirement.as_deref().unwrap_or("*"),
+                repeated
+            );
+            self.print_tree(dep, &format!("{}{}", prefix, indent));
+        }
+    }
 }
 
 impl PostCommandHandler for LegacyPostCommandHandler {
@@ -202,6 +326,159 @@ impl PostCommandHandler for LegacyPostCommandHandler {
         }
     }
 
+    fn on_install(&self, res: InstallResult) {
+        for dp in res.unchanged.iter() {
+            println!("Already installed: {}", dp.name.as_deref().unwrap_or_default());
+        }
+        for dp in res.installed.iter() {
+            println!(
+                "Installed: {} {}",
+                dp.name.as_deref().unwrap_or_default(),
+                dp.version.as_deref().unwrap_or_default()
+            );
+        }
+    }
+
+    fn on_download(&self, event: DownloadEvent) {
+        self.downloads.borrow_mut().update(event);
+    }
+
+    fn on_uninstall(&self, res: UninstallResult) {
+        for (name, version) in res.removed.iter() {
+            println!("Uninstalled: {} {}", name, version);
+        }
+        if !res.dependents.is_empty() {
+            println!("Warning: still needed by {}", res.dependents.join(", "));
+        }
+    }
+
+    fn on_sync(&self, res: SyncResult) {
+        println!(
+            "Synced {} packages, {} indexed for the offline search",
+            res.synced.len(),
+            res.indexed
+        );
+        for failed in res.failed.iter() {
+            println!("Failed: {}", failed);
+        }
+    }
+
+    fn on_info(&self, res: InfoResult) {
+        let dp = &res.package;
+        let name = dp.name.as_deref().unwrap_or("<unnamed>");
+        let header = format!("{} {}", name, dp.version.as_deref().unwrap_or("?"));
+        println!("{}", header);
+        println!("{}", "-".repeat(header.chars().count()));
+        if let Some(title) = dp.title.as_ref() {
+            println!("{}", title);
+        }
+        if let Some(desc) = dp.description.as_ref() {
+            println!("Desc: {}", desc);
+        }
+        if let Some(homepage) = dp.homepage.as_ref() {
+            println!("Homepage: {}", homepage);
+        }
+        if !res.versions.is_empty() {
+            println!("Versions: {}", res.versions.join(", "));
+        }
+        if res.remote {
+            println!("Source: registry");
+        }
+
+        if let Some(delta) = dp.delta.as_ref() {
+            println!("Category: {}", delta.category);
+            if let Some(classes) = delta.classes {
+                println!("Classes: {}", classes);
+            }
+            let splits: Vec<String> = [
+                ("training", delta.training_count),
+                ("validation", delta.validation_count),
+                ("test", delta.test_count),
+            ]
+            .into_iter()
+            .filter_map(|(split, count)| count.map(|count| format!("{} {}", count, split)))
+            .collect();
+            if !splits.is_empty() {
+                println!("Splits: {}", splits.join(", "));
+            }
+            if !delta.input_shape.is_empty() {
+                println!("Input shape: {}", delta.input_shape);
+            }
+        }
+
+        if !dp.licenses.is_empty() {
+            println!("Licenses:");
+            for license in dp.licenses.iter() {
+                println!(
+                    "  {} <{}>",
+                    license.title.as_ref().unwrap_or(&license.name),
+                    license.path
+                );
+            }
+        }
+        if let Some(sources) = dp.sources.as_ref().filter(|sources| !sources.is_empty()) {
+            println!("Sources:");
+            for source in sources.iter() {
+                let title = source.title.as_deref().unwrap_or("<untitled>");
+                match source.path.as_ref() {
+                    Some(path) => println!("  {} <{}>", title, path),
+                    None => println!("  {}", title),
+                }
+            }
+        }
+
+        println!("Resources:");
+        let mut table = Table::new(&["NAME", "SIZE", "FORMAT", "ORIGIN", "STORAGE"]);
+        for resource in dp.resources.iter() {
+            let delta = resource.delta.as_ref();
+            table.row(vec![
+                format!("  {}", resource.name),
+                resource.bytes.map(format_bytes).unwrap_or("?".into()),
+                resource.format.clone().unwrap_or_default(),
+                delta.map(|d| d.origin.clone()).unwrap_or_default(),
+                delta.map(|d| d.local_storage.clone()).unwrap_or_default(),
+            ]);
+        }
+        table.print();
+        println!(
+            "{} download, {} installed",
+            format_bytes(res.download_size),
+            format_bytes(res.installed_size)
+        );
+
+        if res.installed.is_empty() {
+            println!("Not installed");
+        }
+        for installed in res.installed.iter() {
+            println!("Installed: {} in {}", installed.version, installed.path.display());
+        }
+    }
+
+    fn on_tree(&self, tree: DependencyTree) {
+        println!("{} {}", tree.name, tree.version);
+        self.print_tree(&tree, "");
+    }
+
+    fn on_status(&self, res: StatusResult) {
+        let registry = res.registry.unwrap_or("not configured".into());
+        let connection = if res.offline {
+            "offline"
+        } else if res.connected {
+            "connected"
+        } else {
+            "not connected"
+        };
+        println!("Registry: {} ({})", registry, connection);
+        if let Some(error) = res.connection_error {
+            println!("Connection error: {}", error);
+        }
+        if let Some(server) = res.server {
+            println!("Server: {} - {}", server.name, server.version);
+        }
+        println!("Local cache: {}", res.cache_folder.display());
+        println!("{} packages in the local cache", res.cached_packages);
+    }
+
     fn on_clap_error(&self, rep: &Report) {
         println!("{:?}", rep)
     }
@@ -216,44 +493,74 @@ pub async fn command_interpret<I, T, C>(
     itr: I,
     state: &mut NebulaCliState,
     pch: &mut C,
-) -> Result<(), Report>
+) -> Result<ExitStatus, Report>
 where
     I: IntoIterator<Item = T>,
     T: Into<OsString> + Clone,
     C: PostCommandHandler,
 {
     match CmdArgs::try_parse_from(itr) {
-        Ok(cli) => {
-            let res = match cli.cmd {
-                Command::Init(init_args) => init(init_args, state).await,
-                Command::Status(status_args) => status(status_args, state).await,
-                Command::Install(install_args) => install_package(install_args, state).await,
-                Command::Update(update_args) => update_package(update_args, state).await,
-                Command::Uninstall(uninstall_args) => {
-                    uninstall_package(uninstall_args, state).await
-                }
-                Command::Search(search_args) => search_packages(search_args, state, pch).await,
-                Command::List(list_args) => list_packages(list_args, state, pch).await,
-
-                Command::Sync(sync_args) => sync(sync_args, state).await,
-            };
-
-            if let Err(err) = res {
-                let err = err.with_note(|| "Probably nothing happened");
-                pch.on_cli_error(&err);
-            }
-        }
+        Ok(cli) => command_execute(cli.cmd, state, pch).await,
         Err(err) => {
             let r: Report = err.into();
             pch.on_clap_error(&r);
+            Ok(ExitStatus::Usage)
         }
     }
+}
+
+pub async fn command_execute<C>(
+    cmd: Command,
+    state: &mut NebulaCliState,
+    pch: &mut C,
+) -> Result<ExitStatus, Report>
+where
+    C: PostCommandHandler,
+{
+    let res = if state.is_offline() && cmd.needs_network() {
+        Err(Report::new(NetworkError::Offline)
+            .wrap_err(format!(
+                "'{}' needs network access to the registry",
+                cmd.to_string().to_lowercase()
+            ))
+            .with_suggestion(|| "run the command without '--offline'"))
+    } else {
+        match cmd {
+            Command::Init(init_args) => init(init_args, state).await,
+            Command::Status(status_args) => status(status_args, state, pch).await,
+            Command::Info(info_args) => info(info_args, state, pch).await,
+            Command::Install(install_args) => install_package(install_args, state, pch).await,
+            Command::Update(update_args) => update_package(update_args, state).await,
+            Command::Uninstall(uninstall_args) => {
+                uninstall_package(uninstall_args, state, pch).await
+            }
+            Command::Search(search_args) => search_packages(search_args, state, pch).await,
+            Command::List(list_args) => list_packages(list_args, state, pch).await,
 
-    Ok(())
+            Command::Sync(sync_args) => sync(sync_args, state, pch).await,
+            Command::Tree(tree_args) => tree(tree_args, state, pch).await,
+            Command::Completions(completions_args) => completions(completions_args).await,
+        }
+    };
+
+    match res {
+        Ok(()) => Ok(ExitStatus::Success),
+        Err(err) => {
+            let err = err.with_note(|| "Probably nothing happened");
+            pch.on_cli_error(&err);
+            Ok(ExitStatus::of(&err))
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use clap::CommandFactory as _;
+    use nebula_common::{
+        api::SearchArgs,
+        model::{SortBy, SortLevel},
+    };
+
     use super::*;
     #[test]
     fn test_package_status_conversion() {
@@ -290,4 +597,120 @@ mod test {
             _ => panic!("Expected Status command variant, got a different one"),
         }
     }
+
+    #[test]
+    fn test_offline_flag_before_command() {
+        let cli = Cli::parse_from(["nebula", "--offline", "list"]);
+        assert!(cli.offline);
+        assert!(matches!(cli.cmd, Some(Command::List(_))));
+        assert!(!cli.cmd.unwrap().needs_network());
+
+        let cli = Cli::parse_from(["nebula", "sync"]);
+        assert!(!cli.offline);
+        assert!(cli.cmd.unwrap().needs_network());
+    }
+
+    #[test]
+    fn test_registry_option() {
+        let cli = Cli::parse_from(["nebula", "--registry", "registry.example.com:12345", "sync"]);
+        assert_eq!(cli.registry.as_deref(), Some("registry.example.com:12345"));
+        assert_eq!(Cli::parse_from(["nebula", "sync"]).registry, None);
+    }
+
+    #[test]
+    fn test_output_format_parsing() {
+        let cli = Cli::parse_from(["nebula", "--output", "json", "list"]);
+        assert_eq!(cli.output, OutputFormat::Json);
+        let cli = Cli::parse_from(["nebula", "-o", "table", "status"]);
+        assert_eq!(cli.output, OutputFormat::Table);
+        assert_eq!(Cli::parse_from(["nebula", "list"]).output, OutputFormat::Text);
+    }
+
+    #[test]
+    fn test_search_args_parsing() {
+        let args = CmdArgs::parse_from([
+            "test",
+            "search",
+            "cifar",
+            "--kind",
+            "name",
+            "--author",
+            "Krizhevsky",
+            "--author",
+            "Hinton",
+            "--type",
+            "dataset",
+            "--sort",
+            "downloads:desc,name",
+            "--limit",
+            "5",
+            "--created-after",
+            "2009-01-01",
+            "--max-downloads",
+            "100",
+            "--category",
+            "classification",
+            "--classes",
+            "10",
+        ]);
+        let Command::Search(search_args) = args.cmd else {
+            panic!("Expected Search command variant");
+        };
+        let args: SearchArgs = search_args.into();
+        assert_eq!(args.kind, ApiSearchKind::Name);
+        assert_eq!(args.authors, vec!["Krizhevsky".to_string(), "Hinton".to_string()]);
+        assert_eq!(args.package_type, ApiPackageType::Dataset);
+        assert_eq!(
+            args.sort.levels,
+            vec![
+                SortLevel { by: SortBy::Downloads, descending: true },
+                SortLevel { by: SortBy::Name, descending: false }
+            ]
+        );
+        assert_eq!((args.limit, args.offset), (Some(5), None));
+        assert_eq!(args.created.start.unwrap().to_string(), "2009-01-01");
+        assert!(args.created.end.is_none() && args.updated.is_unbounded());
+        assert_eq!((args.min_downloads, args.max_downloads), (None, Some(100)));
+        assert_eq!((args.category.as_deref(), args.classes), (Some("classification"), Some(10)));
+
+        assert!(CmdArgs::try_parse_from(["test", "search", "x", "--sort", "size"]).is_err());
+        assert!(
+            CmdArgs::try_parse_from(["test", "search", "x", "--created-after", "2009"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_completions_parsing() {
+        let cli = Cli::parse_from(["nebula", "completions", "powershell"]);
+        assert!(!cli.cmd.unwrap().needs_network());
+        assert!(CmdArgs::try_parse_from(["test", "completions", "tcsh"]).is_err());
+
+        // every package argument completes dynamically
+        let cmd = Cli::command();
+        for sub in ["install", "info", "update", "uninstall", "tree"] {
+            let sub = cmd.find_subcommand(sub).unwrap();
+            let arg = sub.get_arguments().find(|arg| arg.get_id() == "package_name").unwrap();
+            assert!(arg.get::<clap_complete::ArgValueCompleter>().is_some(), "{}", sub.get_name());
+        }
+    }
+
+    #[test]
+    fn test_info_parsing() {
+        let cli = Cli::parse_from(["nebula", "info", "iris-classical@^1"]);
+        assert!(!cli.cmd.unwrap().needs_network());
+
+        let cli = Cli::parse_from(["nebula", "info", "--remote", "iris-classical"]);
+        assert!(cli.cmd.unwrap().needs_network());
+        assert!(CmdArgs::try_parse_from(["test", "info"]).is_err());
+    }
+
+    #[test]
+    fn test_dependency_commands_parsing() {
+        let cli = Cli::parse_from(["nebula", "tree", "mobilenet_v3_tf2@^1"]);
+        assert!(!cli.cmd.unwrap().needs_network());
+
+        assert!(CmdArgs::try_parse_from(["test", "uninstall", "--all"]).is_ok());
+        assert!(CmdArgs::try_parse_from(["test", "uninstall", "iris-classical"]).is_ok());
+        assert!(CmdArgs::try_parse_from(["test", "uninstall"]).is_err());
+    }
 }
@@ -0,0 +1,530 @@
+//! Machine readable and tabular output of the command results
+//!
+//! The output format is chosen with `--output`. The [JsonPostCommandHandler] and the
+//! [YamlPostCommandHandler] serialize the complete result of a command to stdout, errors are
+//! serialized to stderr. The [TablePostCommandHandler] aligns the results in columns. Scripts
+//! should additionally rely on the [ExitStatus] of the process.
+
+use std::process::ExitCode;
+
+use clap::ValueEnum;
+use color_eyre::eyre::Report;
+use nebula_common::{
+    api::{
+        InfoResult, InstallResult, ListResult, NetworkError, StatusResult, SyncResult,
+        UninstallResult,
+    },
+    model::resolver::{DependencyTree, ResolveError},
+    nebula_proto::PackageInfo,
+};
+use serde::Serialize;
+use tonic::Code;
+
+use super::PostCommandHandler;
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// human readable text
+    #[default]
+    Text,
+
+    Json,
+
+    Yaml,
+
+    /// human readable with aligned columns
+    Table,
+}
+
+/// Exit codes of the command line tool, they are stable such that scripts can rely on them
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExitStatus {
+    #[default]
+    Success = 0,
+
+    /// an error without a more specific exit code
+    Failure = 1,
+
+    /// invalid command line usage
+    Usage = 2,
+
+    /// the registry is not reachable or network access is disabled
+    Network = 3,
+
+    /// the package does not exist
+    NotFound = 4,
+
+    /// the dependencies cannot be resolved
+    Conflict = 5,
+
+    /// the command was cancelled with Ctrl-C
+    Interrupted = 130,
+}
+
+impl ExitStatus {
+    /// Classifies an error by the errors in its chain
+    pub fn of(report: &Report) -> Self {
+        for cause in report.chain() {
+            if let Some(err) = cause.downcast_ref::<ResolveError>() {
+                return match err {
+                    ResolveError::NotFound(_) => ExitStatus::NotFound,
+                    ResolveError::InvalidSpec(_) => ExitStatus::Usage,
+                    _ => ExitStatus::Conflict,
+                };
+            }
+            if cause.is::<NetworkError>() || cause.is::<tonic::transport::Error>() {
+                return ExitStatus::Network;
+            }
+            if cause.is::<clap::Error>() {
+                return ExitStatus::Usage;
+            }
+            if let Some(status) = cause.downcast_ref::<tonic::Status>() {
+                match status.code() {
+                    Code::NotFound => return ExitStatus::NotFound,
+                    Code::Unavailable | Code::DeadlineExceeded => return ExitStatus::Network,
+                    Code::InvalidArgument => return ExitStatus::Usage,
+                    _ => {}
+                }
+            }
+        }
+        ExitStatus::Failure
+    }
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(value: ExitStatus) -> Self {
+        ExitCode::from(value as u8)
+    }
+}
+
+/// An error as it is serialized to stderr
+#[derive(Debug, Serialize)]
+struct ErrorOutput {
+    error: String,
+
+    /// the errors that caused it, outermost first
+    causes: Vec<String>,
+
+    status: ExitStatus,
+
+    exit_code: u8,
+}
+
+impl From<&Report> for ErrorOutput {
+    fn from(value: &Report) -> Self {
+        let status = ExitStatus::of(value);
+        ErrorOutput {
+            error: value.to_string(),
+            causes: value.chain().skip(1).map(|cause| cause.to_string()).collect(),
+            status,
+            exit_code: status as u8,
+        }
+    }
+}
+
+/// Implements [PostCommandHandler] by serializing the results with the given function
+macro_rules! serializing_post_command_handler {
+    ($handler:ident, $to_string:path) => {
+        impl $handler {
+            fn print<T: Serialize + ?Sized>(value: &T) {
+                match $to_string(value) {
+                    Ok(text) => println!("{}", text.trim_end()),
+                    Err(err) => eprintln!("Serializing the output failed: {}", err),
+                }
+            }
+
+            fn print_error(rep: &Report) {
+                match $to_string(&ErrorOutput::from(rep)) {
+                    Ok(text) => eprintln!("{}", text.trim_end()),
+                    Err(_) => eprintln!("{:?}", rep),
+                }
+            }
+        }
+
+        impl PostCommandHandler for $handler {
+            fn on_status(&self, res: StatusResult) {
+                Self::print(&res);
+            }
+
+            fn on_info(&self, res: InfoResult) {
+                Self::print(&res);
+            }
+
+            fn on_install(&self, res: InstallResult) {
+                Self::print(&res);
+            }
+
+            fn on_uninstall(&self, res: UninstallResult) {
+                Self::print(&res);
+            }
+
+            fn on_search_packages(&self, packages: Vec<PackageInfo>) {
+                Self::print(&packages);
+            }
+
+            fn on_list(&self, res: ListResult) {
+                Self::print(&res);
+            }
+
+            fn on_sync(&self, res: SyncResult) {
+                Self::print(&res);
+            }
+
+            fn on_tree(&self, tree: DependencyTree) {
+                Self::print(&tree);
+            }
+
+            fn on_cli_error(&self, rep: &Report) {
+                Self::print_error(rep);
+            }
+
+            fn on_clap_error(&self, rep: &Report) {
+                Self::print_error(rep);
+            }
+        }
+    };
+}
+
+#[derive(Debug, Default)]
+pub struct JsonPostCommandHandler;
+serializing_post_command_handler!(JsonPostCommandHandler, serde_json::to_string_pretty);
+
+#[derive(Debug, Default)]
+pub struct YamlPostCommandHandler;
+serializing_post_command_handler!(YamlPostCommandHandler, serde_yaml_ng::to_string);
+
+/// Rows of text cells that are aligned in columns
+#[derive(Debug)]
+pub(super) struct Table {
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub(super) fn new(header: &[&str]) -> Self {
+        Table { rows: vec![header.iter().map(|el| el.to_string()).collect()] }
+    }
+
+    pub(super) fn row(&mut self, cells: Vec<String>) {
+        self.rows.push(cells);
+    }
+
+    fn render(&self) -> String {
+        let columns = self.rows.iter().map(|row| row.len()).max().unwrap_or_default();
+        let widths: Vec<usize> = (0..columns)
+            .map(|col| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(col))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        self.rows
+            .iter()
+            .map(|row| {
+                let line: Vec<String> = row
+                    .iter()
+                    .zip(widths.iter())
+                    .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                    .collect();
+                line.join("  ").trim_end().to_string()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub(super) fn print(&self) {
+        println!("{}", self.render());
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TablePostCommandHandler;
+
+impl TablePostCommandHandler {
+    /// The fields, the installed versions and the resources of a package
+    fn info_tables(res: &InfoResult) -> Vec<Table> {
+        let dp = &res.package;
+        let mut table = Table::new(&["FIELD", "VALUE"]);
+        table.row(vec!["name".into(), dp.name.clone().unwrap_or_default()]);
+        table.row(vec!["version".into(), dp.version.clone().unwrap_or_default()]);
+        table.row(vec!["title".into(), dp.title.clone().unwrap_or_default()]);
+        table.row(vec!["versions".into(), res.versions.join(", ")]);
+        let source = if res.remote { "registry" } else { "local cache" };
+        table.row(vec!["descriptor".into(), source.into()]);
+        if let Some(delta) = dp.delta.as_ref() {
+            table.row(vec!["category".into(), delta.category.clone()]);
+            table.row(vec![
+                "classes".into(),
+                delta.classes.map(|c| c.to_string()).unwrap_or_default(),
+            ]);
+            let splits = [
+                ("training", delta.training_count),
+                ("validation", delta.validation_count),
+                ("test", delta.test_count),
+            ];
+            for (split, count) in splits {
+                if let Some(count) = count {
+                    table.row(vec![format!("{} count", split), count.to_string()]);
+                }
+            }
+            table.row(vec!["input shape".into(), delta.input_shape.clone()]);
+        }
+        for license in dp.licenses.iter() {
+            let name = license.title.as_ref().unwrap_or(&license.name);
+            table.row(vec!["license".into(), format!("{} <{}>", name, license.path)]);
+        }
+        for source in dp.sources.iter().flatten() {
+            let title = source.title.as_deref().unwrap_or("<untitled>");
+            let value = match source.path.as_ref() {
+                Some(path) => format!("{} <{}>", title, path),
+                None => title.to_string(),
+            };
+            table.row(vec!["source".into(), value]);
+        }
+        table.row(vec!["download".into(), res.download_size.to_string()]);
+        table.row(vec!["installed size".into(), res.installed_size.to_string()]);
+        let mut tables = vec![table];
+
+        if !res.installed.is_empty() {
+            let mut table = Table::new(&["INSTALLED", "PATH"]);
+            for installed in res.installed.iter() {
+                table.row(vec![installed.version.clone(), installed.path.display().to_string()]);
+            }
+            tables.push(table);
+        }
+
+        let mut table = Table::new(&["RESOURCE", "BYTES", "FORMAT", "ORIGIN", "STORAGE"]);
+        for resource in dp.resources.iter() {
+            let delta = resource.delta.as_ref();
+            table.row(vec![
+                resource.name.clone(),
+                resource.bytes.map(|b| b.to_string()).unwrap_or_default(),
+                resource.format.clone().unwrap_or_default(),
+                delta.map(|d| d.origin.clone()).unwrap_or_default(),
+                delta.map(|d| d.local_storage.clone()).unwrap_or_default(),
+            ]);
+        }
+        tables.push(table);
+        tables
+    }
+
+    fn tree_rows(table: &mut Table, tree: &DependencyTree, depth: usize) {
+        let repeated = if tree.repeated { " (*)" } else { "" };
+        table.row(vec![
+            format!("{}{}{}", "  ".repeat(depth), tree.name, repeated),
+            tree.version.clone(),
+            tree.requirement.clone().unwrap_or("*".into()),
+        ]);
+        for dep in tree.dependencies.iter() {
+            Self::tree_rows(table, dep, depth + 1);
+        }
+    }
+}
+
+impl PostCommandHandler for TablePostCommandHandler {
+    fn on_status(&self, res: StatusResult) {
+        let mut table = Table::new(&["FIELD", "VALUE"]);
+        table.row(vec!["registry".into(), res.registry.unwrap_or("not configured".into())]);
+        table.row(vec!["offline".into(), res.offline.to_string()]);
+        table.row(vec!["connected".into(), res.connected.to_string()]);
+        if let Some(error) = res.connection_error {
+            table.row(vec!["connection error".into(), error]);
+        }
+        if let Some(server) = res.server {
+            table.row(vec!["server".into(), format!("{} - {}", server.name, server.version)]);
+        }
+        table.row(vec!["cache".into(), res.cache_folder.display().to_string()]);
+        table.row(vec!["cached packages".into(), res.cached_packages.to_string()]);
+        table.print();
+    }
+
+    fn on_info(&self, res: InfoResult) {
+        let tables: Vec<String> = Self::info_tables(&res).iter().map(Table::render).collect();
+        println!("{}", tables.join("\n\n"));
+    }
+
+    fn on_install(&self, res: InstallResult) {
+        let mut table = Table::new(&["NAME", "VERSION", "STATE"]);
+        let rows = res
+            .unchanged
+            .iter()
+            .map(|dp| (dp, "unchanged"))
+            .chain(res.installed.iter().map(|dp| (dp, "installed")));
+        for (dp, state) in rows {
+            table.row(vec![
+                dp.name.clone().unwrap_or_default(),
+                dp.version.clone().unwrap_or_default(),
+                state.into(),
+            ]);
+        }
+        table.print();
+    }
+
+    fn on_uninstall(&self, res: UninstallResult) {
+        let mut table = Table::new(&["NAME", "VERSION"]);
+        for (name, version) in res.removed {
+            table.row(vec![name, version]);
+        }
+        table.print();
+        if !res.dependents.is_empty() {
+            eprintln!("Warning: still needed by {}", res.dependents.join(", "));
+        }
+    }
+
+    fn on_search_packages(&self, packages: Vec<PackageInfo>) {
+        let mut table = Table::new(&["NAME", "VERSION", "TYPE", "DOWNLOAD", "DOWNLOADS", "TITLE"]);
+        for pi in packages {
+            table.row(vec![
+                pi.name.clone(),
+                pi.version.clone(),
+                pi.package_type().as_str_name().to_lowercase(),
+                pi.download_size.to_string(),
+                pi.downloads.to_string(),
+                pi.title.unwrap_or(pi.description),
+            ]);
+        }
+        table.print();
+    }
+
+    fn on_list(&self, res: ListResult) {
+        let mut table = Table::new(&["NAME", "VERSION", "ID", "DESCRIPTION"]);
+        for dp in res.packages {
+            table.row(vec![
+                dp.name.clone().unwrap_or_default(),
+                dp.version.clone().unwrap_or_default(),
+                dp.id.clone().unwrap_or_default(),
+                dp.description.clone().unwrap_or_default(),
+            ]);
+        }
+        table.print();
+    }
+
+    fn on_sync(&self, res: SyncResult) {
+        let mut table = Table::new(&["NAME", "STATE"]);
+        for name in res.synced {
+            table.row(vec![name, "synced".into()]);
+        }
+        for failed in res.failed {
+            table.row(vec![failed, "failed".into()]);
+        }
+        table.print();
+        println!("{} packages indexed for the offline search", res.indexed);
+    }
+
+    fn on_tree(&self, tree: DependencyTree) {
+        let mut table = Table::new(&["NAME", "VERSION", "REQUIREMENT"]);
+        Self::tree_rows(&mut table, &tree, 0);
+        table.print();
+    }
+
+    fn on_cli_error(&self, rep: &Report) {
+        eprintln!("{:?}", rep);
+    }
+
+    fn on_clap_error(&self, rep: &Report) {
+        eprintln!("{:?}", rep);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use color_eyre::eyre::eyre;
+    use nebula_common::{
+        api::InstalledVersion,
+        datapackage::{DataPackageNotValidated, DataResourceNotValidated, ValidateData as _},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_table_alignment() {
+        let mut table = Table::new(&["NAME", "VERSION"]);
+        table.row(vec!["iris-classical".into(), "1.0.0".into()]);
+        table.row(vec!["cifar".into(), "10.0.0".into()]);
+        assert_eq!(
+            table.render(),
+            "NAME            VERSION\niris-classical  1.0.0\ncifar           10.0.0"
+        );
+    }
+
+    #[test]
+    fn test_exit_status() {
+        assert_eq!(ExitStatus::of(&eyre!("something")), ExitStatus::Failure);
+        let not_found = Report::new(ResolveError::NotFound("iris".into())).wrap_err("install");
+        assert_eq!(ExitStatus::of(&not_found), ExitStatus::NotFound);
+        assert_eq!(ExitStatus::of(&Report::new(NetworkError::Offline)), ExitStatus::Network);
+        let status = tonic::Status::unavailable("down");
+        assert_eq!(ExitStatus::of(&Report::new(status)), ExitStatus::Network);
+        assert_eq!(ExitCode::from(ExitStatus::Conflict), ExitCode::from(5));
+    }
+
+    #[test]
+    fn test_serialize_results() {
+        // packages without id or version are serialized instead of panicking
+        let dp = DataPackageNotValidated {
+            name: Some("iris".into()),
+            resources: vec![DataResourceNotValidated::default()],
+            ..Default::default()
+        };
+        let res = ListResult { packages: vec![dp.validate().unwrap()] };
+        let json: serde_json::Value = serde_json::to_value(&res).unwrap();
+        assert_eq!(json["packages"][0]["name"], "iris");
+        assert!(json["packages"][0]["id"].is_null());
+
+        let pi = PackageInfo { name: "iris".into(), package_type: 1, ..Default::default() };
+        let yaml = serde_yaml_ng::to_string(&vec![pi]).unwrap();
+        assert!(yaml.contains("package_type: dataset"));
+
+        let error = ErrorOutput::from(&Report::new(NetworkError::Offline).wrap_err("sync"));
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["exit_code"], 3);
+        assert_eq!(json["status"], "network");
+        assert_eq!(json["causes"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_info_tables() {
+        let dp: DataPackageNotValidated = serde_json::from_value(serde_json::json!({
+            "name": "iris",
+            "version": "1.0.0",
+            "resources": [{"name": "data", "bytes": 4551, "format": "csv"}],
+            "licenses": [{"name": "CC0-1.0", "path": "https://creativecommons.org/publicdomain/zero/1.0/"}],
+            "sources": [{"title": "UCI", "path": "https://archive.ics.uci.edu/dataset/53/iris"}],
+            "delta": {
+                "category": "classification",
+                "classes": 3,
+                "training_count": 120,
+                "test_count": 30,
+                "input_shape": "4"
+            }
+        }))
+        .unwrap();
+        let res = InfoResult {
+            package: dp.validate().unwrap(),
+            remote: false,
+            versions: vec!["1.0.0".into()],
+            download_size: 4551,
+            installed_size: 4551,
+            installed: vec![InstalledVersion {
+                version: "1.0.0".into(),
+                path: "installed/iris/1.0.0".into(),
+            }],
+        };
+        let tables: Vec<String> =
+            TablePostCommandHandler::info_tables(&res).iter().map(Table::render).collect();
+        assert_eq!(tables.len(), 3);
+        let fields = &tables[0];
+        assert!(fields.contains("descriptor      local cache"));
+        assert!(fields.contains("training count  120"));
+        assert!(fields.contains("test count      30"));
+        assert!(!fields.contains("validation count"));
+        assert!(fields.contains("CC0-1.0 <https://creativecommons.org/publicdomain/zero/1.0/>"));
+        assert!(fields.contains("UCI <https://archive.ics.uci.edu/dataset/53/iris>"));
+        assert_eq!(tables[1], "INSTALLED  PATH\n1.0.0      installed/iris/1.0.0");
+        assert!(tables[2].contains("data      4551   csv"));
+    }
+}
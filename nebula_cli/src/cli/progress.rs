@@ -0,0 +1,170 @@
+//! A single line progress bar for the downloads of the legacy command line
+//!
+//! The progress of all running downloads is aggregated into one line that is redrawn on stderr,
+//! finished and failed downloads are printed above it.
+
+use std::{collections::BTreeMap, io::Write as _, time::Duration};
+
+use nebula_common::client::download::DownloadEvent;
+
+const BAR_WIDTH: usize = 30;
+
+#[derive(Debug, Clone, Default)]
+struct Running {
+    downloaded: u64,
+
+    total: Option<u64>,
+
+    rate: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct ProgressLine {
+    running: BTreeMap<String, Running>,
+
+    /// length of the drawn line, to clear it before printing a message
+    drawn: usize,
+}
+
+impl ProgressLine {
+    pub fn update(&mut self, event: DownloadEvent) {
+        match event {
+            DownloadEvent::Started { name, total, resumed } => {
+                if resumed > 0 {
+                    self.message(&format!("Resuming {} at {}", name, format_bytes(resumed)));
+                }
+                self.running.insert(name, Running { downloaded: resumed, total, rate: 0.0 });
+            }
+            DownloadEvent::Progress(progress) => {
+                self.running.insert(
+                    progress.name,
+                    Running {
+                        downloaded: progress.downloaded,
+                        total: progress.total,
+                        rate: progress.rate,
+                    },
+                );
+            }
+            DownloadEvent::Finished { name, bytes } => {
+                self.running.remove(&name);
+                self.message(&format!("Downloaded {} ({})", name, format_bytes(bytes)));
+            }
+            DownloadEvent::Failed { name, error } => {
+                self.running.remove(&name);
+                self.message(&format!("Failed {}: {}", name, error));
+            }
+        }
+        self.draw();
+    }
+
+    fn message(&mut self, msg: &str) {
+        self.clear();
+        eprintln!("{}", msg);
+    }
+
+    fn clear(&mut self) {
+        if self.drawn > 0 {
+            eprint!("\r{}\r", " ".repeat(self.drawn));
+            self.drawn = 0;
+        }
+    }
+
+    fn draw(&mut self) {
+        self.clear();
+        if self.running.is_empty() {
+            return;
+        }
+        let line = self.render();
+        eprint!("{}", line);
+        let _ = std::io::stderr().flush();
+        self.drawn = line.chars().count();
+    }
+
+    fn render(&self) -> String {
+        let downloaded: u64 = self.running.values().map(|r| r.downloaded).sum();
+        let rate: f64 = self.running.values().map(|r| r.rate).sum();
+        let total: Option<u64> = self.running.values().map(|r| r.total).sum();
+
+        let (bar, percent, eta) = match total.filter(|total| *total > 0) {
+            Some(total) => {
+                let ratio = (downloaded as f64 / total as f64).min(1.0);
+                let filled = (ratio * BAR_WIDTH as f64) as usize;
+                let eta = (rate > 0.0).then(|| {
+                    Duration::from_secs_f64(total.saturating_sub(downloaded) as f64 / rate)
+                });
+                (
+                    format!("{}{}", "#".repeat(filled), "-".repeat(BAR_WIDTH - filled)),
+                    format!("{:>3}%", (ratio * 100.0) as u32),
+                    eta.map(format_duration).unwrap_or("--:--".into()),
+                )
+            }
+            None => ("?".repeat(BAR_WIDTH), " ?%".into(), "--:--".into()),
+        };
+        format!(
+            "[{}] {} {} {}/s ETA {} ({} running)",
+            bar,
+            percent,
+            format_bytes(downloaded),
+            format_bytes(rate as u64),
+            eta,
+            self.running.len()
+        )
+    }
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", value, UNITS[unit]) }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nebula_common::client::download::DownloadProgress;
+
+    use super::*;
+
+    #[test]
+    fn test_render_aggregates_downloads() {
+        let mut line = ProgressLine::default();
+        line.running
+            .insert("a".into(), Running { downloaded: 512, total: Some(1024), rate: 256.0 });
+        line.running.insert("b".into(), Running { downloaded: 0, total: Some(1024), rate: 256.0 });
+        assert_eq!(
+            line.render(),
+            format!(
+                "[{}{}]  25% 512 B 512 B/s ETA 00:03 (2 running)",
+                "#".repeat(7),
+                "-".repeat(23)
+            )
+        );
+
+        line.update(DownloadEvent::Progress(DownloadProgress {
+            name: "c".into(),
+            downloaded: 10,
+            total: None,
+            rate: 0.0,
+            eta: None,
+        }));
+        assert!(line.render().contains("?%"));
+        line.update(DownloadEvent::Finished { name: "c".into(), bytes: 10 });
+        assert_eq!(line.running.len(), 2);
+
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_duration(Duration::from_secs(3725)), "1:02:05");
+    }
+}
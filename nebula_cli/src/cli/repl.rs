@@ -0,0 +1,258 @@
+//! Line editor of the interactive mode based on [rustyline]
+//!
+//! Offers line editing, a persistent history in the data folder and tab completion. The completion
+//! uses the same [clap_complete] engine as the shell completions, such that commands, options and
+//! package names are completed. Lines are split into words like a shell does, see [split_words].
+
+use std::{ffi::OsString, path::PathBuf};
+
+use clap::CommandFactory as _;
+use color_eyre::eyre::{Report, eyre};
+use rustyline::{
+    Config, Context, Editor, Helper, Highlighter, Hinter, Validator,
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    history::FileHistory,
+};
+
+use super::CmdArgs;
+
+/// File in the data folder that keeps the history of the interactive mode
+pub const HISTORY_FILE: &str = "repl-history.txt";
+
+const HISTORY_SIZE: usize = 1000;
+
+/// Commands that only exist in the interactive mode
+const REPL_COMMANDS: [&str; 3] = ["help", "quit", "exit"];
+
+/// Result of reading one line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadLine {
+    Line(String),
+
+    /// Ctrl-C discarded the line
+    Interrupted,
+
+    /// Ctrl-D ends the session
+    Eof,
+}
+
+pub struct Repl {
+    editor: Editor<ReplHelper, FileHistory>,
+    history: PathBuf,
+}
+
+impl Repl {
+    /// Creates the line editor and loads the history from the given file if it exists
+    pub fn new(history: PathBuf) -> Result<Self, Report> {
+        let config = Config::builder()
+            .max_history_size(HISTORY_SIZE)?
+            .history_ignore_dups(true)?
+            .history_ignore_space(true)
+            .auto_add_history(true)
+            .build();
+        let mut editor = Editor::with_config(config)?;
+        editor.set_helper(Some(ReplHelper));
+        if history.exists() {
+            editor.load_history(&history)?;
+        }
+        Ok(Repl { editor, history })
+    }
+
+    /// Reads a line, blocks until the user hits enter
+    pub fn read_line(&mut self, prompt: &str) -> Result<ReadLine, Report> {
+        match tokio::task::block_in_place(|| self.editor.readline(prompt)) {
+            Ok(line) => Ok(ReadLine::Line(line)),
+            Err(ReadlineError::Interrupted) => Ok(ReadLine::Interrupted),
+            Err(ReadlineError::Eof) => Ok(ReadLine::Eof),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Writes the history, such that it survives the session
+    pub fn save_history(&mut self) -> Result<(), Report> {
+        if let Some(parent) = self.history.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        self.editor.save_history(&self.history)?;
+        Ok(())
+    }
+}
+
+#[derive(Helper, Highlighter, Hinter, Validator)]
+struct ReplHelper;
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let words = Words::from(&line[..pos]);
+        let mut args: Vec<OsString> = vec!["nebula".into()];
+        args.extend(words.words.iter().map(OsString::from));
+        if !words.in_word {
+            args.push(OsString::new());
+        }
+        let index = args.len() - 1;
+        let current = args[index].to_string_lossy().to_string();
+
+        let mut values: Vec<String> = if index == 1 {
+            REPL_COMMANDS
+                .iter()
+                .filter(|cmd| cmd.starts_with(&current))
+                .map(|cmd| cmd.to_string())
+                .collect()
+        } else {
+            vec![]
+        };
+        let candidates =
+            clap_complete::engine::complete(&mut CmdArgs::command(), args, index, None)?;
+        values.extend(
+            candidates
+                .into_iter()
+                .filter(|candidate| !candidate.is_hide_set())
+                .map(|candidate| candidate.get_value().to_string_lossy().to_string()),
+        );
+        values.sort();
+        values.dedup();
+
+        let pairs = values
+            .into_iter()
+            .map(|value| Pair { replacement: quote(&value), display: value })
+            .collect();
+        Ok((words.last_start, pairs))
+    }
+}
+
+/// Splits a line into words like a shell: whitespace separates words unless it is quoted with `'` or
+/// `"` or escaped with `\`. Within double quotes `\` escapes `"` and `\` only.
+pub fn split_words(line: &str) -> Result<Vec<String>, Report> {
+    let words = Words::from(line);
+    match words.open_quote {
+        Some(quote) => Err(eyre!("missing closing quote {}", quote)),
+        None => Ok(words.words),
+    }
+}
+
+/// Quotes the word if the shell-like splitting would change it
+fn quote(word: &str) -> String {
+    if !word.is_empty() && !word.contains(|c: char| c.is_whitespace() || "'\"\\".contains(c)) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r#"'"'"'"#))
+    }
+}
+
+#[derive(Debug, Default)]
+struct Words {
+    words: Vec<String>,
+
+    /// byte offset of the last word, the length of the line if it ends with whitespace
+    last_start: usize,
+
+    /// true if the line ends within a word
+    in_word: bool,
+
+    /// the quote that is not closed at the end of the line
+    open_quote: Option<char>,
+}
+
+impl From<&str> for Words {
+    fn from(line: &str) -> Self {
+        let mut words = Words { last_start: line.len(), ..Default::default() };
+        let mut current = String::new();
+        let mut chars = line.char_indices().peekable();
+        while let Some((idx, c)) = chars.next() {
+            match (words.open_quote, c) {
+                (None, c) if c.is_whitespace() => {
+                    if words.in_word {
+                        words.words.push(std::mem::take(&mut current));
+                        words.in_word = false;
+                    }
+                    continue;
+                }
+                (None, '\'' | '"') => words.open_quote = Some(c),
+                (Some(quote), c) if c == quote => words.open_quote = None,
+                (None | Some('"'), '\\') => match chars.peek() {
+                    Some(&(_, next))
+                        if words.open_quote.is_none() || matches!(next, '"' | '\\') =>
+                    {
+                        current.push(next);
+                        chars.next();
+                    }
+                    _ => current.push('\\'),
+                },
+                (_, c) => current.push(c),
+            }
+            if !words.in_word {
+                words.in_word = true;
+                words.last_start = idx;
+            }
+        }
+
+        if words.in_word {
+            words.words.push(current);
+        } else {
+            words.last_start = line.len();
+        }
+        words
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words("  install  Iris-Classical@^1.0 ").unwrap(),
+            vec!["install", "Iris-Classical@^1.0"]
+        );
+        assert_eq!(
+            split_words(r#"search "climate data" --author 'O'"'"'Brien' a\ b"#).unwrap(),
+            vec!["search", "climate data", "--author", "O'Brien", "a b"]
+        );
+        assert_eq!(
+            split_words(r#"search "" "a\"b\\c\d""#).unwrap(),
+            vec!["search", "", r#"a"b\c\d"#]
+        );
+        assert!(split_words("search 'climate data").is_err());
+        assert!(split_words("").unwrap().is_empty());
+
+        for word in ["iris", "climate data", "O'Brien", "", r"a\b"] {
+            assert_eq!(split_words(&quote(word)).unwrap(), vec![word]);
+        }
+    }
+
+    #[test]
+    fn test_words_at_cursor() {
+        let words = Words::from("info iris");
+        assert_eq!((words.last_start, words.in_word), (5, true));
+        let words = Words::from("search 'climate da");
+        assert_eq!((words.last_start, words.in_word), (7, true));
+        assert_eq!(words.words.last().map(String::as_str), Some("climate da"));
+        let words = Words::from("info ");
+        assert_eq!((words.last_start, words.in_word), (5, false));
+    }
+
+    #[test]
+    fn test_complete_commands() {
+        let history = rustyline::history::DefaultHistory::new();
+        let ctx = Context::new(&history);
+        let values = |line: &str| {
+            let (start, pairs) = ReplHelper.complete(line, line.len(), &ctx).unwrap();
+            (start, pairs.into_iter().map(|pair| pair.replacement).collect::<Vec<_>>())
+        };
+
+        assert_eq!(values("in"), (0, vec!["info".into(), "init".into(), "install".into()]));
+        assert_eq!(values("ex"), (0, vec!["exit".to_string()]));
+        let (start, options) = values("search --cre");
+        assert_eq!(start, 7);
+        assert_eq!(options, vec!["--created-after", "--created-before"]);
+    }
+}
@@ -1,55 +1,94 @@
 //! runner for a legecy command line
 
-use crate::cli::LegacyPostCommandHandler;
+use crate::{cli::LegacyPostCommandHandler, get_data_dir};
 
-use super::{Cli, command_interpret};
+use super::{
+    Cli, ExitStatus, JsonPostCommandHandler, OutputFormat, PostCommandHandler,
+    TablePostCommandHandler, YamlPostCommandHandler, command_execute, command_interpret,
+    repl::{HISTORY_FILE, ReadLine, Repl},
+    split_words,
+};
 use color_eyre::eyre::Report;
 use nebula_common::NebulaCliState;
 
-pub async fn run_legacy_cmd(mut args: Cli, mut state: NebulaCliState) -> Result<(), Report> {
+/// Runs the command or the interactive mode, returns the status of the last command
+pub async fn run_legacy_cmd(args: Cli, state: NebulaCliState) -> Result<ExitStatus, Report> {
+    match args.output {
+        OutputFormat::Text => run_with(args, state, LegacyPostCommandHandler::default()).await,
+        OutputFormat::Json => run_with(args, state, JsonPostCommandHandler).await,
+        OutputFormat::Yaml => run_with(args, state, YamlPostCommandHandler).await,
+        OutputFormat::Table => run_with(args, state, TablePostCommandHandler).await,
+    }
+}
+
+async fn run_with<C: PostCommandHandler>(
+    mut args: Cli,
+    mut state: NebulaCliState,
+    mut post_command_handler: C,
+) -> Result<ExitStatus, Report> {
     use clap::CommandFactory;
     use color_eyre::Section as _;
     use tracing::info;
 
-    use std::io::{self, Write as _};
     info!("{}", super::version());
 
-    let mut post_command_handler = LegacyPostCommandHandler {};
-
-    if let Some(_initial_cmd) = args.cmd {
-        command_interpret(std::env::args_os(), &mut state, &mut post_command_handler).await?;
+    let mut status = ExitStatus::Success;
+    if let Some(initial_cmd) = args.cmd.take() {
+        status = command_execute(initial_cmd, &mut state, &mut post_command_handler).await?;
     } else if !args.interactive {
-        // neither initial cmd nor interactive --> wrong usage
-        Cli::command().print_long_help()?;
+        // neither initial cmd nor interactive --> wrong usage, json and yaml keep stdout parsable
+        if matches!(args.output, OutputFormat::Text | OutputFormat::Table) {
+            Cli::command().print_long_help()?;
+        }
 
-        return Err(Report::msg("Invalid command-line usage")
+        let report = Report::msg("Invalid command-line usage")
             .with_suggestion(|| "Either use 'nebula_cli --i' or use a command: 'nebula_cli list'")
-            .with_suggestion(|| "If unsure use 'nebula_cli help'"));
+            .with_suggestion(|| "If unsure use 'nebula_cli help'");
+        post_command_handler.on_clap_error(&report);
+        return Ok(ExitStatus::Usage);
     } else {
         info!("Type 'help' or 'help <command-name>' for instructions");
     }
 
-    while args.interactive {
-        // Prompt user for input
-        print!("> ");
-        io::stdout().flush()?;
+    if args.interactive {
+        status = run_interactive(status, &mut state, &mut post_command_handler).await?;
+    }
 
-        // Read input from stdin
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
+    info!("Gracefully shutting down");
+    Ok(status)
+}
 
-        input = input.trim().to_lowercase();
-        if input.is_empty() {
-            continue;
-        }
+/// Executes the commands typed by the user until the session ends, returns the status of the last command
+async fn run_interactive<C: PostCommandHandler>(
+    mut status: ExitStatus,
+    state: &mut NebulaCliState,
+    post_command_handler: &mut C,
+) -> Result<ExitStatus, Report> {
+    use clap::CommandFactory;
+
+    let mut repl = Repl::new(get_data_dir().join(HISTORY_FILE))?;
+    loop {
+        let line = match repl.read_line("> ")? {
+            ReadLine::Line(line) => line,
+            ReadLine::Interrupted => continue,
+            ReadLine::Eof => break,
+        };
+        repl.save_history()?;
 
-        let mut it = input.split(' ');
-        if let Some(first) = it.next() {
-            if first == "quit" || first == "exit" {
-                args.interactive = false;
-            } else if first == "help" {
-                if let Some(sub_command) = it.next() {
-                    let sub_command = sub_command.trim();
+        let words = match split_words(&line) {
+            Ok(words) => words,
+            Err(err) => {
+                post_command_handler.on_clap_error(&err);
+                status = ExitStatus::Usage;
+                continue;
+            }
+        };
+
+        match words.first().map(String::as_str) {
+            None => continue,
+            Some("quit" | "exit") => break,
+            Some("help") => {
+                if let Some(sub_command) = words.get(1) {
                     let mut cmd_fac = Cli::command();
                     let cmd_candidate =
                         cmd_fac.get_subcommands_mut().find(|cmd| cmd.get_name() == sub_command);
@@ -62,15 +101,22 @@ pub async fn run_legacy_cmd(mut args: Cli, mut state: NebulaCliState) -> Result<
                 } else {
                     Cli::command().print_long_help()?;
                 }
-            } else {
-                // Convert input to iterator and use in command_interpret
-                input = "nebula_cli ".to_owned() + &input;
-                let args = input.split_whitespace().map(|s| s.to_string()).collect::<Vec<_>>();
-                command_interpret(args.into_iter(), &mut state, &mut post_command_handler).await?;
+            }
+            Some(_) => {
+                let args = std::iter::once("nebula".to_string()).chain(words);
+                // Ctrl-C drops the running command but keeps the session
+                tokio::select! {
+                    res = command_interpret(args, state, post_command_handler) => {
+                        status = res?;
+                    }
+                    _ = tokio::signal::ctrl_c() => {
+                        eprintln!("Cancelled");
+                        status = ExitStatus::Interrupted;
+                    }
+                }
             }
         }
     }
 
-    info!("Gracefully shutting down");
-    Ok(())
+    Ok(status)
 }
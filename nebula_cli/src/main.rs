@@ -1,31 +1,37 @@
 //! Command line interface to manage local nebula-environments
 
-use std::env;
-use std::path::PathBuf;
-
-use clap::Parser as _;
-use cli::Cli;
-use directories::ProjectDirs;
+use std::process::ExitCode;
+
+use clap::{CommandFactory as _, Parser as _};
+use clap_complete::CompleteEnv;
+use nebula_cli::{
+    LOG_ENV, LOG_FILE, PROJECT_NAME,
+    cli::{COMPLETE_VAR, Cli},
+    get_config_dir, get_data_dir,
+};
 use nebula_common::NebulaCliState;
-use nebula_common::configuration::tracing::{AppDefaultValuesFromEnv, initialize_logging};
-
-use lazy_static::lazy_static;
+use nebula_common::configuration::tracing::AppDefaultValuesFromEnv;
 
 use color_eyre::eyre::Report;
 use tracing::level_filters::LevelFilter;
 
 #[cfg(feature = "tui")]
-use tui::run_tui;
+use nebula_cli::tui::{log_channel, run_tui};
+#[cfg(feature = "tui")]
+use nebula_common::configuration::tracing::initialize_logging_with;
+#[cfg(feature = "tui")]
+use tracing_subscriber::Layer as _;
 
 #[cfg(not(feature = "tui"))]
-use cli::run_legacy_cmd;
-
-mod cli;
-#[cfg(feature = "tui")]
-pub mod tui;
+use nebula_cli::cli::run_legacy_cmd;
+#[cfg(not(feature = "tui"))]
+use nebula_common::configuration::tracing::initialize_logging;
 
 #[tokio::main]
-async fn main() -> Result<(), Report> {
+async fn main() -> Result<ExitCode, Report> {
+    // answers the completion requests of the shell and exits
+    CompleteEnv::with_factory(Cli::command).var(COMPLETE_VAR).bin("nebula").complete();
+
     color_eyre::install()?;
 
     // read top-level cli:
@@ -41,11 +47,16 @@ async fn main() -> Result<(), Report> {
     };
 
     let lvl = if args.verbose { LevelFilter::TRACE } else { LevelFilter::INFO };
+    // the TUI shows the logs in a pane instead of the console
+    #[cfg(feature = "tui")]
+    let (log_layer, log_rx) = log_channel();
     #[cfg(feature = "tui")]
     {
+        let (console_lvl, layer) =
+            if args.tui { (None, Some(log_layer.boxed())) } else { (Some(lvl), None) };
         // we did not start another thread yet, therefore the initialization is safe.unsafe
         unsafe {
-            initialize_logging(if args.tui { None } else { Some(lvl) }, env_vars)?;
+            initialize_logging_with(console_lvl, layer, env_vars)?;
         }
     }
     #[cfg(not(feature = "tui"))]
@@ -56,77 +67,16 @@ async fn main() -> Result<(), Report> {
     let mut state = NebulaCliState::new(get_data_dir(), get_config_dir());
     state.init_config()?;
     state.init_data_source();
-    state.init_client().await?;
+    // the client connects lazily, such that commands working on the local cache need no network
+    state.set_offline(args.offline);
+    if let Some(address) = args.registry.as_deref() {
+        state.set_registry_address(address)?;
+    }
 
     #[cfg(feature = "tui")]
-    {
-        run_tui(args, state).await
-    }
+    let status = run_tui(args, state, log_rx).await?;
     #[cfg(not(feature = "tui"))]
-    {
-        run_legacy_cmd(args, state).await
-    }
-}
-
-lazy_static! {
-    pub static ref PROJECT_NAME: String = env!("CARGO_CRATE_NAME").to_uppercase().to_string();
-    pub static ref DATA_FOLDER: Option<PathBuf> =
-        env::var(format!("{}_DATA", PROJECT_NAME.clone())).ok().map(PathBuf::from);
-    pub static ref CONFIG_FOLDER: Option<PathBuf> =
-        env::var(format!("{}_CONFIG", PROJECT_NAME.clone())).ok().map(PathBuf::from);
-    pub static ref LOG_ENV: String = format!("{}_LOGLEVEL", PROJECT_NAME.clone());
-    pub static ref LOG_FILE: String = format!("{}.log", env!("CARGO_PKG_NAME"));
-}
-
-pub fn get_data_dir() -> PathBuf {
-    let directory = if let Some(s) = DATA_FOLDER.clone() {
-        s
-    } else if let Some(proj_dirs) = project_directory() {
-        proj_dirs.data_local_dir().to_path_buf()
-    } else {
-        PathBuf::from(".").join(".data")
-    };
-    directory
-}
-
-pub fn get_config_dir() -> PathBuf {
-    let directory = if let Some(s) = CONFIG_FOLDER.clone() {
-        s
-    } else if let Some(proj_dirs) = project_directory() {
-        proj_dirs.config_local_dir().to_path_buf()
-    } else {
-        PathBuf::from(".").join(".config")
-    };
-    directory
-}
-
-fn project_directory() -> Option<ProjectDirs> {
-    ProjectDirs::from("com", "blackportal.ai", env!("CARGO_PKG_NAME"))
-}
-
-const VERSION_MESSAGE: &str = concat!(
-    env!("CARGO_PKG_VERSION"),
-    "-",
-    env!("VERGEN_GIT_DESCRIBE"),
-    " (",
-    env!("VERGEN_BUILD_DATE"),
-    ")"
-);
-
-pub fn version() -> String {
-    let author = clap::crate_authors!();
-
-    let current_exe_path = PathBuf::from(clap::crate_name!()).display().to_string();
-    let config_dir_path = get_config_dir().display().to_string();
-    let data_dir_path = get_data_dir().display().to_string();
-
-    format!(
-        "\
-{current_exe_path} - {VERSION_MESSAGE}
-
-Authors: {author}
+    let status = run_legacy_cmd(args, state).await?;
 
-Config directory: {config_dir_path}
-Data directory: {data_dir_path}"
-    )
+    Ok(status.into())
 }
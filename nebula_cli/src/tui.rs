@@ -4,8 +4,11 @@ mod action;
 mod app;
 mod components;
 mod config;
+mod logging;
 mod run;
+mod tasks;
 
+pub use logging::log_channel;
 pub use run::run_tui;
 
 pub use app::App;
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Eq, strum::Display, Serialize, Deserialize)]
+use crate::tui::{app::Mode, components::browser::PackageList, tasks::TaskEvent};
+
+#[derive(Debug, Clone, PartialEq, strum::Display, Serialize, Deserialize)]
 pub enum Action {
     Tick,
     Render,
@@ -12,4 +14,25 @@ pub enum Action {
     Error(String),
     Help,
     Command(String),
+
+    /// selects the key bindings of the mode, e.g. while a filter is typed
+    SwitchMode(Mode),
+
+    /// reads the packages of the local cache
+    LoadPackages,
+
+    #[serde(skip)]
+    Packages(PackageList),
+
+    /// a background task changed, see [crate::tui::tasks]
+    Task(TaskEvent),
+
+    /// cancels the running task or the next queued one
+    CancelTask,
+
+    /// cancels the running and all queued tasks
+    CancelAllTasks,
+
+    /// opens or closes the log pane
+    ToggleLogs,
 }
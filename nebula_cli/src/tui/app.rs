@@ -1,20 +1,39 @@
-use color_eyre::{Result, eyre};
+use std::{collections::BTreeSet, rc::Rc};
+
+use color_eyre::Result;
 use crossterm::event::KeyEvent;
-use nebula_common::NebulaCliState;
-use ratatui::prelude::Rect;
+use nebula_common::{
+    NebulaCliState,
+    api::{ListArgs, list_all, list_packages},
+    model::{PackageStatus, PackageType},
+};
+use ratatui::prelude::{Constraint, Layout, Rect};
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
-
-use crate::{
-    cli::{PostCommandHandler, command_interpret},
-    tui::{
-        Event, Tui,
-        action::Action,
-        components::{Component, home::Home},
-        config::Config,
+use tokio::sync::{
+    Mutex,
+    mpsc::{self, Receiver},
+};
+
+use crate::tui::{
+    Event, Tui,
+    action::Action,
+    components::{
+        Component,
+        browser::{PackageBrowser, PackageList},
+        log_pane::LogPane,
+        task_panel::TaskPanel,
     },
+    config::Config,
+    logging::LogRecord,
+    tasks::TaskQueue,
 };
 
+/// height of the task panel below the package browser
+const TASK_PANEL_HEIGHT: u16 = 7;
+
+/// share of the screen that the open log pane takes
+const LOG_PANE_PERCENTAGE: u16 = 40;
+
 pub struct App {
     config: Config,
     tick_rate: f64,
@@ -22,39 +41,54 @@ pub struct App {
     components: Vec<Box<dyn Component>>,
     should_quit: bool,
     should_suspend: bool,
+    show_logs: bool,
     mode: Mode,
     last_tick_key_events: Vec<KeyEvent>,
     action_tx: mpsc::UnboundedSender<Action>,
     action_rx: mpsc::UnboundedReceiver<Action>,
-    state: NebulaCliState,
-    post_command_handler: RatatuiPostCommandHandler,
+    state: Rc<Mutex<NebulaCliState>>,
+    tasks: TaskQueue,
 }
 
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Mode {
     #[default]
     Home,
-}
 
-pub struct RatatuiPostCommandHandler {}
-impl PostCommandHandler for RatatuiPostCommandHandler {}
+    /// text is typed, such that only few keys are bound
+    Filter,
+
+    /// the log pane has the keyboard focus
+    Logs,
+}
 
 impl App {
-    pub fn new(tick_rate: f64, frame_rate: f64, state: NebulaCliState) -> Result<Self> {
+    pub fn new(
+        tick_rate: f64,
+        frame_rate: f64,
+        state: NebulaCliState,
+        log_rx: Receiver<LogRecord>,
+    ) -> Result<Self> {
         let (action_tx, action_rx) = mpsc::unbounded_channel();
+        let state = Rc::new(Mutex::new(state));
         Ok(Self {
             tick_rate,
             frame_rate,
-            components: vec![Box::new(Home::new())],
+            components: vec![
+                Box::new(PackageBrowser::new()),
+                Box::new(TaskPanel::new()),
+                Box::new(LogPane::new(log_rx)),
+            ],
             should_quit: false,
             should_suspend: false,
+            show_logs: false,
             config: Config::new()?,
             mode: Mode::Home,
             last_tick_key_events: Vec::new(),
+            tasks: TaskQueue::new(state.clone(), action_tx.clone()),
             action_tx,
             action_rx,
             state,
-            post_command_handler: RatatuiPostCommandHandler {},
         })
     }
 
@@ -76,6 +110,7 @@ impl App {
         }
 
         let action_tx = self.action_tx.clone();
+        action_tx.send(Action::LoadPackages)?;
         loop {
             self.handle_events(&mut tui).await?;
             self.handle_actions(&mut tui).await?;
@@ -86,6 +121,7 @@ impl App {
                 // tui.mouse(true);
                 tui.enter()?;
             } else if self.should_quit {
+                self.tasks.stop();
                 tui.stop()?;
                 break;
             }
@@ -156,13 +192,22 @@ impl App {
                 Action::Resize(w, h) => self.handle_resize(tui, w, h)?,
                 Action::Render => self.render(tui)?,
 
-                Action::Command(ref cmd) => command_interpret(
-                    ["nebula_cli".to_string(), cmd.to_lowercase()].into_iter(),
-                    &mut self.state,
-                    &mut self.post_command_handler,
-                )
-                .await
-                .map_err(|_e| eyre::Report::msg("todo"))?,
+                Action::SwitchMode(mode) => self.mode = mode,
+                Action::LoadPackages => self.spawn_load_packages(),
+
+                Action::Command(ref cmd) => {
+                    self.tasks.enqueue(cmd.clone());
+                }
+                Action::Task(ref event) => {
+                    self.tasks.on_event(event);
+                    // the task may have changed the cache or the installed packages
+                    if event.is_final() {
+                        self.action_tx.send(Action::LoadPackages)?;
+                    }
+                }
+                Action::CancelTask => self.tasks.cancel_next(),
+                Action::CancelAllTasks => self.tasks.cancel_all(),
+                Action::ToggleLogs => self.show_logs = !self.show_logs,
 
                 _ => {}
             }
@@ -175,6 +220,19 @@ impl App {
         Ok(())
     }
 
+    /// Loads the packages in the background, a running task holds the state until it is done
+    fn spawn_load_packages(&self) {
+        let state = self.state.clone();
+        let action_tx = self.action_tx.clone();
+        tokio::task::spawn_local(async move {
+            let action = match load_packages(&mut *state.lock().await).await {
+                Ok(list) => Action::Packages(list),
+                Err(err) => Action::Error(err.to_string()),
+            };
+            let _ = action_tx.send(action);
+        });
+    }
+
     fn handle_resize(&mut self, tui: &mut Tui, w: u16, h: u16) -> Result<()> {
         tui.resize(Rect::new(0, 0, w, h))?;
         self.render(tui)?;
@@ -183,8 +241,19 @@ impl App {
 
     fn render(&mut self, tui: &mut Tui) -> Result<()> {
         tui.draw(|frame| {
-            for component in self.components.iter_mut() {
-                if let Err(err) = component.draw(frame, frame.area()) {
+            let logs = if self.show_logs {
+                Constraint::Percentage(LOG_PANE_PERCENTAGE)
+            } else {
+                Constraint::Length(0)
+            };
+            let areas: [Rect; 3] = Layout::vertical([
+                Constraint::Fill(1),
+                Constraint::Length(TASK_PANEL_HEIGHT),
+                logs,
+            ])
+            .areas(frame.area());
+            for (component, area) in self.components.iter_mut().zip(areas) {
+                if let Err(err) = component.draw(frame, area) {
                     let _ =
                         self.action_tx.send(Action::Error(format!("Failed to draw: {:?}", err)));
                 }
@@ -193,3 +262,12 @@ impl App {
         Ok(())
     }
 }
+
+/// The packages of the local cache, marked as installed if their descriptor is installed
+async fn load_packages(state: &mut NebulaCliState) -> Result<PackageList> {
+    let args = ListArgs { package_status: PackageStatus::All, package_type: PackageType::Both };
+    let packages = list_packages(args, state).await?.packages;
+    let installed: BTreeSet<String> =
+        list_all(&state.installed()?).await.iter().map(PackageList::key).collect();
+    Ok(PackageList { packages, installed })
+}
@@ -8,7 +8,9 @@ use tokio::sync::mpsc::UnboundedSender;
 
 use crate::tui::{Event, action::Action, config::Config};
 
-pub mod home;
+pub mod browser;
+pub mod log_pane;
+pub mod task_panel;
 
 /// `Component` is a trait that represents a visual and interactive element of the user interface.
 ///
@@ -0,0 +1,643 @@
+//! Module contains the package browser of the TUI
+//!
+//! Shows the packages of the local cache in a table and the descriptor of the selected package in a
+//! side pane. Typing after `/` filters the table, `s` and `r` change the sort order and the install
+//! and uninstall commands are bound to keys. Update has no key until the command is implemented.
+
+use std::collections::BTreeSet;
+
+use color_eyre::Result;
+use crossterm::event::{KeyCode, KeyEvent};
+use nebula_common::{
+    datapackage::DataPackage,
+    model::{SearchKind, SortBy, SortLevel, SortSettings},
+};
+use ratatui::{
+    prelude::*,
+    style::palette::tailwind::{BLUE, GREEN, SLATE},
+    widgets::*,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::Component;
+use crate::{
+    cli::format_bytes,
+    tui::{action::Action, app::Mode, config::Config},
+};
+
+const HEADER_STYLE: Style = Style::new().fg(SLATE.c100).bg(BLUE.c800);
+const SELECTED_STYLE: Style = Style::new().bg(SLATE.c800).add_modifier(Modifier::BOLD);
+const NORMAL_ROW_BG: Color = SLATE.c950;
+const TEXT_FG_COLOR: Color = SLATE.c200;
+const INSTALLED_FG_COLOR: Color = GREEN.c500;
+
+/// rows that PageUp and PageDown move
+const PAGE: usize = 10;
+
+/// the properties that `s` cycles through
+const SORT_FIELDS: [SortBy; 3] = [SortBy::Name, SortBy::CreationDate, SortBy::Author];
+
+/// Packages of the local cache with the installed ones
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PackageList {
+    pub packages: Vec<DataPackage>,
+
+    /// installed packages as `name@version`
+    pub installed: BTreeSet<String>,
+}
+
+impl PackageList {
+    pub fn key(package: &DataPackage) -> String {
+        format!(
+            "{}@{}",
+            package.name.as_deref().unwrap_or_default(),
+            package.version.as_deref().unwrap_or_default()
+        )
+    }
+
+    fn is_installed(&self, package: &DataPackage) -> bool {
+        self.installed.contains(&Self::key(package))
+    }
+}
+
+pub struct PackageBrowser {
+    command_tx: Option<UnboundedSender<Action>>,
+    config: Config,
+
+    /// sorted by [Self::sort]
+    list: PackageList,
+
+    /// indices of the packages that match the filter
+    visible: Vec<usize>,
+    state: TableState,
+
+    filter: String,
+
+    /// true while the filter is typed
+    editing: bool,
+
+    /// false while another pane has the keyboard focus
+    active: bool,
+    sort: SortLevel,
+
+    /// the last message for the user, e.g. an error
+    status: Option<String>,
+
+    /// key of the package whose uninstall waits for a second press of `d`
+    confirm_uninstall: Option<String>,
+}
+
+impl Default for PackageBrowser {
+    fn default() -> Self {
+        Self {
+            command_tx: None,
+            config: Config::default(),
+            list: PackageList::default(),
+            visible: vec![],
+            state: TableState::default(),
+            filter: String::new(),
+            editing: false,
+            active: true,
+            sort: SortLevel { by: SortBy::Name, descending: false },
+            status: None,
+            confirm_uninstall: None,
+        }
+    }
+}
+
+impl PackageBrowser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn set_packages(&mut self, list: PackageList) {
+        let selected = self.selected().map(PackageList::key);
+        self.list = list;
+        self.apply(selected);
+    }
+
+    /// Sorts and filters the packages, keeps the selection if the package is still visible
+    fn apply(&mut self, selected: Option<String>) {
+        SortSettings { levels: vec![self.sort] }.sort(&mut self.list.packages, |_| 0);
+        self.visible = self
+            .list
+            .packages
+            .iter()
+            .enumerate()
+            .filter(|(_, package)| SearchKind::Relaxed.matches(&self.filter, package))
+            .map(|(idx, _)| idx)
+            .collect();
+
+        let position = selected.and_then(|key| {
+            self.visible.iter().position(|idx| PackageList::key(&self.list.packages[*idx]) == key)
+        });
+        match position {
+            Some(position) => self.state.select(Some(position)),
+            None if self.visible.is_empty() => self.state.select(None),
+            None => self.state.select(Some(0)),
+        }
+    }
+
+    fn selected(&self) -> Option<&DataPackage> {
+        self.state
+            .selected()
+            .and_then(|position| self.visible.get(position))
+            .map(|idx| &self.list.packages[*idx])
+    }
+
+    fn select(&mut self, position: usize) {
+        if !self.visible.is_empty() {
+            self.state.select(Some(position.min(self.visible.len() - 1)));
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let position = self.state.selected().unwrap_or_default();
+        self.select(position.saturating_add_signed(delta));
+    }
+
+    fn set_filter(&mut self, filter: String) {
+        self.filter = filter;
+        self.apply(self.selected().map(PackageList::key));
+    }
+
+    fn set_editing(&mut self, editing: bool) -> Option<Action> {
+        self.editing = editing;
+        Some(Action::SwitchMode(if editing { Mode::Filter } else { Mode::Home }))
+    }
+
+    fn cycle_sort(&mut self) {
+        let next = SORT_FIELDS.iter().position(|by| *by == self.sort.by).map_or(0, |idx| idx + 1);
+        self.sort.by = SORT_FIELDS[next % SORT_FIELDS.len()];
+        self.apply(self.selected().map(PackageList::key));
+    }
+
+    fn reverse_sort(&mut self) {
+        self.sort.descending = !self.sort.descending;
+        self.apply(self.selected().map(PackageList::key));
+    }
+
+    /// Sends a command line for the selected package to the app
+    fn run_for_selected(&mut self, command: impl Fn(&str, &str) -> String) {
+        let Some(package) = self.selected() else {
+            return;
+        };
+        let command = command(
+            package.name.as_deref().unwrap_or_default(),
+            package.version.as_deref().unwrap_or_default(),
+        );
+        let Some(sender) = &self.command_tx else {
+            return;
+        };
+        self.status = match sender.send(Action::Command(command.clone())) {
+            Ok(()) => Some(format!("Queued '{}'", command)),
+            Err(_) => Some(format!("Queueing '{}' failed, the app is shutting down", command)),
+        };
+    }
+
+    /// Asks for a confirmation on the first press, uninstalls the selected package on the second
+    fn uninstall_selected(&mut self, pending: Option<String>) {
+        let Some(selected) = self.selected().map(PackageList::key) else {
+            return;
+        };
+        if pending.as_ref() == Some(&selected) {
+            self.run_for_selected(|name, version| format!("uninstall {}@={}", name, version));
+        } else {
+            self.status =
+                Some(format!("Press d again to uninstall {}, any other key cancels.", selected));
+            self.confirm_uninstall = Some(selected);
+        }
+    }
+
+    fn handle_filter_key(&mut self, key: KeyEvent) -> Option<Action> {
+        match key.code {
+            KeyCode::Char(c) => {
+                let mut filter = self.filter.clone();
+                filter.push(c);
+                self.set_filter(filter);
+            }
+            KeyCode::Backspace => {
+                let mut filter = self.filter.clone();
+                filter.pop();
+                self.set_filter(filter);
+            }
+            KeyCode::Down => self.move_selection(1),
+            KeyCode::Up => self.move_selection(-1),
+            KeyCode::Enter => return self.set_editing(false),
+            KeyCode::Esc => {
+                self.set_filter(String::new());
+                return self.set_editing(false);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn render_filter(&self, area: Rect, buf: &mut Buffer) {
+        let direction = if self.sort.descending { "↓" } else { "↑" };
+        let sort = match self.sort.by {
+            SortBy::CreationDate => "created",
+            SortBy::Downloads => "downloads",
+            SortBy::Name => "name",
+            SortBy::Author => "author",
+        };
+        let cursor = if self.editing { "█" } else { "" };
+        let line = Line::from(vec![
+            Span::raw(" Filter: ").bold(),
+            Span::raw(format!("{}{}", self.filter, cursor)),
+            Span::raw(format!(
+                "   {} of {} packages, sorted by {} {}",
+                self.visible.len(),
+                self.list.packages.len(),
+                sort,
+                direction
+            ))
+            .fg(SLATE.c400),
+        ]);
+        Paragraph::new(line).style(HEADER_STYLE).render(area, buf);
+    }
+
+    fn render_footer(&self, area: Rect, buf: &mut Buffer) {
+        let text = match (&self.status, self.editing) {
+            (_, true) => "Type to filter, Enter to keep the filter, Esc to clear it.".into(),
+            (Some(status), _) => status.clone(),
+            (None, _) => "↓↑ move, / filter, s sort, r reverse, i install, d uninstall, \
+                          c cancel task, l logs, F5 reload, q quit"
+                .into(),
+        };
+        Paragraph::new(text).centered().render(area, buf);
+    }
+
+    fn render_table(&mut self, area: Rect, buf: &mut Buffer) {
+        let header =
+            Row::new(["", "Name", "Version", "Type", "Size", "Created"]).style(HEADER_STYLE);
+        let rows: Vec<Row> = self
+            .visible
+            .iter()
+            .map(|idx| {
+                let package = &self.list.packages[*idx];
+                let installed = self.list.is_installed(package);
+                Row::new([
+                    if installed { "✓".to_string() } else { String::new() },
+                    package.name.clone().unwrap_or_default(),
+                    package.version.clone().unwrap_or_default(),
+                    package.kind().to_string(),
+                    format_bytes(package.download_size()),
+                    package
+                        .created
+                        .as_deref()
+                        .map(|el| el.get(..10).unwrap_or(el))
+                        .unwrap_or_default()
+                        .into(),
+                ])
+                .fg(if installed { INSTALLED_FG_COLOR } else { TEXT_FG_COLOR })
+            })
+            .collect();
+
+        let widths = [
+            Constraint::Length(1),
+            Constraint::Fill(3),
+            Constraint::Length(9),
+            Constraint::Length(7),
+            Constraint::Length(10),
+            Constraint::Length(10),
+        ];
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(Block::new().borders(Borders::RIGHT).bg(NORMAL_ROW_BG))
+            .row_highlight_style(SELECTED_STYLE)
+            .highlight_symbol(">")
+            .highlight_spacing(HighlightSpacing::Always);
+
+        // We need to disambiguate this trait method as both `Widget` and `StatefulWidget` share the
+        // same method name `render`.
+        StatefulWidget::render(table, area, buf, &mut self.state);
+    }
+
+    fn render_details(&self, area: Rect, buf: &mut Buffer) {
+        let block = Block::new()
+            .title(Line::raw("Details").centered())
+            .borders(Borders::TOP)
+            .border_set(symbols::border::EMPTY)
+            .border_style(HEADER_STYLE)
+            .bg(NORMAL_ROW_BG)
+            .padding(Padding::horizontal(1));
+
+        let lines = match self.selected() {
+            Some(package) => details(package, self.list.is_installed(package)),
+            None => vec![Line::raw("Nothing selected, run 'nebula sync' to fill the local cache")],
+        };
+        Paragraph::new(lines)
+            .block(block)
+            .fg(TEXT_FG_COLOR)
+            .wrap(Wrap { trim: false })
+            .render(area, buf);
+    }
+}
+
+/// The descriptor as lines of text: resources, licenses and delta metadata
+fn details(package: &DataPackage, installed: bool) -> Vec<Line<'static>> {
+    let mut lines = vec![Line::raw(PackageList::key(package)).bold()];
+    let mut field = |name: &str, value: Option<String>| {
+        if let Some(value) = value.filter(|el| !el.is_empty()) {
+            lines.push(Line::from(vec![Span::raw(format!("{}: ", name)).bold(), Span::raw(value)]));
+        }
+    };
+    field("Title", package.title.clone());
+    field("Description", package.description.clone());
+    field("Type", Some(package.kind().to_string()));
+    field("Installed", Some(if installed { "yes" } else { "no" }.into()));
+    field("Created", package.created.clone());
+    field("Homepage", package.homepage.clone());
+    field("Keywords", package.keywords.as_ref().map(|kws| kws.join(", ")));
+    field(
+        "Authors",
+        package.contributor.as_ref().map(|contributors| {
+            contributors
+                .iter()
+                .filter_map(|c| c.title.clone().or_else(|| c.organziation.clone()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        }),
+    );
+    field(
+        "Size",
+        Some(format!(
+            "{} download, {} installed",
+            format_bytes(package.download_size()),
+            format_bytes(package.installed_size())
+        )),
+    );
+
+    if let Some(delta) = package.delta.as_ref() {
+        field("Category", Some(delta.category.clone()));
+        field("Classes", delta.classes.map(|el| el.to_string()));
+        field("Input shape", Some(delta.input_shape.clone()));
+        let counts = [
+            ("train", delta.training_count),
+            ("validation", delta.validation_count),
+            ("test", delta.test_count),
+        ]
+        .into_iter()
+        .filter_map(|(name, count)| count.map(|count| format!("{} {}", count, name)))
+        .collect::<Vec<_>>();
+        field("Samples", Some(counts.join(", ")));
+        if let Some(model) = delta.model.as_ref() {
+            field("Framework", model.framework.clone());
+            field("Format", model.format.clone());
+        }
+        let dependencies = package
+            .dependencies()
+            .map(|(name, req)| format!("{} {}", name, req))
+            .collect::<Vec<_>>();
+        field("Dependencies", Some(dependencies.join(", ")));
+    }
+
+    let mut section = |title: &str, entries: Vec<String>| {
+        if !entries.is_empty() {
+            lines.push(Line::default());
+            lines.push(Line::raw(title.to_string()).bold());
+            lines.extend(entries.into_iter().map(|entry| Line::raw(format!("  {}", entry))));
+        }
+    };
+    section(
+        "Licenses",
+        package
+            .licenses
+            .iter()
+            .map(|license| license.title.clone().unwrap_or(license.name.clone()))
+            .collect(),
+    );
+    section(
+        "Resources",
+        package
+            .resources
+            .iter()
+            .map(|resource| {
+                format!(
+                    "{}  {}  {}",
+                    resource.name,
+                    resource.bytes.map(format_bytes).unwrap_or("-".into()),
+                    resource.format.as_deref().unwrap_or("-")
+                )
+            })
+            .collect(),
+    );
+    lines
+}
+
+impl Component for PackageBrowser {
+    fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> Result<()> {
+        self.command_tx = Some(tx);
+        Ok(())
+    }
+
+    fn register_config_handler(&mut self, config: Config) -> Result<()> {
+        self.config = config;
+        Ok(())
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) -> Result<Option<Action>> {
+        if !self.active {
+            return Ok(None);
+        }
+        if self.editing {
+            return Ok(self.handle_filter_key(key));
+        }
+        let pending = self.confirm_uninstall.take();
+        if pending.is_some() && !matches!(key.code, KeyCode::Char('d') | KeyCode::Delete) {
+            self.status = None;
+        }
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => self.move_selection(1),
+            KeyCode::Char('k') | KeyCode::Up => self.move_selection(-1),
+            KeyCode::PageDown => self.move_selection(PAGE as isize),
+            KeyCode::PageUp => self.move_selection(-(PAGE as isize)),
+            KeyCode::Char('g') | KeyCode::Home => self.select(0),
+            KeyCode::Char('G') | KeyCode::End => self.select(usize::MAX),
+            KeyCode::Char('/') => return Ok(self.set_editing(true)),
+            KeyCode::Esc => self.set_filter(String::new()),
+            KeyCode::Char('s') => self.cycle_sort(),
+            KeyCode::Char('r') => self.reverse_sort(),
+            KeyCode::Char('i') => {
+                self.run_for_selected(|name, version| format!("install {}@={}", name, version))
+            }
+            KeyCode::Char('d') | KeyCode::Delete => self.uninstall_selected(pending),
+            KeyCode::F(5) => return Ok(Some(Action::LoadPackages)),
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        match action {
+            Action::Packages(list) => {
+                self.set_packages(list);
+                self.status = None;
+                self.confirm_uninstall = None;
+            }
+            Action::Error(message) => self.status = Some(message),
+            Action::SwitchMode(Mode::Home) => self.active = true,
+            Action::SwitchMode(Mode::Logs) => self.active = false,
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rect) -> Result<()> {
+        let [filter_area, main_area, footer_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Fill(1), Constraint::Length(1)])
+                .areas(area);
+        let [table_area, details_area] =
+            Layout::horizontal([Constraint::Fill(3), Constraint::Fill(2)]).areas(main_area);
+
+        let buf = frame.buffer_mut();
+        self.render_filter(filter_area, buf);
+        self.render_footer(footer_area, buf);
+        self.render_table(table_area, buf);
+        self.render_details(details_area, buf);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crossterm::event::KeyModifiers;
+    use nebula_common::datapackage::{DataPackageNotValidated, ValidateData as _};
+
+    use super::*;
+
+    fn package(name: &str, version: &str, created: &str) -> DataPackage {
+        DataPackageNotValidated {
+            name: Some(name.into()),
+            version: Some(version.into()),
+            created: Some(created.into()),
+            resources: vec![Default::default()],
+            ..Default::default()
+        }
+        .validate()
+        .unwrap()
+    }
+
+    fn names(browser: &PackageBrowser) -> Vec<String> {
+        browser
+            .visible
+            .iter()
+            .map(|idx| browser.list.packages[*idx].name.clone().unwrap())
+            .collect()
+    }
+
+    fn press(browser: &mut PackageBrowser, code: KeyCode) -> Option<Action> {
+        browser.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE)).unwrap()
+    }
+
+    #[test]
+    fn test_filter_and_sort() {
+        let mut browser = PackageBrowser::new();
+        browser.set_packages(PackageList {
+            packages: vec![
+                package("mobilenet_v3_tf2", "1.0.0", "2024-01-02"),
+                package("cifar-10-binary", "1.0.0", "2009-04-08"),
+                package("iris-classical", "1.0.0", "1988-07-01"),
+            ],
+            installed: ["iris-classical@1.0.0".to_string()].into(),
+        });
+        assert_eq!(names(&browser), vec!["cifar-10-binary", "iris-classical", "mobilenet_v3_tf2"]);
+        assert!(browser.list.is_installed(&browser.list.packages[1]));
+
+        // the selection follows the package
+        press(&mut browser, KeyCode::Down);
+        assert_eq!(browser.selected().unwrap().name.as_deref(), Some("iris-classical"));
+        press(&mut browser, KeyCode::Char('s'));
+        assert_eq!(names(&browser), vec!["iris-classical", "cifar-10-binary", "mobilenet_v3_tf2"]);
+        press(&mut browser, KeyCode::Char('r'));
+        assert_eq!(names(&browser), vec!["mobilenet_v3_tf2", "cifar-10-binary", "iris-classical"]);
+        assert_eq!(browser.state.selected(), Some(2));
+
+        assert_eq!(press(&mut browser, KeyCode::Char('/')), Some(Action::SwitchMode(Mode::Filter)));
+        for c in "CIF".chars() {
+            press(&mut browser, KeyCode::Char(c));
+        }
+        assert_eq!(names(&browser), vec!["cifar-10-binary"]);
+        assert_eq!(browser.state.selected(), Some(0));
+        assert_eq!(press(&mut browser, KeyCode::Esc), Some(Action::SwitchMode(Mode::Home)));
+        assert_eq!(names(&browser).len(), 3);
+
+        // q is only a filter character while typing
+        press(&mut browser, KeyCode::Char('/'));
+        press(&mut browser, KeyCode::Char('q'));
+        assert!(names(&browser).is_empty());
+        assert_eq!(browser.selected(), None);
+    }
+
+    #[test]
+    fn test_draw() {
+        let mut browser = PackageBrowser::new();
+        let mut iris = package("iris-classical", "1.0.0", "1988-07-01").into_inner();
+        iris.title = Some("Iris flower dataset".into());
+        iris.resources[0].name = "iris-table".into();
+        iris.resources[0].bytes = Some(3858);
+        browser.set_packages(PackageList {
+            packages: vec![iris.validate().unwrap(), package("cifar-10-binary", "1.0.0", "2009")],
+            installed: ["iris-classical@1.0.0".to_string()].into(),
+        });
+
+        press(&mut browser, KeyCode::Down);
+
+        let mut terminal = Terminal::new(backend::TestBackend::new(100, 12)).unwrap();
+        terminal.draw(|frame| browser.draw(frame, frame.area()).unwrap()).unwrap();
+        let screen: Vec<String> = terminal
+            .backend()
+            .buffer()
+            .content
+            .chunks(100)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect();
+        assert!(screen[0].contains("2 of 2 packages, sorted by name ↑"));
+        assert!(
+            screen.iter().any(|row| row.contains("✓ iris-classical") && row.contains("1988-07-01"))
+        );
+        assert!(screen.iter().any(|row| row.contains("Title: Iris flower dataset")));
+        assert!(screen.iter().any(|row| row.contains("iris-table")));
+    }
+
+    #[test]
+    fn test_key_actions() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut browser = PackageBrowser::new();
+        browser.register_action_handler(tx).unwrap();
+        browser.set_packages(PackageList {
+            packages: vec![package("iris-classical", "1.0.0", "1988-07-01")],
+            installed: BTreeSet::new(),
+        });
+
+        press(&mut browser, KeyCode::Char('i'));
+        assert_eq!(rx.try_recv().unwrap(), Action::Command("install iris-classical@=1.0.0".into()));
+        // the uninstall waits for a confirmation, any other key cancels it
+        press(&mut browser, KeyCode::Char('d'));
+        assert!(rx.try_recv().is_err());
+        assert!(browser.status.as_deref().is_some_and(|status| status.contains("d again")));
+        press(&mut browser, KeyCode::Char('s'));
+        assert_eq!(browser.status, None);
+        press(&mut browser, KeyCode::Char('d'));
+        assert!(rx.try_recv().is_err());
+        press(&mut browser, KeyCode::Delete);
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            Action::Command("uninstall iris-classical@=1.0.0".into())
+        );
+        assert_eq!(press(&mut browser, KeyCode::F(5)), Some(Action::LoadPackages));
+
+        // update is not implemented and has no key
+        press(&mut browser, KeyCode::Char('u'));
+        assert!(rx.try_recv().is_err());
+
+        // a closed channel is reported instead of panicking
+        drop(rx);
+        press(&mut browser, KeyCode::Char('i'));
+        assert!(browser.status.as_deref().is_some_and(|status| status.contains("failed")));
+
+        let lines = details(browser.selected().unwrap(), false);
+        assert!(lines.iter().any(|line| line.to_string() == "Installed: no"));
+    }
+}
@@ -0,0 +1,287 @@
+//! Module contains the log pane of the TUI
+//!
+//! Shows the events of the tracing subscriber, see [crate::tui::logging]. The pane is toggled by the
+//! `ToggleLogs` action and has the keyboard focus while it is open. `v` changes the minimal level, `/`
+//! searches the messages and targets, the pane follows the newest events until it is scrolled.
+
+use std::collections::VecDeque;
+
+use color_eyre::Result;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    prelude::*,
+    style::palette::tailwind::{BLUE, RED, SLATE, YELLOW},
+    widgets::*,
+};
+use tokio::sync::mpsc::Receiver;
+use tracing::Level;
+
+use super::Component;
+use crate::tui::{action::Action, app::Mode, logging::LogRecord};
+
+const HEADER_STYLE: Style = Style::new().fg(SLATE.c100).bg(BLUE.c800);
+const NORMAL_ROW_BG: Color = SLATE.c950;
+
+/// events that are kept in the pane
+const MAX_RECORDS: usize = 2000;
+
+/// rows that PageUp and PageDown scroll
+const PAGE: usize = 10;
+
+/// the minimal levels that `v` cycles through
+const LEVELS: [Level; 5] = [Level::TRACE, Level::DEBUG, Level::INFO, Level::WARN, Level::ERROR];
+
+pub struct LogPane {
+    log_rx: Receiver<LogRecord>,
+    records: VecDeque<LogRecord>,
+    visible: bool,
+
+    /// shows the events of this level and the more severe ones
+    level: Level,
+    search: String,
+
+    /// true while the search is typed
+    editing: bool,
+
+    /// rows between the last shown event and the newest one, 0 follows the newest events
+    scroll: usize,
+}
+
+impl LogPane {
+    pub fn new(log_rx: Receiver<LogRecord>) -> Self {
+        Self {
+            log_rx,
+            records: VecDeque::new(),
+            visible: false,
+            level: Level::TRACE,
+            search: String::new(),
+            editing: false,
+            scroll: 0,
+        }
+    }
+
+    /// Takes the events that the subscriber sent since the last tick
+    fn receive(&mut self) {
+        while let Ok(record) = self.log_rx.try_recv() {
+            // keeps the shown rows in place while scrolled
+            if self.scroll > 0 && self.matches(&record) {
+                self.scroll += 1;
+            }
+            if self.records.len() == MAX_RECORDS {
+                self.records.pop_front();
+            }
+            self.records.push_back(record);
+        }
+    }
+
+    fn matches(&self, record: &LogRecord) -> bool {
+        let search = self.search.to_lowercase();
+        record.level <= self.level
+            && (record.message.to_lowercase().contains(&search)
+                || record.target.to_lowercase().contains(&search))
+    }
+
+    fn cycle_level(&mut self) {
+        let next = LEVELS.iter().position(|level| *level == self.level).map_or(0, |idx| idx + 1);
+        self.level = LEVELS[next % LEVELS.len()];
+        self.scroll = 0;
+    }
+
+    fn set_editing(&mut self, editing: bool) -> Option<Action> {
+        self.editing = editing;
+        Some(Action::SwitchMode(if editing { Mode::Filter } else { Mode::Logs }))
+    }
+
+    fn handle_search_key(&mut self, key: KeyEvent) -> Option<Action> {
+        match key.code {
+            KeyCode::Char(c) => self.search.push(c),
+            KeyCode::Backspace => {
+                self.search.pop();
+            }
+            KeyCode::Enter => return self.set_editing(false),
+            KeyCode::Esc => {
+                self.search.clear();
+                return self.set_editing(false);
+            }
+            _ => {}
+        }
+        self.scroll = 0;
+        None
+    }
+
+    fn title(&self) -> String {
+        if self.editing {
+            return format!(" Search: {}█ ", self.search);
+        }
+        let search =
+            if self.search.is_empty() { String::new() } else { format!(", '{}'", self.search) };
+        format!(" Logs ({}{}) — v level, / search, l close ", self.level, search)
+    }
+}
+
+impl Component for LogPane {
+    fn handle_key_event(&mut self, key: KeyEvent) -> Result<Option<Action>> {
+        if !self.visible {
+            return Ok(None);
+        }
+        if self.editing {
+            return Ok(self.handle_search_key(key));
+        }
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::Char('k') | KeyCode::Up => self.scroll += 1,
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(PAGE),
+            KeyCode::PageUp => self.scroll += PAGE,
+            KeyCode::Char('g') | KeyCode::Home => self.scroll = self.records.len(),
+            KeyCode::Char('G') | KeyCode::End => self.scroll = 0,
+            KeyCode::Char('v') => self.cycle_level(),
+            KeyCode::Char('/') => return Ok(self.set_editing(true)),
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        match action {
+            Action::Tick | Action::Render => self.receive(),
+            Action::ToggleLogs => {
+                self.visible = !self.visible;
+                let mode = if self.visible { Mode::Logs } else { Mode::Home };
+                return Ok(Some(Action::SwitchMode(mode)));
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rect) -> Result<()> {
+        if !self.visible || area.is_empty() {
+            return Ok(());
+        }
+        let block = Block::new()
+            .title(Line::raw(self.title()).centered())
+            .borders(Borders::TOP)
+            .border_set(symbols::border::EMPTY)
+            .border_style(HEADER_STYLE)
+            .bg(NORMAL_ROW_BG)
+            .padding(Padding::horizontal(1));
+        let height = block.inner(area).height as usize;
+
+        let matching: Vec<&LogRecord> =
+            self.records.iter().filter(|record| self.matches(record)).collect();
+        self.scroll = self.scroll.min(matching.len().saturating_sub(height));
+        let end = matching.len() - self.scroll;
+        let lines: Vec<Line> = matching[end.saturating_sub(height)..end]
+            .iter()
+            .map(|record| {
+                let color = match record.level {
+                    Level::ERROR => RED.c500,
+                    Level::WARN => YELLOW.c400,
+                    Level::INFO => BLUE.c400,
+                    _ => SLATE.c400,
+                };
+                Line::from(vec![
+                    Span::raw(format!("{:<5} ", record.level)).fg(color),
+                    Span::raw(format!("{}: ", record.target)).fg(SLATE.c500),
+                    Span::raw(record.message.as_str()).fg(SLATE.c200),
+                ])
+            })
+            .collect();
+
+        Paragraph::new(lines).block(block).render(area, frame.buffer_mut());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crossterm::event::KeyModifiers;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    fn record(level: Level, message: &str) -> LogRecord {
+        LogRecord { level, target: "nebula_cli".into(), message: message.into() }
+    }
+
+    fn press(pane: &mut LogPane, code: KeyCode) -> Option<Action> {
+        pane.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE)).unwrap()
+    }
+
+    fn screen(pane: &mut LogPane) -> Vec<String> {
+        let mut terminal = Terminal::new(backend::TestBackend::new(80, 4)).unwrap();
+        terminal.draw(|frame| pane.draw(frame, frame.area()).unwrap()).unwrap();
+        terminal
+            .backend()
+            .buffer()
+            .content
+            .chunks(80)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_toggle_and_scroll() {
+        let (tx, rx) = mpsc::channel(16);
+        let mut pane = LogPane::new(rx);
+        for idx in 0..5 {
+            tx.try_send(record(Level::INFO, &format!("message {}", idx))).unwrap();
+        }
+        pane.update(Action::Tick).unwrap();
+        assert_eq!(pane.records.len(), 5);
+
+        // keys are ignored while the pane is hidden
+        press(&mut pane, KeyCode::Char('k'));
+        assert_eq!(pane.scroll, 0);
+        assert!(screen(&mut pane).iter().all(|row| row.trim().is_empty()));
+
+        let action = pane.update(Action::ToggleLogs).unwrap();
+        assert_eq!(action, Some(Action::SwitchMode(Mode::Logs)));
+        let rows = screen(&mut pane);
+        assert!(rows[0].contains("Logs (TRACE)"));
+        assert!(rows[1].contains("INFO  nebula_cli: message 2"));
+        assert!(rows[3].contains("message 4"));
+
+        press(&mut pane, KeyCode::Char('k'));
+        tx.try_send(record(Level::INFO, "message 5")).unwrap();
+        pane.update(Action::Tick).unwrap();
+        assert!(screen(&mut pane)[3].contains("message 3"));
+        press(&mut pane, KeyCode::Char('g'));
+        assert!(screen(&mut pane)[1].contains("message 0"));
+        press(&mut pane, KeyCode::Char('G'));
+        assert!(screen(&mut pane)[3].contains("message 5"));
+
+        let action = pane.update(Action::ToggleLogs).unwrap();
+        assert_eq!(action, Some(Action::SwitchMode(Mode::Home)));
+    }
+
+    #[test]
+    fn test_level_and_search() {
+        let (tx, rx) = mpsc::channel(16);
+        let mut pane = LogPane::new(rx);
+        tx.try_send(record(Level::DEBUG, "cache hit")).unwrap();
+        tx.try_send(record(Level::WARN, "registry offline")).unwrap();
+        tx.try_send(record(Level::ERROR, "download of iris failed")).unwrap();
+        pane.update(Action::Tick).unwrap();
+        pane.update(Action::ToggleLogs).unwrap();
+
+        press(&mut pane, KeyCode::Char('v'));
+        press(&mut pane, KeyCode::Char('v'));
+        press(&mut pane, KeyCode::Char('v'));
+        assert_eq!(pane.level, Level::WARN);
+        let rows = screen(&mut pane);
+        assert!(rows[0].contains("Logs (WARN)"));
+        assert!(rows[1].contains("registry offline"));
+        assert!(rows[2].contains("download of iris failed"));
+
+        assert_eq!(press(&mut pane, KeyCode::Char('/')), Some(Action::SwitchMode(Mode::Filter)));
+        for c in "IRIS".chars() {
+            press(&mut pane, KeyCode::Char(c));
+        }
+        assert_eq!(press(&mut pane, KeyCode::Enter), Some(Action::SwitchMode(Mode::Logs)));
+        let rows = screen(&mut pane);
+        assert!(rows[0].contains("'IRIS'"));
+        assert!(rows[1].contains("download of iris failed"));
+        assert!(rows[2].trim().is_empty());
+    }
+}
@@ -0,0 +1,245 @@
+//! Module contains the panel of the background tasks
+//!
+//! Lists the queued and running tasks first, followed by the finished ones, newest first. A running
+//! task shows the progress of its downloads, a failed one its error.
+
+use std::collections::BTreeMap;
+
+use color_eyre::Result;
+use ratatui::{
+    prelude::*,
+    style::palette::tailwind::{BLUE, GREEN, RED, SLATE, YELLOW},
+    widgets::*,
+};
+
+use super::Component;
+use crate::{
+    cli::format_bytes,
+    tui::{
+        action::Action,
+        tasks::{TaskEvent, TaskId},
+    },
+};
+
+const HEADER_STYLE: Style = Style::new().fg(SLATE.c100).bg(BLUE.c800);
+const NORMAL_ROW_BG: Color = SLATE.c950;
+
+/// finished tasks that are kept in the panel
+const MAX_FINISHED: usize = 50;
+
+#[derive(Debug, Clone, PartialEq)]
+enum TaskState {
+    Queued,
+    Running,
+    Finished,
+    Failed(String),
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Progress {
+    downloaded: u64,
+    total: Option<u64>,
+    rate: f64,
+}
+
+#[derive(Debug, Clone)]
+struct Task {
+    command: String,
+    state: TaskState,
+    progress: Option<Progress>,
+
+    /// the last message of the command
+    message: Option<String>,
+}
+
+impl Task {
+    fn is_done(&self) -> bool {
+        !matches!(self.state, TaskState::Queued | TaskState::Running)
+    }
+}
+
+#[derive(Default)]
+pub struct TaskPanel {
+    tasks: BTreeMap<TaskId, Task>,
+}
+
+impl TaskPanel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn on_event(&mut self, event: TaskEvent) {
+        if let TaskEvent::Queued { id, command } = event {
+            let task = Task { command, state: TaskState::Queued, progress: None, message: None };
+            self.tasks.insert(id, task);
+            return;
+        }
+        let Some(task) = self.tasks.get_mut(&event.id()) else {
+            return;
+        };
+        match event {
+            TaskEvent::Queued { .. } => {}
+            TaskEvent::Started(_) => task.state = TaskState::Running,
+            TaskEvent::Progress { downloaded, total, rate, .. } => {
+                task.progress = Some(Progress { downloaded, total, rate })
+            }
+            TaskEvent::Message { message, .. } => task.message = Some(message),
+            TaskEvent::Finished(_) => task.state = TaskState::Finished,
+            TaskEvent::Failed { error, .. } => task.state = TaskState::Failed(error),
+            TaskEvent::Cancelled(_) => task.state = TaskState::Cancelled,
+        }
+        self.forget_finished();
+    }
+
+    /// Drops the oldest finished tasks
+    fn forget_finished(&mut self) {
+        let done: Vec<TaskId> =
+            self.tasks.iter().filter(|(_, task)| task.is_done()).map(|(id, _)| *id).collect();
+        for id in done.iter().take(done.len().saturating_sub(MAX_FINISHED)) {
+            self.tasks.remove(id);
+        }
+    }
+
+    /// Tasks in the order of the panel
+    fn rows(&self) -> impl Iterator<Item = (&TaskId, &Task)> {
+        let pending = self.tasks.iter().filter(|(_, task)| !task.is_done());
+        let done = self.tasks.iter().rev().filter(|(_, task)| task.is_done());
+        pending.chain(done)
+    }
+
+    fn render_task(&self, id: TaskId, task: &Task, area: Rect, buf: &mut Buffer) {
+        let [state_area, command_area, info_area] =
+            Layout::horizontal([Constraint::Length(10), Constraint::Fill(2), Constraint::Fill(3)])
+                .spacing(1)
+                .areas(area);
+
+        let (state, color) = match task.state {
+            TaskState::Queued => ("queued", SLATE.c400),
+            TaskState::Running => ("running", YELLOW.c400),
+            TaskState::Finished => ("done", GREEN.c500),
+            TaskState::Failed(_) => ("failed", RED.c500),
+            TaskState::Cancelled => ("cancelled", SLATE.c400),
+        };
+        Span::raw(state).fg(color).render(state_area, buf);
+        Span::raw(format!("#{} {}", id, task.command)).fg(SLATE.c200).render(command_area, buf);
+
+        match (&task.state, &task.progress) {
+            (TaskState::Failed(error), _) => {
+                Span::raw(error.as_str()).fg(RED.c400).render(info_area, buf)
+            }
+            (TaskState::Running, Some(progress)) => {
+                let ratio = progress
+                    .total
+                    .filter(|total| *total > 0)
+                    .map_or(0.0, |total| progress.downloaded as f64 / total as f64)
+                    .min(1.0);
+                let amount = match progress.total {
+                    Some(_) => format!("{:>3.0}%", ratio * 100.0),
+                    None => format_bytes(progress.downloaded),
+                };
+                LineGauge::default()
+                    .filled_style(Style::new().fg(BLUE.c400))
+                    .unfilled_style(Style::new().fg(SLATE.c700))
+                    .label(format!("{} {}/s", amount, format_bytes(progress.rate as u64)))
+                    .ratio(ratio)
+                    .render(info_area, buf);
+            }
+            (_, _) => {
+                let message = task.message.as_deref().unwrap_or_default();
+                Span::raw(message).fg(SLATE.c400).render(info_area, buf)
+            }
+        }
+    }
+}
+
+impl Component for TaskPanel {
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        if let Action::Task(event) = action {
+            self.on_event(event);
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rect) -> Result<()> {
+        let pending = self.tasks.values().filter(|task| !task.is_done()).count();
+        let title = format!(" Tasks ({} pending) — c cancel, C cancel all ", pending);
+        let block = Block::new()
+            .title(Line::raw(title).centered())
+            .borders(Borders::TOP)
+            .border_set(symbols::border::EMPTY)
+            .border_style(HEADER_STYLE)
+            .bg(NORMAL_ROW_BG)
+            .padding(Padding::horizontal(1));
+        let inner = block.inner(area);
+        let buf = frame.buffer_mut();
+        block.render(area, buf);
+
+        let rows =
+            Layout::vertical(vec![Constraint::Length(1); inner.height as usize]).split(inner);
+        for ((id, task), row) in self.rows().zip(rows.iter()) {
+            self.render_task(*id, task, *row, buf);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn screen(panel: &mut TaskPanel) -> Vec<String> {
+        let mut terminal = Terminal::new(backend::TestBackend::new(80, 5)).unwrap();
+        terminal.draw(|frame| panel.draw(frame, frame.area()).unwrap()).unwrap();
+        terminal
+            .backend()
+            .buffer()
+            .content
+            .chunks(80)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_task_rows() {
+        let mut panel = TaskPanel::new();
+        for (id, command) in
+            ["sync", "install iris-classical@=1.0.0", "uninstall cifar"].iter().enumerate()
+        {
+            panel.on_event(TaskEvent::Queued { id, command: command.to_string() });
+        }
+        panel.on_event(TaskEvent::Started(0));
+        panel.on_event(TaskEvent::Failed { id: 0, error: "needs network access".into() });
+        panel.on_event(TaskEvent::Started(1));
+        panel.on_event(TaskEvent::Progress {
+            id: 1,
+            downloaded: 512,
+            total: Some(2048),
+            rate: 1024.0,
+        });
+
+        let screen = screen(&mut panel);
+        assert!(screen[0].contains("Tasks (2 pending)"));
+        assert!(screen[1].contains("running") && screen[1].contains("#1 install iris-classical"));
+        assert!(screen[1].contains("25% 1.0 KiB/s"));
+        assert!(screen[2].contains("queued") && screen[2].contains("#2 uninstall cifar"));
+        assert!(screen[3].contains("failed") && screen[3].contains("needs network access"));
+
+        // events of unknown tasks are ignored
+        panel.on_event(TaskEvent::Finished(7));
+        assert_eq!(panel.tasks.len(), 3);
+    }
+
+    #[test]
+    fn test_forget_finished() {
+        let mut panel = TaskPanel::new();
+        for id in 0..MAX_FINISHED + 5 {
+            panel.on_event(TaskEvent::Queued { id, command: "sync".into() });
+            panel.on_event(TaskEvent::Cancelled(id));
+        }
+        panel.on_event(TaskEvent::Queued { id: 100, command: "sync".into() });
+        assert_eq!(panel.tasks.len(), MAX_FINISHED + 1);
+        assert_eq!(panel.tasks.keys().next(), Some(&5));
+        assert_eq!(panel.rows().next().map(|(id, _)| *id), Some(100));
+    }
+}
@@ -0,0 +1,111 @@
+//! Forwards the tracing events into the TUI
+//!
+//! In TUI mode nothing is logged to the console, [TuiLogLayer] sends the events that go into the log file
+//! through a bounded channel to the log pane instead. Events are dropped while the channel is full, such
+//! that logging never blocks.
+
+use std::fmt::{Debug, Write as _};
+
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tracing::{
+    Event, Level, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{Layer, layer::Context};
+
+/// events that are buffered until the log pane takes them
+const LOG_CHANNEL_CAPACITY: usize = 1024;
+
+/// A tracing event as shown in the log pane
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub level: Level,
+    pub target: String,
+
+    /// the message followed by the other fields as `name=value`
+    pub message: String,
+}
+
+pub struct TuiLogLayer {
+    tx: Sender<LogRecord>,
+}
+
+/// Creates the layer for the subscriber and the receiver of the log pane
+pub fn log_channel() -> (TuiLogLayer, Receiver<LogRecord>) {
+    let (tx, rx) = mpsc::channel(LOG_CHANNEL_CAPACITY);
+    (TuiLogLayer { tx }, rx)
+}
+
+impl<S: Subscriber> Layer<S> for TuiLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let record = LogRecord {
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_string(),
+            message: visitor.message + &visitor.fields,
+        };
+        let _ = self.tx.try_send(record);
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            let _ = write!(self.fields, " {}={}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    use super::*;
+
+    #[test]
+    fn test_forward_events() {
+        let (layer, mut rx) = log_channel();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(package = "iris", bytes = 3858, "installed {}", "iris-classical");
+            tracing::warn!(target: "nebula_common", "offline");
+            for _ in 0..LOG_CHANNEL_CAPACITY {
+                tracing::debug!("dropped when the channel is full");
+            }
+        });
+
+        let record = rx.try_recv().unwrap();
+        assert_eq!(record.level, Level::INFO);
+        assert_eq!(record.message, "installed iris-classical package=iris bytes=3858");
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            LogRecord {
+                level: Level::WARN,
+                target: "nebula_common".into(),
+                message: "offline".into()
+            }
+        );
+        let mut count = 2;
+        while rx.try_recv().is_ok() {
+            count += 1;
+        }
+        assert_eq!(count, LOG_CHANNEL_CAPACITY);
+    }
+}
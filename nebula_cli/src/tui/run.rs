@@ -1,17 +1,31 @@
 use color_eyre::eyre::Report;
 use nebula_common::NebulaCliState;
+use tokio::sync::mpsc::Receiver;
 
-use crate::cli::{Cli, run_legacy_cmd};
+use crate::{
+    cli::{Cli, ExitStatus, run_legacy_cmd},
+    tui::logging::LogRecord,
+};
 
-pub async fn run_tui(args: Cli, state: NebulaCliState) -> Result<(), Report> {
+pub async fn run_tui(
+    args: Cli,
+    state: NebulaCliState,
+    log_rx: Receiver<LogRecord>,
+) -> Result<ExitStatus, Report> {
     use super::App;
 
     if args.tui {
-        let mut app = App::new(args.tick_rate, args.frame_rate, state)?;
-        app.run().await?;
+        // the app spawns its background tasks locally, because the state is not `Send`
+        let tasks = tokio::task::LocalSet::new();
+        tasks
+            .run_until(async {
+                let mut app = App::new(args.tick_rate, args.frame_rate, state, log_rx)?;
+                app.run().await
+            })
+            .await?;
+        Ok(ExitStatus::Success)
     } else {
         // otherwise process one command
-        run_legacy_cmd(args, state).await?;
+        run_legacy_cmd(args, state).await
     }
-    Ok(())
 }
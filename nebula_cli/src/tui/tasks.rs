@@ -0,0 +1,394 @@
+//! Background tasks of the TUI
+//!
+//! Commands like install or sync are queued as tasks and executed one after another by a worker,
+//! such that the user interface keeps responding. Every task reports its state and the progress of its
+//! downloads as [Action::Task] through the action channel of the app. Each task has a child token of
+//! the queue's [CancellationToken], cancelling it drops the running command.
+//!
+//! The queue's token is not derived from [crate::tui::Tui::cancellation_token]. That token only
+//! lives as long as one event loop: [crate::tui::Tui::exit] cancels it on every suspend and
+//! [crate::tui::Tui::start] replaces it on resume, a child of it would cancel the queued tasks on
+//! `Ctrl-Z`. The app cancels the tasks with [TaskQueue::stop] when it quits instead.
+//!
+//! The state of nebula is not `Send`, therefore the worker is spawned on the [tokio::task::LocalSet]
+//! that runs the app.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
+
+use color_eyre::eyre::Report;
+use nebula_common::{
+    NebulaCliState,
+    api::{InstallResult, SyncResult, UninstallResult},
+    client::download::DownloadEvent,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{
+        Mutex,
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+    },
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    cli::{ExitStatus, PostCommandHandler, command_interpret, split_words},
+    tui::action::Action,
+};
+
+pub type TaskId = usize;
+
+/// Changes of a task, the last event of a task is `Finished`, `Failed` or `Cancelled`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TaskEvent {
+    Queued {
+        id: TaskId,
+        command: String,
+    },
+
+    Started(TaskId),
+
+    /// progress of all downloads of the task
+    Progress {
+        id: TaskId,
+        downloaded: u64,
+        total: Option<u64>,
+
+        /// bytes per second
+        rate: f64,
+    },
+
+    /// an intermediate result, e.g. a finished download
+    Message {
+        id: TaskId,
+        message: String,
+    },
+
+    Finished(TaskId),
+
+    Failed {
+        id: TaskId,
+        error: String,
+    },
+
+    Cancelled(TaskId),
+}
+
+impl TaskEvent {
+    pub fn id(&self) -> TaskId {
+        match self {
+            TaskEvent::Queued { id, .. }
+            | TaskEvent::Progress { id, .. }
+            | TaskEvent::Message { id, .. }
+            | TaskEvent::Failed { id, .. } => *id,
+            TaskEvent::Started(id) | TaskEvent::Finished(id) | TaskEvent::Cancelled(id) => *id,
+        }
+    }
+
+    /// true if the task is done
+    pub fn is_final(&self) -> bool {
+        matches!(self, TaskEvent::Finished(_) | TaskEvent::Failed { .. } | TaskEvent::Cancelled(_))
+    }
+}
+
+struct QueuedTask {
+    id: TaskId,
+    command: String,
+    token: CancellationToken,
+}
+
+/// Queue of commands that are executed in the background, one at a time
+pub struct TaskQueue {
+    next_id: TaskId,
+    queue_tx: UnboundedSender<QueuedTask>,
+
+    /// tokens of the queued and the running tasks
+    tokens: BTreeMap<TaskId, CancellationToken>,
+    cancellation_token: CancellationToken,
+    action_tx: UnboundedSender<Action>,
+    worker: JoinHandle<()>,
+}
+
+impl TaskQueue {
+    /// Spawns the worker that executes the tasks on the given state
+    pub fn new(state: Rc<Mutex<NebulaCliState>>, action_tx: UnboundedSender<Action>) -> Self {
+        let (queue_tx, queue_rx) = mpsc::unbounded_channel();
+        let worker = tokio::task::spawn_local(worker(state, queue_rx, action_tx.clone()));
+        Self {
+            next_id: 0,
+            queue_tx,
+            tokens: BTreeMap::new(),
+            cancellation_token: CancellationToken::new(),
+            action_tx,
+            worker,
+        }
+    }
+
+    /// Queues a command line, e.g. `install iris-classical@=1.0.0`
+    pub fn enqueue(&mut self, command: String) -> TaskId {
+        let id = self.next_id;
+        self.next_id += 1;
+        let token = self.cancellation_token.child_token();
+        self.tokens.insert(id, token.clone());
+        let _ =
+            self.action_tx.send(Action::Task(TaskEvent::Queued { id, command: command.clone() }));
+        let _ = self.queue_tx.send(QueuedTask { id, command, token });
+        id
+    }
+
+    /// Cancels the oldest task that is not done, that is the running one if any
+    pub fn cancel_next(&mut self) {
+        if let Some(token) = self.tokens.values().next() {
+            token.cancel();
+        }
+    }
+
+    /// Cancels the running and all queued tasks
+    pub fn cancel_all(&mut self) {
+        self.tokens.values().for_each(CancellationToken::cancel);
+    }
+
+    /// Keeps track of the tasks that are done
+    pub fn on_event(&mut self, event: &TaskEvent) {
+        if event.is_final() {
+            self.tokens.remove(&event.id());
+        }
+    }
+
+    /// Cancels all tasks and stops the worker
+    pub fn stop(&self) {
+        self.cancellation_token.cancel();
+        self.worker.abort();
+    }
+}
+
+async fn worker(
+    state: Rc<Mutex<NebulaCliState>>,
+    mut queue_rx: UnboundedReceiver<QueuedTask>,
+    action_tx: UnboundedSender<Action>,
+) {
+    while let Some(task) = queue_rx.recv().await {
+        let event = if task.token.is_cancelled() {
+            TaskEvent::Cancelled(task.id)
+        } else {
+            let _ = action_tx.send(Action::Task(TaskEvent::Started(task.id)));
+            let mut state = state.lock().await;
+            tokio::select! {
+                _ = task.token.cancelled() => TaskEvent::Cancelled(task.id),
+                event = execute(&task, &mut state, action_tx.clone()) => event,
+            }
+        };
+        let _ = action_tx.send(Action::Task(event));
+    }
+}
+
+async fn execute(
+    task: &QueuedTask,
+    state: &mut NebulaCliState,
+    action_tx: UnboundedSender<Action>,
+) -> TaskEvent {
+    let id = task.id;
+    let words = match split_words(&task.command) {
+        Ok(words) => words,
+        Err(err) => return TaskEvent::Failed { id, error: err.to_string() },
+    };
+    let args = std::iter::once("nebula".to_string()).chain(words);
+    let mut handler = RatatuiPostCommandHandler::new(id, action_tx);
+    match command_interpret(args, state, &mut handler).await {
+        Ok(ExitStatus::Success) => TaskEvent::Finished(id),
+        Ok(status) => TaskEvent::Failed {
+            id,
+            error: handler.error.take().unwrap_or_else(|| format!("failed with {:?}", status)),
+        },
+        Err(err) => TaskEvent::Failed { id, error: format!("{:#}", err) },
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Download {
+    downloaded: u64,
+    total: Option<u64>,
+    rate: f64,
+}
+
+/// Forwards the results and the download progress of a command as [TaskEvent]
+pub struct RatatuiPostCommandHandler {
+    id: TaskId,
+    action_tx: UnboundedSender<Action>,
+    downloads: RefCell<HashMap<String, Download>>,
+    error: RefCell<Option<String>>,
+}
+
+impl RatatuiPostCommandHandler {
+    pub fn new(id: TaskId, action_tx: UnboundedSender<Action>) -> Self {
+        Self { id, action_tx, downloads: Default::default(), error: Default::default() }
+    }
+
+    fn send(&self, event: TaskEvent) {
+        let _ = self.action_tx.send(Action::Task(event));
+    }
+
+    fn message(&self, message: String) {
+        self.send(TaskEvent::Message { id: self.id, message });
+    }
+}
+
+impl PostCommandHandler for RatatuiPostCommandHandler {
+    fn on_install(&self, res: InstallResult) {
+        let names: Vec<_> = res.installed.iter().filter_map(|dp| dp.name.clone()).collect();
+        if names.is_empty() {
+            self.message("already installed".into());
+        } else {
+            self.message(format!("installed {}", names.join(", ")));
+        }
+    }
+
+    fn on_uninstall(&self, res: UninstallResult) {
+        let removed: Vec<_> =
+            res.removed.iter().map(|(name, version)| format!("{}@{}", name, version)).collect();
+        self.message(format!("removed {}", removed.join(", ")));
+    }
+
+    fn on_sync(&self, res: SyncResult) {
+        match res.failed.len() {
+            0 => self.message(format!("synced {} packages", res.synced.len())),
+            failed => self.message(format!("synced {}, {} failed", res.synced.len(), failed)),
+        }
+    }
+
+    fn on_download(&self, event: DownloadEvent) {
+        let mut downloads = self.downloads.borrow_mut();
+        match event {
+            DownloadEvent::Started { name, total, resumed } => {
+                downloads.insert(name, Download { downloaded: resumed, total, rate: 0.0 });
+            }
+            DownloadEvent::Progress(progress) => {
+                downloads.insert(
+                    progress.name,
+                    Download {
+                        downloaded: progress.downloaded,
+                        total: progress.total,
+                        rate: progress.rate,
+                    },
+                );
+            }
+            DownloadEvent::Finished { name, bytes } => {
+                downloads
+                    .insert(name, Download { downloaded: bytes, total: Some(bytes), rate: 0.0 });
+            }
+            DownloadEvent::Failed { name, error } => {
+                self.message(format!("{}: {}", name, error));
+            }
+        }
+
+        let total = downloads.values().map(|dl| dl.total).sum::<Option<u64>>();
+        self.send(TaskEvent::Progress {
+            id: self.id,
+            downloaded: downloads.values().map(|dl| dl.downloaded).sum(),
+            total,
+            rate: downloads.values().map(|dl| dl.rate).sum(),
+        });
+    }
+
+    fn on_cli_error(&self, rep: &Report) {
+        *self.error.borrow_mut() = Some(format!("{:#}", rep));
+    }
+
+    fn on_clap_error(&self, rep: &Report) {
+        *self.error.borrow_mut() = Some(rep.to_string());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use nebula_common::client::download::DownloadProgress;
+
+    use super::*;
+
+    async fn next_task_event(rx: &mut UnboundedReceiver<Action>) -> TaskEvent {
+        loop {
+            if let Some(Action::Task(event)) = rx.recv().await {
+                return event;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queue_and_cancel() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let folder = PathBuf::from("tmp_tasks");
+                let mut state = NebulaCliState::new(folder.join("data"), folder.join("config"));
+                state.set_offline(true);
+                let (tx, mut rx) = mpsc::unbounded_channel();
+                let mut queue = TaskQueue::new(Rc::new(Mutex::new(state)), tx);
+
+                // the worker runs when the test awaits, the second task is cancelled while it is queued
+                queue.enqueue("sync".into());
+                let cancelled = queue.enqueue("install iris-classical".into());
+                queue.tokens[&cancelled].cancel();
+                queue.enqueue("search 'unclosed".into());
+
+                let mut events = vec![];
+                while events.iter().filter(|event: &&TaskEvent| event.is_final()).count() < 3 {
+                    let event = next_task_event(&mut rx).await;
+                    queue.on_event(&event);
+                    events.push(event);
+                }
+                assert_eq!(events[0], TaskEvent::Queued { id: 0, command: "sync".into() });
+                assert!(events.contains(&TaskEvent::Started(0)));
+                assert!(events.iter().any(|event| matches!(
+                    event,
+                    TaskEvent::Failed { id: 0, error } if error.contains("needs network access")
+                )));
+                assert!(events.contains(&TaskEvent::Cancelled(1)));
+                assert!(!events.contains(&TaskEvent::Started(1)));
+                assert!(events.iter().any(|event| matches!(
+                    event,
+                    TaskEvent::Failed { id: 2, error } if error.contains("missing closing quote")
+                )));
+                assert!(queue.tokens.is_empty());
+                queue.stop();
+                let _ = std::fs::remove_dir_all(&folder);
+            })
+            .await;
+    }
+
+    #[test]
+    fn test_download_progress() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let handler = RatatuiPostCommandHandler::new(7, tx);
+        handler.on_download(DownloadEvent::Started {
+            name: "a".into(),
+            total: Some(100),
+            resumed: 10,
+        });
+        handler.on_download(DownloadEvent::Started {
+            name: "b".into(),
+            total: Some(50),
+            resumed: 0,
+        });
+        handler.on_download(DownloadEvent::Progress(DownloadProgress {
+            name: "b".into(),
+            downloaded: 25,
+            total: Some(50),
+            rate: 1000.0,
+            eta: None,
+        }));
+
+        let mut last = None;
+        while let Ok(Action::Task(event)) = rx.try_recv() {
+            last = Some(event);
+        }
+        assert_eq!(
+            last,
+            Some(TaskEvent::Progress { id: 7, downloaded: 35, total: Some(150), rate: 1000.0 })
+        );
+    }
+}
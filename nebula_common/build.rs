@@ -1,4 +1,32 @@
+use std::{env, path::PathBuf};
+
 fn main() {
-    tonic_build::compile_protos("proto/nebula.proto")
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    let mut builder = tonic_build::configure();
+    // the responses are printed as json or yaml by the command line tool
+    for message in [
+        "PackageInfo",
+        "License",
+        "Author",
+        "DeltaMetadata",
+        "ModelMetadata",
+        "TensorSpec",
+        "DownloadCount",
+        "ServerInfo",
+    ] {
+        builder = builder
+            .type_attribute(format!(".nebula.v1.{}", message), "#[derive(serde::Serialize)]");
+    }
+    builder
+        .field_attribute(
+            ".nebula.v1.PackageInfo.package_type",
+            "#[serde(serialize_with = \"crate::nebula_proto::serialize_package_type\")]",
+        )
+        .field_attribute(
+            ".nebula.v1.ServerInfo.features",
+            "#[serde(serialize_with = \"crate::nebula_proto::serialize_features\")]",
+        )
+        .file_descriptor_set_path(out_dir.join("nebula_descriptor.bin"))
+        .compile_protos(&["proto/nebula.proto"], &["proto"])
         .unwrap_or_else(|e| panic!("Failed to compile protos {:?}", e));
 }
@@ -0,0 +1,225 @@
+//! Functionality for showing the details of one package
+
+use std::path::PathBuf;
+
+use color_eyre::{
+    Section as _,
+    eyre::{Report, eyre},
+};
+use semver::Version;
+
+use crate::{
+    NebulaCliState,
+    client::get_package_info,
+    datapackage::DataPackage,
+    model::resolver::{ResolveError, parse_package_spec},
+    nebula_proto::PackageInfo,
+};
+
+use super::install::list_all;
+
+pub struct InfoArgs {
+    /// name of the package, optionally with a version requirement as `name@requirement`
+    pub package: String,
+
+    /// ask the registry instead of the local cache
+    pub remote: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct InfoResult {
+    /// the descriptor of the newest matching version
+    pub package: DataPackage,
+
+    /// true if the descriptor is from the registry
+    pub remote: bool,
+
+    /// versions of the package in the local cache, newest first
+    pub versions: Vec<String>,
+
+    /// bytes that are transferred to install the package
+    pub download_size: u64,
+
+    /// bytes that are kept on disk after the installation
+    pub installed_size: u64,
+
+    /// installed versions of the package, newest first
+    pub installed: Vec<InstalledVersion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct InstalledVersion {
+    pub version: String,
+
+    /// folder with the descriptor and the resources
+    pub path: PathBuf,
+}
+
+/// Gathers the descriptor and install state of a package from the local cache or the registry
+pub async fn package_info(
+    args: InfoArgs,
+    state: &mut NebulaCliState,
+) -> Result<InfoResult, Report> {
+    let (name, req) = parse_package_spec(&args.package)?;
+    let matches = |dp: &DataPackage| {
+        dp.name.as_deref() == Some(name.as_str())
+            && dp
+                .version
+                .as_deref()
+                .and_then(|v| Version::parse(v).ok())
+                .is_some_and(|v| req.matches(&v))
+    };
+
+    let mut cached: Vec<DataPackage> = list_all(state).await.into_iter().filter(&matches).collect();
+    cached.sort_by_cached_key(|dp| dp.version.as_deref().and_then(|v| Version::parse(v).ok()));
+    cached.reverse();
+    let versions = cached.iter().filter_map(|dp| dp.version.clone()).collect();
+
+    let package = if args.remote {
+        let client = state.client().await?;
+        let info = get_package_info(client, name.clone()).await?;
+        remote_package(info, &args.package, matches)?
+    } else {
+        cached.into_iter().next().ok_or_else(|| {
+            Report::new(ResolveError::NotFound(args.package.clone()))
+                .with_suggestion(|| "run 'nebula sync' or use '--remote' to ask the registry")
+        })?
+    };
+
+    let installed_source = state.installed()?;
+    let mut installed: Vec<InstalledVersion> = list_all(&installed_source)
+        .await
+        .into_iter()
+        .filter(|dp| dp.name.as_deref() == Some(name.as_str()))
+        .filter_map(|dp| dp.version.clone())
+        .map(|version| InstalledVersion {
+            path: installed_source.path().join(&name).join(&version),
+            version,
+        })
+        .collect();
+    installed.sort_by_cached_key(|el| Version::parse(&el.version).ok());
+    installed.reverse();
+
+    Ok(InfoResult {
+        download_size: package.download_size(),
+        installed_size: package.installed_size(),
+        package,
+        remote: args.remote,
+        versions,
+        installed,
+    })
+}
+
+/// The descriptor sent by the registry, if it fulfills the package specification
+fn remote_package(
+    info: PackageInfo,
+    spec: &str,
+    matches: impl Fn(&DataPackage) -> bool,
+) -> Result<DataPackage, Report> {
+    let package = DataPackage::try_from(info)
+        .map_err(|err| eyre!("The registry sent an invalid descriptor: {}", err))?;
+    if !matches(&package) {
+        return Err(Report::new(ResolveError::NotFound(spec.to_string()))
+            .with_note(|| "the registry serves the newest version only"));
+    }
+    Ok(package)
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        datapackage::{DataPackageNotValidated, DataResourceNotValidated, ValidateData as _},
+        storage::MetaDataSource as _,
+    };
+
+    fn package(name: &str, version: &str) -> DataPackage {
+        DataPackageNotValidated {
+            name: Some(name.into()),
+            version: Some(version.into()),
+            id: Some(Uuid::new_v4().to_string()),
+            resources: vec![DataResourceNotValidated { name: "data".into(), ..Default::default() }],
+            ..Default::default()
+        }
+        .validate()
+        .unwrap()
+    }
+
+    fn args(package: &str) -> InfoArgs {
+        InfoArgs { package: package.into(), remote: false }
+    }
+
+    fn is_not_found(report: &Report) -> bool {
+        matches!(report.downcast_ref::<ResolveError>(), Some(ResolveError::NotFound(_)))
+    }
+
+    #[tokio::test]
+    async fn test_local_versions() {
+        let folder = PathBuf::from("tmp_info_local");
+        let _ = std::fs::remove_dir_all(&folder);
+        let mut state = NebulaCliState::new(folder.clone(), folder.join("config"));
+        state.init_data_source();
+        for (name, version) in
+            [("iris", "1.0.0"), ("iris", "1.10.0"), ("iris", "1.2.0"), ("iris", "2.0.0-rc.1")]
+                .into_iter()
+                .chain([("iris-classical", "1.5.0")])
+        {
+            state.put_package_metadata(&package(name, version)).await.unwrap();
+        }
+        let mut installed = state.installed().unwrap();
+        for version in ["1.0.0", "1.10.0"] {
+            installed.put_package_metadata(&package("iris", version)).await.unwrap();
+        }
+        installed.put_package_metadata(&package("iris-classical", "1.5.0")).await.unwrap();
+
+        // versions are compared as semver, not as text
+        let res = package_info(args("iris@^1"), &mut state).await.unwrap();
+        assert_eq!(res.package.version.as_deref(), Some("1.10.0"));
+        assert_eq!(res.versions, vec!["1.10.0", "1.2.0", "1.0.0"]);
+        assert!(!res.remote);
+
+        let res = package_info(args("iris"), &mut state).await.unwrap();
+        assert_eq!(res.package.version.as_deref(), Some("1.10.0"));
+        assert_eq!(res.versions, vec!["1.10.0", "1.2.0", "1.0.0"]);
+        let res = package_info(args("iris@>=2.0.0-rc.1"), &mut state).await.unwrap();
+        assert_eq!(res.versions, vec!["2.0.0-rc.1"]);
+
+        // the installed versions of the package only, with their folders
+        let root = folder.join("installed").join("iris");
+        assert_eq!(
+            res.installed,
+            vec![
+                InstalledVersion { version: "1.10.0".into(), path: root.join("1.10.0") },
+                InstalledVersion { version: "1.0.0".into(), path: root.join("1.0.0") },
+            ]
+        );
+
+        let err = package_info(args("iris@^3"), &mut state).await.unwrap_err();
+        assert!(is_not_found(&err));
+        let err = package_info(args("iri"), &mut state).await.unwrap_err();
+        assert!(is_not_found(&err));
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn test_remote_package() {
+        let info = |dp: DataPackage| PackageInfo {
+            datapackage_json: Some(serde_json::to_string(&*dp).unwrap()),
+            ..Default::default()
+        };
+        let is_iris = |dp: &DataPackage| dp.name.as_deref() == Some("iris");
+
+        let dp = remote_package(info(package("iris", "1.0.0")), "iris", is_iris).unwrap();
+        assert_eq!(dp.version.as_deref(), Some("1.0.0"));
+
+        // the registry answered with another package
+        let err = remote_package(info(package("iris-classical", "1.0.0")), "iris", is_iris);
+        assert!(is_not_found(&err.unwrap_err()));
+
+        let err = remote_package(PackageInfo::default(), "iris", is_iris).unwrap_err();
+        assert!(!is_not_found(&err));
+    }
+}
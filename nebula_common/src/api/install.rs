@@ -0,0 +1,422 @@
+//! Functionality for installing packages together with their dependencies
+
+use std::path::{Component, Path, PathBuf};
+
+use color_eyre::{
+    Section as _,
+    eyre::{Report, eyre},
+};
+use semver::VersionReq;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::warn;
+
+use crate::{
+    NebulaCliState,
+    archive::{ArchiveFormat, ArchiveMember, ExtractLimits, extract_members},
+    client::download::{DownloadEvent, DownloadManager, DownloadRequest},
+    datapackage::{DataPackage, DataResourceNotValidated},
+    model::{
+        FieldSettings, FilterSettings, PagationSettings, SortSettings,
+        resolver::{Resolution, ResolveError, Resolver, parse_package_spec},
+    },
+    nebula_proto::RegistryFeature,
+    storage::{MetaDataSource as _, blob::BlobDigest},
+};
+
+pub struct InstallArgs {
+    /// name of the package, optionally with a version requirement as `name@requirement`
+    pub package: String,
+
+    /// receives the progress of the resource downloads
+    pub events: Option<UnboundedSender<DownloadEvent>>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct InstallResult {
+    /// the newly installed packages, dependencies first
+    pub installed: Vec<DataPackage>,
+
+    /// packages of the resolution that were installed before
+    pub unchanged: Vec<DataPackage>,
+}
+
+/// Resolves the dependencies against the local cache and installs the missing packages
+pub async fn install_package(
+    args: InstallArgs,
+    state: &mut NebulaCliState,
+) -> Result<InstallResult, Report> {
+    let (name, req) = parse_package_spec(&args.package)?;
+    let resolution = resolve(state, &name, &req).await?;
+
+    let via_gateway = gateway_downloads(state).await;
+    let mut installed = state.installed()?;
+    let present = list_all(&installed).await;
+    let mut reval = InstallResult { installed: vec![], unchanged: vec![] };
+    let mut requests = vec![];
+    for package in resolution.install_order() {
+        if present.iter().any(|dp| dp.name == package.name && dp.version == package.version) {
+            reval.unchanged.push(package.clone());
+            continue;
+        }
+        requests.extend(download_requests(state, installed.path(), package, via_gateway)?);
+        reval.installed.push(package.clone());
+    }
+
+    // the descriptors are written once all resources are there, a failed installation is
+    // resumed by the next attempt
+    let mut manager = DownloadManager::new(state.download_settings())?;
+    if let Some(events) = args.events {
+        manager = manager.with_events(events);
+    }
+    let failed: Vec<String> = requests
+        .iter()
+        .zip(manager.download_all(&requests).await)
+        .filter_map(|(request, result)| {
+            result.err().map(|err| format!("{}: {}", request.name, err))
+        })
+        .collect();
+    if !failed.is_empty() {
+        return Err(eyre!("Downloading {} resource(s) failed", failed.len())
+            .with_section(|| failed.join("\n"))
+            .with_suggestion(|| "run the installation again to resume the downloads"));
+    }
+
+    for package in reval.installed.iter() {
+        extract_archives(installed.path(), package).await?;
+        installed.put_package_metadata(package).await?;
+    }
+    Ok(reval)
+}
+
+/// True if the registry advertises downloads and its gateway is configured
+///
+/// The gateway then serves remote resources as well, such that the registry mirrors and counts
+/// them. Without a connection the remote resources are downloaded from their origin.
+async fn gateway_downloads(state: &mut NebulaCliState) -> bool {
+    if state.is_offline() || state.gateway_url().is_none() {
+        return false;
+    }
+    if let Err(err) = state.client().await {
+        warn!("Downloading remote resources from their origin: {}", err);
+        return false;
+    }
+    state
+        .server_info()
+        .is_some_and(|info| info.features.contains(&(RegistryFeature::Download as i32)))
+}
+
+/// The downloads of the resources of a package into its folder below `root`
+///
+/// Files not hosted remotely are served by the registry gateway, remote files only if
+/// `via_gateway` is set.
+fn download_requests(
+    state: &NebulaCliState,
+    root: &Path,
+    package: &DataPackage,
+    via_gateway: bool,
+) -> Result<Vec<DownloadRequest>, Report> {
+    let name = package.name.as_deref().unwrap_or_default();
+    let version = package.version.as_deref().unwrap_or_default();
+    let folder = package_folder(root, package)?;
+
+    let mut reval = vec![];
+    for dr in package.resources.iter().filter(|dr| dr.is_downloaded()) {
+        let paths = resource_paths(dr);
+        for (index, path) in paths.iter().enumerate() {
+            let url = if is_remote(path) && !via_gateway {
+                path.to_string()
+            } else {
+                let gateway = state.gateway_url().ok_or_else(|| {
+                    eyre!(
+                        "Resource '{}' of '{}' is hosted by the registry, its gateway is unknown",
+                        dr.name,
+                        name
+                    )
+                    .with_suggestion(|| "configure remote_registry.gateway_url")
+                })?;
+                gateway_resource_url(gateway, name, version, &dr.name, index)?
+            };
+            let target = resource_target(&folder, dr, index, paths.len())?;
+            let (request_name, size) = match paths.len() {
+                1 => (dr.name.clone(), dr.bytes),
+                _ => (format!("{}[{}]", dr.name, index), None),
+            };
+            reval.push(DownloadRequest { name: request_name, url, target, size });
+        }
+    }
+    Ok(reval)
+}
+
+/// The gateway route of a resource file of a package version, the names are percent-encoded as
+/// path segments
+fn gateway_resource_url(
+    gateway: &str,
+    package: &str,
+    version: &str,
+    resource: &str,
+    index: usize,
+) -> Result<String, Report> {
+    let mut url = reqwest::Url::parse(gateway)
+        .map_err(|err| eyre!("Invalid gateway url '{}': {}", gateway, err))?;
+    url.path_segments_mut()
+        .map_err(|_| eyre!("Invalid gateway url '{}'", gateway))?
+        .pop_if_empty()
+        .extend(["v1", "packages", package, "resources", resource]);
+    url.query_pairs_mut().append_pair("index", &index.to_string()).append_pair("version", version);
+    Ok(url.into())
+}
+
+/// Extracts the `local-archive` resources of a package and removes the temporary archives
+async fn extract_archives(root: &Path, package: &DataPackage) -> Result<(), Report> {
+    let folder = package_folder(root, package)?;
+    let archives: Vec<(&DataResourceNotValidated, ArchiveFormat)> = package
+        .resources
+        .iter()
+        .filter(|dr| dr.is_downloaded() && resource_paths(dr).len() == 1)
+        .filter_map(|dr| {
+            let format = dr.format.as_deref().and_then(ArchiveFormat::detect);
+            format
+                .or_else(|| resource_paths(dr).first().and_then(|p| ArchiveFormat::detect(p)))
+                .map(|format| (dr, format))
+        })
+        .collect();
+
+    let mut members: Vec<Vec<ArchiveMember>> = vec![vec![]; archives.len()];
+    for dr in package.resources.iter().filter(|dr| !dr.is_downloaded() && dr.path.is_some()) {
+        let wanted = dr.delta.as_ref().and_then(|delta| delta.archive.as_deref());
+        let candidates: Vec<usize> = (0..archives.len())
+            .filter(|i| wanted.is_none_or(|name| archives[*i].0.name == name))
+            .collect();
+        let [index] = candidates[..] else {
+            return Err(eyre!("Archive of resource '{}' is ambiguous or missing", dr.name)
+                .with_suggestion(|| "name the archive resource in the delta extension"));
+        };
+        for path in resource_paths(dr) {
+            let relative = relative_file(path)
+                .ok_or_else(|| eyre!("Resource '{}' has the invalid path '{}'", dr.name, path))?;
+            members[index].push(ArchiveMember { path: path.into(), target: folder.join(relative) });
+        }
+    }
+
+    for ((dr, format), members) in archives.into_iter().zip(members) {
+        let archive = resource_target(&folder, dr, 0, 1)?;
+        if !members.is_empty() {
+            let path = archive.clone();
+            tokio::task::spawn_blocking(move || {
+                extract_members(&path, format, &members, &ExtractLimits::default())
+            })
+            .await?
+            .map_err(|err| Report::new(err).wrap_err(format!("Extracting '{}' failed", dr.name)))?;
+        }
+        if !dr.is_installed() {
+            tokio::fs::remove_file(&archive).await?;
+        }
+    }
+    Ok(())
+}
+
+fn package_folder(root: &Path, package: &DataPackage) -> Result<PathBuf, Report> {
+    let name = package.name.as_deref().ok_or_else(|| eyre!("Package missing name"))?;
+    let version = package.version.as_deref().ok_or_else(|| eyre!("Package missing version"))?;
+    let name = segment(name).ok_or_else(|| eyre!("Package has the invalid name '{}'", name))?;
+    let version = segment(version)
+        .ok_or_else(|| eyre!("Package '{}' has the invalid version '{}'", name, version))?;
+    Ok(root.join(name).join(version))
+}
+
+fn resource_paths(dr: &DataResourceNotValidated) -> Vec<&str> {
+    (0..).map_while(|i| dr.path.as_ref().and_then(|p| p.nth(i))).collect()
+}
+
+fn is_remote(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Where a downloaded file of a resource is stored
+///
+/// Files in the package folder of the registry keep their relative path, other resources are
+/// named after the resource.
+fn resource_target(
+    folder: &Path,
+    dr: &DataResourceNotValidated,
+    index: usize,
+    count: usize,
+) -> Result<PathBuf, Report> {
+    let path = resource_paths(dr).get(index).copied().unwrap_or_default();
+    match relative_file(path) {
+        Some(relative) if !is_remote(path) => Ok(folder.join(relative)),
+        _ => {
+            let mut file = segment(&dr.name)
+                .ok_or_else(|| eyre!("Resource has the invalid name '{}'", dr.name))?
+                .to_string();
+            if count > 1 {
+                file.push_str(&format!("-{}", index));
+            }
+            if let Some(format) = dr.format.as_deref() {
+                let format = segment(format).ok_or_else(|| {
+                    eyre!("Resource '{}' has the invalid format '{}'", dr.name, format)
+                })?;
+                file.push_str(&format!(".{}", format));
+            }
+            Ok(folder.join(file))
+        }
+    }
+}
+
+/// A name that is a single component of a path, like `relative_file` it never leaves the folder
+fn segment(name: &str) -> Option<&str> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(c)), None) if c == name => Some(name),
+        _ => None,
+    }
+}
+
+/// A relative path that stays inside the package folder, blob references are no files
+fn relative_file(path: &str) -> Option<&Path> {
+    let relative = Path::new(path);
+    (!path.is_empty()
+        && BlobDigest::from_reference(path).is_none()
+        && relative.components().all(|c| matches!(c, Component::Normal(_))))
+    .then_some(relative)
+}
+
+/// Resolves a package and its dependencies against the local cache
+pub(crate) async fn resolve(
+    state: &NebulaCliState,
+    name: &str,
+    req: &VersionReq,
+) -> Result<Resolution, Report> {
+    let available = list_all(state).await;
+    Resolver::new(available).resolve(name, req).map_err(|err| {
+        let missing = matches!(err, ResolveError::NotFound(_));
+        let report = Report::new(err);
+        if missing {
+            report.with_suggestion(|| "run 'nebula sync' to update the local cache")
+        } else {
+            report
+        }
+    })
+}
+
+/// All descriptors of a data source, e.g. the installed packages of [NebulaCliState::installed]
+pub async fn list_all(ds: &dyn crate::storage::MetaDataSource) -> Vec<DataPackage> {
+    ds.list_packages(
+        SortSettings::default(),
+        FilterSettings::default(),
+        PagationSettings::unlimited(),
+        FieldSettings::default(),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write as _;
+
+    use super::*;
+    use crate::datapackage::{
+        DataPackageNotValidated, DeltaDataResourceNotValidated, PathSingleOrVec, ValidateData as _,
+    };
+
+    fn delta(origin: &str, local_storage: &str) -> Option<DeltaDataResourceNotValidated> {
+        Some(DeltaDataResourceNotValidated {
+            origin: origin.into(),
+            format: None,
+            local_storage: local_storage.into(),
+            archive: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_extract_archives() {
+        let root = PathBuf::from("tmp_install_archives");
+        let _ = std::fs::remove_dir_all(&root);
+        let package = DataPackageNotValidated {
+            name: Some("cifar".into()),
+            version: Some("1.0.0".into()),
+            resources: vec![
+                DataResourceNotValidated {
+                    name: "complete-archive".into(),
+                    path: Some(PathSingleOrVec::Single("https://host/cifar.tar.gz?x=1".into())),
+                    format: Some("tar.gz".into()),
+                    delta: delta("remote", "temp"),
+                    ..Default::default()
+                },
+                DataResourceNotValidated {
+                    name: "train-batch".into(),
+                    path: Some(PathSingleOrVec::Vec(vec![
+                        "batch_1.bin".into(),
+                        "batch_2.bin".into(),
+                    ])),
+                    delta: delta("local-archive", "installed"),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+        .validate()
+        .unwrap();
+
+        let folder = package_folder(&root, &package).unwrap();
+        let archive = resource_target(&folder, &package.resources[0], 0, 1).unwrap();
+        assert_eq!(archive, folder.join("complete-archive.tar.gz"));
+        std::fs::create_dir_all(&folder).unwrap();
+        let mut builder = tar::Builder::new(vec![]);
+        for (path, content) in [("cifar/batch_1.bin", b"one"), ("cifar/batch_2.bin", b"two")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(3);
+            builder.append_data(&mut header, path, content.as_slice()).unwrap();
+        }
+        let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::fast());
+        gz.write_all(&builder.into_inner().unwrap()).unwrap();
+        std::fs::write(&archive, gz.finish().unwrap()).unwrap();
+
+        extract_archives(&root, &package).await.unwrap();
+        assert_eq!(std::fs::read(folder.join("batch_1.bin")).unwrap(), b"one");
+        assert_eq!(std::fs::read(folder.join("batch_2.bin")).unwrap(), b"two");
+        assert!(!archive.exists(), "temporary archives are removed");
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_single_segments() {
+        let mut package = DataPackageNotValidated {
+            name: Some("../iris".into()),
+            version: Some("1.0.0".into()),
+            resources: vec![DataResourceNotValidated {
+                name: "data".into(),
+                path: Some(PathSingleOrVec::Single("https://host/iris.csv".into())),
+                format: Some("csv".into()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let root = Path::new("root");
+        assert!(package_folder(root, &package.clone().validate().unwrap()).is_err());
+        package.name = Some("iris".into());
+        package.version = Some("1.0.0/..".into());
+        assert!(package_folder(root, &package.clone().validate().unwrap()).is_err());
+
+        package.version = Some("1.0.0".into());
+        let folder = package_folder(root, &package.clone().validate().unwrap()).unwrap();
+        assert_eq!(folder, root.join("iris").join("1.0.0"));
+        for (name, format) in [("data", "csv/../../x"), ("../data", "csv"), ("/data", "csv")] {
+            package.resources[0].name = name.into();
+            package.resources[0].format = Some(format.into());
+            let package = package.clone().validate().unwrap();
+            assert!(resource_target(&folder, &package.resources[0], 0, 1).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_gateway_resource_url() {
+        assert_eq!(
+            gateway_resource_url("http://localhost:8080/", "iris classical", "1.0.0+x", "a/b?c", 2)
+                .unwrap(),
+            "http://localhost:8080/v1/packages/iris%20classical/resources/a%2Fb%3Fc?index=2&version=1.0.0%2Bx"
+        );
+        assert!(gateway_resource_url("not a url", "iris", "1.0.0", "data", 0).is_err());
+    }
+}
@@ -20,6 +20,7 @@ pub struct ListArgs {
     pub package_type: PackageType,
 }
 
+#[derive(Debug, serde::Serialize)]
 pub struct ListResult {
     pub packages: Vec<DataPackage>,
 }
@@ -30,9 +31,9 @@ pub async fn list_packages(
 ) -> Result<ListResult, Report> {
     let reval: Result<Vec<DataPackage>, DataSourceError> = state
         .apply_data_source(async move |ds| {
-            let filter = FilterSettings { package_type: args.package_type };
+            let filter = FilterSettings { package_type: args.package_type, ..Default::default() };
             let sort = SortSettings::default();
-            let pagation = PagationSettings::default();
+            let pagation = PagationSettings::unlimited();
             let fields = FieldSettings::default();
 
             Ok(ds.list_packages(sort, filter, pagation, fields).await)
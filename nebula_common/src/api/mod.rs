@@ -1,15 +1,31 @@
 //! implementation of an API for the nebula command line interface
 
+mod info;
 mod install;
 mod list;
 mod search;
 mod status;
 mod sync;
+mod tree;
 mod uninstall;
 mod update;
 
 pub(crate) mod state;
 
+pub use state::INSTALLED_FOLDER;
+pub use state::LOCAL_REGISTRY_FOLDER;
+pub use state::NetworkError;
+
+pub use info::InfoArgs;
+pub use info::InfoResult;
+pub use info::InstalledVersion;
+pub use info::package_info;
+
+pub use install::InstallArgs;
+pub use install::InstallResult;
+pub use install::install_package;
+pub use install::list_all;
+
 pub use list::ListArgs;
 pub use list::ListResult;
 pub use list::list_packages;
@@ -17,6 +33,17 @@ pub use list::list_packages;
 pub use search::SearchArgs;
 pub use search::search_package;
 
+pub use status::StatusArgs;
+pub use status::StatusResult;
+pub use status::status;
+
 pub use sync::SyncArgs;
-pub use sync::SyncRe;
+pub use sync::SyncResult;
 pub use sync::sync_packages;
+
+pub use tree::TreeArgs;
+pub use tree::dependency_tree;
+
+pub use uninstall::UninstallArgs;
+pub use uninstall::UninstallResult;
+pub use uninstall::uninstall_package;
@@ -1,15 +1,167 @@
+use std::collections::HashMap;
+
 use color_eyre::eyre::Report;
+use tracing::{debug, warn};
+
+use crate::{
+    NebulaCliState,
+    client::{is_unreachable, search_packages},
+    datapackage::DataPackage,
+    model::{
+        DateRange, FieldSettings, FilterSettings, PackageType, PagationSettings, SearchKind,
+        SortSettings,
+    },
+    registry::{self, PackageInfo, SearchPackagesRequest},
+    storage::{MetaDataSource as _, index::SearchIndex, paginate},
+};
 
-use crate::{NebulaCliState, client::search_packages, registry::PackageInfo};
+use super::install::list_all;
 
+#[derive(Debug, Clone, Default)]
 pub struct SearchArgs {
     pub query: String,
+
+    pub kind: SearchKind,
+
+    /// packages by one of the authors, all if empty
+    pub authors: Vec<String>,
+
+    pub package_type: PackageType,
+
+    pub sort: SortSettings,
+
+    pub limit: Option<u32>,
+
+    pub offset: Option<u32>,
+
+    pub created: DateRange,
+
+    pub updated: DateRange,
+
+    /// download counts are known by the registry only
+    pub min_downloads: Option<u32>,
+
+    pub max_downloads: Option<u32>,
+
+    /// category of the delta metadata
+    pub category: Option<String>,
+
+    /// number of classes of the delta metadata
+    pub classes: Option<u32>,
+}
+
+impl SearchArgs {
+    fn filter(&self) -> FilterSettings {
+        FilterSettings {
+            package_type: self.package_type,
+            kind: self.kind,
+            authors: self.authors.clone(),
+            created: self.created,
+            updated: self.updated,
+            category: self.category.clone(),
+            classes: self.classes,
+        }
+    }
+
+    fn pagation(&self) -> PagationSettings {
+        let default = PagationSettings::default();
+        PagationSettings {
+            limit: self.limit.unwrap_or(default.limit),
+            offset: self.offset.unwrap_or(default.offset),
+        }
+    }
+}
+
+impl From<&SearchArgs> for SearchPackagesRequest {
+    fn from(value: &SearchArgs) -> Self {
+        let int = |el: u32| i32::try_from(el).unwrap_or(i32::MAX);
+        let range = |el: &DateRange| (!el.is_unbounded()).then(|| registry::DateRange::from(el));
+        SearchPackagesRequest {
+            search_query: value.query.clone(),
+            package_type: registry::PackageType::from(value.package_type) as i32,
+            sort_parameters: value.sort.levels.iter().map(|&el| el.into()).collect(),
+            limit: value.limit.map(int),
+            offset: value.offset.map(int),
+            created_date: range(&value.created),
+            updated_date: range(&value.updated),
+            kind: Some(registry::SearchKind::from(value.kind) as i32),
+            authors: value.authors.clone(),
+            min_downloads: value.min_downloads.map(int),
+            max_downloads: value.max_downloads.map(int),
+            category: value.category.clone(),
+            classes: value.classes,
+            ..Default::default()
+        }
+    }
 }
 
+/// Searches the remote registry and falls back to the local cache if offline or not reachable
 pub async fn search_package(
     args: SearchArgs,
     state: &mut NebulaCliState,
 ) -> Result<Vec<PackageInfo>, Report> {
-    let tmp = search_packages(state.client()?, args.query).await?;
-    Ok(tmp.packages)
+    if !state.is_offline() {
+        match state.client().await {
+            Ok(client) => match search_packages(client, (&args).into()).await {
+                Ok(list) => return Ok(list.packages),
+                Err(err) if is_unreachable(&err) => warn!("Searching the local cache: {}", err),
+                Err(err) => return Err(err),
+            },
+            Err(err) => warn!("Searching the local cache: {}", err),
+        }
+    }
+
+    if args.min_downloads.is_some() || args.max_downloads.is_some() {
+        warn!("The local cache does not know download counts, ignoring the download filter");
+    }
+    // the index ranks relaxed searches, the data source matches names and authors
+    let mut packages = if args.kind == SearchKind::Relaxed && !args.query.trim().is_empty() {
+        search_index(&args, state).await
+    } else {
+        state
+            .search_package(
+                &args.query,
+                args.sort.clone(),
+                args.filter(),
+                PagationSettings::unlimited(),
+            )
+            .await
+    };
+    args.sort.sort(&mut packages, |_| 0);
+    let packages = paginate(packages, args.pagation());
+    Ok(packages.into_iter().map(PackageInfo::from).collect())
+}
+
+/// Searches the ranked full-text index and applies the filters of the arguments
+async fn search_index(args: &SearchArgs, state: &mut NebulaCliState) -> Vec<DataPackage> {
+    let index = refresh_search_index(state).await;
+    let mut candidates: HashMap<String, DataPackage> = state
+        .list_packages(
+            SortSettings::default(),
+            args.filter(),
+            PagationSettings::unlimited(),
+            FieldSettings::default(),
+        )
+        .await
+        .into_iter()
+        .filter_map(|dp| SearchIndex::key(&dp).map(|key| (key, dp)))
+        .collect();
+    index.search(&args.query).into_iter().filter_map(|hit| candidates.remove(&hit.key)).collect()
+}
+
+/// Updates the persisted search index with the changes of the local cache
+pub(crate) async fn refresh_search_index(state: &NebulaCliState) -> SearchIndex {
+    let path = state.search_index_path();
+    let mut index = SearchIndex::load(&path);
+    let changes = index.update(&list_all(state).await);
+    if !changes.is_empty() {
+        debug!(
+            "Search index: {} added, {} updated, {} removed",
+            changes.added, changes.updated, changes.removed
+        );
+        if let Err(err) = index.save(&path) {
+            warn!("Cannot save the search index '{}': {}", path.display(), err);
+        }
+    }
+    index
 }
@@ -1,19 +1,28 @@
 use std::{path::PathBuf, sync::Arc};
 
-use color_eyre::eyre::{Report, eyre};
+use color_eyre::{
+    Section as _,
+    eyre::{Report, eyre},
+};
 use tokio::sync::Mutex;
 
 use async_trait::async_trait;
 
 use crate::{
-    client::init_client,
-    configuration::cli::{self, get_configuration},
+    client::{RegistryClient, init_client},
+    configuration::cli::{self, DownloadSettings, get_configuration},
     datapackage::DataPackage,
     model::{FieldSettings, FilterSettings, PagationSettings, SortSettings},
-    registry::nebula_package_query_client::NebulaPackageQueryClient,
-    storage::{MetaDataSource, root_folder::RootFolderSource},
+    registry::ServerInfo,
+    storage::{MetaDataSource, index::SearchIndex, root_folder::RootFolderSource},
 };
 
+/// Folder of the data folder that holds the local cache of the registry descriptors
+pub const LOCAL_REGISTRY_FOLDER: &str = "local-registry";
+
+/// Folder of the data folder that holds the installed packages
+pub const INSTALLED_FOLDER: &str = "installed";
+
 /// The state of nebula api (client side)
 ///
 /// todo: get rid of lifetime, e.g. by using async closures to work with [NebulaState::data_source]
@@ -27,11 +36,17 @@ pub struct NebulaState {
 
     config_folder: PathBuf,
 
-    query_client: Option<NebulaPackageQueryClient<tonic::transport::channel::Channel>>,
+    query_client: Option<RegistryClient>,
+
+    /// forbids any network access, the api works on the local cache only
+    offline: bool,
 
     cli_api_settings: Option<cli::Settings>,
 
     data_source: Option<Arc<Mutex<Box<dyn MetaDataSource + Send + Sync>>>>,
+
+    /// descriptors of the installed packages, the resources are stored next to them
+    installed: Option<RootFolderSource>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -40,9 +55,18 @@ pub enum DataSourceError {
     NotAvailable,
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum NetworkError {
+    #[error("Network access to the registry is disabled in offline mode")]
+    Offline,
+
+    #[error("Cannot init client: Configuration not loaded")]
+    NoConfiguration,
+}
+
 impl NebulaState {
     pub fn new(data_folder: PathBuf, config_folder: PathBuf) -> Self {
-        let registry_path = data_folder.join("local-registry");
+        let registry_path = data_folder.join(LOCAL_REGISTRY_FOLDER);
         NebulaState {
             data_folder,
             config_folder,
@@ -51,7 +75,9 @@ impl NebulaState {
             virt_env_path: None,
             cli_api_settings: None,
             query_client: None,
+            offline: false,
             data_source: None,
+            installed: None,
         }
     }
 
@@ -60,22 +86,93 @@ impl NebulaState {
         Ok(())
     }
 
+    /// Connects to the remote registry, the connection is established lazily by [NebulaState::client]
     pub async fn init_client(&mut self) -> Result<(), Report> {
+        if self.offline {
+            return Err(NetworkError::Offline.into());
+        }
+
         if let Some(cfg) = &self.cli_api_settings {
             let rr = &cfg.remote_registry;
-            self.query_client = Some(init_client(&rr.host, rr.port).await?);
+            let client = init_client(&rr.host, rr.port, &rr.connection).await.map_err(|err| {
+                err.wrap_err(format!("Registry at '{}:{}' is not reachable", rr.host, rr.port))
+                    .with_suggestion(|| "use '--offline' to work with the local cache only")
+            })?;
+            self.query_client = Some(client);
             Ok(())
         } else {
-            Err(eyre!("Cannot init client: Configuration not loaded"))
+            Err(NetworkError::NoConfiguration.into())
         }
     }
 
+    /// Overrides the address of the configured remote registry, given as `<host>:<port>`
+    pub fn set_registry_address(&mut self, address: &str) -> Result<(), Report> {
+        let (host, port) = address
+            .rsplit_once(':')
+            .filter(|(host, _)| !host.is_empty())
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+            .ok_or_else(|| {
+                eyre!("Invalid registry address '{}', expected <host>:<port>", address)
+            })?;
+        let cfg = self.cli_api_settings.as_mut().ok_or(NetworkError::NoConfiguration)?;
+        cfg.remote_registry.host = host.to_string();
+        cfg.remote_registry.port = port;
+        self.query_client = None;
+        Ok(())
+    }
+
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+        if offline {
+            self.query_client = None;
+        }
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Address of the remote registry in the form host:port, if the configuration is loaded
+    pub fn registry_address(&self) -> Option<String> {
+        self.cli_api_settings
+            .as_ref()
+            .map(|cfg| format!("{}:{}", cfg.remote_registry.host, cfg.remote_registry.port))
+    }
+
+    /// Settings of the download manager, the defaults if the configuration is not loaded
+    pub fn download_settings(&self) -> DownloadSettings {
+        self.cli_api_settings.as_ref().map(|cfg| cfg.download.clone()).unwrap_or_default()
+    }
+
+    /// Base url of the registry gateway, used to download resources hosted by the registry
+    pub fn gateway_url(&self) -> Option<&str> {
+        self.cli_api_settings.as_ref().and_then(|cfg| cfg.remote_registry.gateway_url.as_deref())
+    }
+
+    /// Information about the remote registry, only available once connected
+    pub fn server_info(&self) -> Option<&ServerInfo> {
+        self.query_client.as_ref().and_then(|client| client.server_info())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.query_client.is_some()
+    }
+
     pub fn init_data_source(&mut self) {
         if self.data_source.is_none() {
             self.data_source = Some(Arc::new(Mutex::new(Box::new(
                 RootFolderSource::new_from_folder(self.registry_path.clone()),
             ))))
         }
+        if self.installed.is_none() {
+            self.installed =
+                Some(RootFolderSource::new_from_folder(self.data_folder.join(INSTALLED_FOLDER)));
+        }
+    }
+
+    /// The installed packages, the source shares its buffer with the state
+    pub fn installed(&self) -> Result<RootFolderSource, DataSourceError> {
+        self.installed.clone().ok_or(DataSourceError::NotAvailable)
     }
 
     pub async fn apply_data_source<R>(
@@ -103,14 +200,21 @@ impl NebulaState {
         &self.registry_path
     }
 
+    /// The full-text search index over the local cache
+    pub fn search_index_path(&self) -> PathBuf {
+        self.data_folder.join(SearchIndex::FILE_NAME)
+    }
+
     pub fn virtual_path(&self) -> &Option<PathBuf> {
         &self.virt_env_path
     }
 
-    pub fn client(
-        &mut self,
-    ) -> Result<&mut NebulaPackageQueryClient<tonic::transport::channel::Channel>, Report> {
-        Ok(self.query_client.as_mut().unwrap())
+    /// Gets the client of the remote registry and connects on first use
+    pub async fn client(&mut self) -> Result<&mut RegistryClient, Report> {
+        if self.query_client.is_none() {
+            self.init_client().await?;
+        }
+        self.query_client.as_mut().ok_or_else(|| eyre!("Client not initialized"))
     }
 }
 
@@ -140,14 +244,37 @@ impl MetaDataSource for NebulaState {
         }
     }
 
+    async fn get_package_by_name(&self, name: &str, filter: FilterSettings) -> Option<DataPackage> {
+        if let Some(ds) = &self.data_source {
+            let ds = ds.lock().await;
+            ds.get_package_by_name(name, filter).await
+        } else {
+            None
+        }
+    }
+
+    async fn get_package_version(&self, name: &str, version: &str) -> Option<DataPackage> {
+        if let Some(ds) = &self.data_source {
+            let ds = ds.lock().await;
+            ds.get_package_version(name, version).await
+        } else {
+            None
+        }
+    }
+
     async fn search_package(
         &self,
-        _search_query: &str,
-        _sort: SortSettings,
-        _filter: FilterSettings,
-        _pagation: PagationSettings,
+        search_query: &str,
+        sort: SortSettings,
+        filter: FilterSettings,
+        pagation: PagationSettings,
     ) -> Vec<DataPackage> {
-        todo!()
+        if let Some(ds) = &self.data_source {
+            let ds = ds.lock().await;
+            ds.search_package(search_query, sort, filter, pagation).await
+        } else {
+            vec![]
+        }
     }
 
     async fn put_package_metadata(&mut self, package: &DataPackage) -> Result<(), Report> {
@@ -0,0 +1,69 @@
+//! Functionality for printing status information
+
+use std::path::PathBuf;
+
+use color_eyre::eyre::Report;
+
+use crate::{
+    NebulaCliState,
+    model::{FieldSettings, FilterSettings, PagationSettings, SortSettings},
+    registry::ServerInfo,
+    storage::MetaDataSource as _,
+};
+
+pub struct StatusArgs {}
+
+#[derive(Debug, serde::Serialize)]
+pub struct StatusResult {
+    /// true if network access is forbidden and only the local cache is used
+    pub offline: bool,
+
+    /// true if a connection to the remote registry has been established
+    pub connected: bool,
+
+    /// why the remote registry could not be connected, None if connected or offline
+    pub connection_error: Option<String>,
+
+    /// address of the remote registry as configured
+    pub registry: Option<String>,
+
+    /// information about the remote registry, only available if connected
+    pub server: Option<ServerInfo>,
+
+    /// folder of the local cache
+    pub cache_folder: PathBuf,
+
+    /// number of packages in the local cache
+    pub cached_packages: usize,
+}
+
+/// Gathers status information from the local state and connects to the remote registry
+///
+/// A registry that cannot be reached is reported as not connected, in offline mode no connection
+/// is attempted.
+pub async fn status(_args: StatusArgs, state: &mut NebulaCliState) -> Result<StatusResult, Report> {
+    let connection_error = if state.is_offline() {
+        None
+    } else {
+        state.client().await.err().map(|err| err.to_string())
+    };
+    let pagation = PagationSettings::unlimited();
+    let cached = state
+        .list_packages(
+            SortSettings::default(),
+            FilterSettings::default(),
+            pagation,
+            FieldSettings::default(),
+        )
+        .await;
+
+    Ok(StatusResult {
+        offline: state.is_offline(),
+        connected: state.is_connected(),
+        connection_error,
+        registry: state.registry_address(),
+        server: state.server_info().cloned(),
+        cache_folder: state.registry().clone(),
+        cached_packages: cached.len(),
+    })
+}
@@ -1,49 +1,65 @@
-use color_eyre::eyre::{self, Report};
-use tracing::info;
+use color_eyre::eyre::Report;
+use tracing::{info, warn};
 
 use crate::{
-    NebulaCliState,
-    client::list_packages,
-    datapackage::{DataPackage, DataPackageNotValidated, ValidateData},
-    registry::{FieldOptions, PackageInfo},
+    NebulaCliState, client::list_all_packages, datapackage::DataPackage, registry::FieldOptions,
     storage::MetaDataSource,
 };
 
-pub struct SyncRe {}
+use super::search::refresh_search_index;
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct SyncResult {
+    /// names of the packages written to the local cache
+    pub synced: Vec<String>,
+
+    /// packages of the registry that could not be cached, with the reason
+    pub failed: Vec<String>,
+
+    /// packages in the offline search index after the sync
+    pub indexed: usize,
+}
 
 pub struct SyncArgs {
     // todo: use timestamp datatype
     pub last_sync: Option<f32>,
 }
 
-fn from_json(pi: &PackageInfo) -> Result<DataPackage, Report> {
-    let data_package: DataPackageNotValidated = serde_json::from_str(pi.datapackage_json())?;
-
-    data_package.validate().map_err(|e| eyre::Report::msg(e.to_string()))
-}
-
-pub async fn sync_packages(_args: SyncArgs, state: &mut NebulaCliState) -> Result<SyncRe, Report> {
+pub async fn sync_packages(
+    _args: SyncArgs,
+    state: &mut NebulaCliState,
+) -> Result<SyncResult, Report> {
     // todo: use timestamp and server side decisions instead of complete list
-    let fo = FieldOptions { include_datapackage_json: true, include_preview_images: false };
-    let package_list = list_packages(Some(fo), state.client().unwrap()).await?;
+    let fo = FieldOptions {
+        include_datapackage_json: true,
+        include_preview_images: false,
+        include_download_history: false,
+    };
+    let packages = list_all_packages(Some(fo), state.client().await?).await?;
 
-    for pi in package_list.packages {
+    let mut reval = SyncResult::default();
+    for pi in packages {
+        let name = pi.name.clone();
         // get datapackage json from package info:
-        match from_json(&pi) {
+        let result = match DataPackage::try_from(pi) {
+            Ok(dp) => state.put_package_metadata(&dp).await.map(|_| dp),
+            Err(err) => Err(Report::new(err)),
+        };
+        match result {
             Ok(dp) => {
-                if let Err(err) = state.put_package_metadata(&dp).await {
-                    println!("{:?}", err);
-                    continue;
-                } else {
-                    info!("Synced: {}", dp.name.clone().unwrap_or("nameless".into()));
-                }
+                info!("Synced: {}", dp.name.as_deref().unwrap_or("nameless"));
+                reval.synced.push(name);
             }
             Err(err) => {
-                println!("{:?}", err);
-                continue;
+                warn!("Syncing '{}' failed: {:?}", name, err);
+                reval.failed.push(format!("{}: {}", name, err));
             }
         }
     }
 
-    Ok(SyncRe {})
+    let index = refresh_search_index(state).await;
+    info!("Indexed {} packages for the offline search", index.len());
+    reval.indexed = index.len();
+
+    Ok(reval)
 }
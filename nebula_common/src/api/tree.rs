@@ -0,0 +1,24 @@
+//! Functionality for showing the dependency graph of a package
+
+use color_eyre::eyre::Report;
+
+use crate::{
+    NebulaCliState,
+    model::resolver::{DependencyTree, parse_package_spec},
+};
+
+use super::install::resolve;
+
+pub struct TreeArgs {
+    /// name of the package, optionally with a version requirement as `name@requirement`
+    pub package: String,
+}
+
+/// Resolves the dependencies of the package against the local cache
+pub async fn dependency_tree(
+    args: TreeArgs,
+    state: &mut NebulaCliState,
+) -> Result<DependencyTree, Report> {
+    let (name, req) = parse_package_spec(&args.package)?;
+    Ok(resolve(state, &name, &req).await?.tree())
+}
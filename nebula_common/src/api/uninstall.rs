@@ -0,0 +1,127 @@
+//! Functionality for uninstalling packages
+
+use color_eyre::eyre::{Report, eyre};
+use tracing::warn;
+
+use crate::{
+    NebulaCliState,
+    model::resolver::{dependents, parse_package_spec},
+};
+
+use super::install::list_all;
+
+pub struct UninstallArgs {
+    /// name of the package, optionally with a version requirement as `name@requirement`
+    pub package: Option<String>,
+
+    /// uninstall every package, the package name is ignored
+    pub all: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct UninstallResult {
+    /// name and version of the removed packages
+    pub removed: Vec<(String, String)>,
+
+    /// installed packages that still depend on a removed package
+    pub dependents: Vec<String>,
+}
+
+/// Removes the matching installed versions, packages that depend on them are reported
+pub async fn uninstall_package(
+    args: UninstallArgs,
+    state: &mut NebulaCliState,
+) -> Result<UninstallResult, Report> {
+    let installed = state.installed()?;
+    let present = list_all(&installed).await;
+
+    let spec = match (args.all, args.package) {
+        (true, _) => None,
+        (false, Some(package)) => Some(parse_package_spec(&package)?),
+        (false, None) => return Err(eyre!("Either give a package name or use '--all'")),
+    };
+    let (remove, keep): (Vec<_>, Vec<_>) = present.iter().partition(|dp| match &spec {
+        None => true,
+        Some((name, req)) => {
+            dp.name.as_ref() == Some(name)
+                && dp
+                    .version
+                    .as_ref()
+                    .is_some_and(|v| semver::Version::parse(v).is_ok_and(|v| req.matches(&v)))
+        }
+    });
+    if let (Some((name, _)), true) = (&spec, remove.is_empty()) {
+        return Err(eyre!("Package '{}' is not installed", name));
+    }
+
+    let mut reval = UninstallResult { removed: vec![], dependents: vec![] };
+    for package in remove {
+        let (Some(name), Some(version)) = (package.name.clone(), package.version.clone()) else {
+            warn!("Skipping an installed package without name or version: {:?}", package.id);
+            continue;
+        };
+        // descriptors that are not stored in `<name>/<version>` are not removed
+        if !installed.remove_package_metadata(&name, &version)? {
+            warn!("Package '{}@{}' is not installed in its own folder, skipping it", name, version);
+            continue;
+        }
+        reval.dependents.extend(dependents(keep.iter().copied(), &name, &version));
+        reval.removed.push((name, version));
+    }
+    reval.dependents.sort();
+    reval.dependents.dedup();
+    Ok(reval)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        datapackage::{
+            DataPackageNotValidated, DataResourceNotValidated, PathSingleOrVec, ValidateData as _,
+        },
+        storage::MetaDataSource as _,
+    };
+
+    #[tokio::test]
+    async fn test_uninstall_all_skips_unknown_descriptors() {
+        let root = PathBuf::from("tmp_uninstall_all");
+        let _ = std::fs::remove_dir_all(&root);
+        let mut state = NebulaCliState::new(root.join("data"), root.join("config"));
+        state.init_data_source();
+        let mut installed = state.installed().unwrap();
+
+        let package = DataPackageNotValidated {
+            name: Some("iris".into()),
+            version: Some("1.0.0".into()),
+            id: Some("00000000-0000-0000-0000-000000000001".into()),
+            resources: vec![DataResourceNotValidated {
+                name: "data".into(),
+                path: Some(PathSingleOrVec::Single("data.csv".into())),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        installed.put_package_metadata(&package.clone().validate().unwrap()).await.unwrap();
+        // a descriptor without version and one outside of `<name>/<version>`
+        let versionless = DataPackageNotValidated { version: None, ..package.clone() };
+        let moved = DataPackageNotValidated { name: Some("moved".into()), ..package };
+        for (folder, package) in [("versionless", versionless), ("moved", moved)] {
+            let folder = installed.path().join(folder);
+            std::fs::create_dir_all(&folder).unwrap();
+            let json = serde_json::to_string(&package).unwrap();
+            std::fs::write(folder.join("datapackage.json"), json).unwrap();
+        }
+        installed.rescan();
+        assert_eq!(list_all(&installed).await.len(), 3);
+
+        let args = UninstallArgs { package: None, all: true };
+        let result = uninstall_package(args, &mut state).await.unwrap();
+        assert_eq!(result.removed, vec![("iris".to_string(), "1.0.0".to_string())]);
+        assert!(!installed.path().join("iris").exists());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
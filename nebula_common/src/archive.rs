@@ -0,0 +1,400 @@
+//! Extraction of resources from downloaded archives
+//!
+//! Resources with the origin `local-archive` are members of an archive resource of the same
+//! package. Only the declared members are extracted, the archive is read as a stream and every
+//! member is written to the target that the installer chose for it, never to the path stored in
+//! the archive. Links and special files are ignored, the size of the extracted data is limited to
+//! protect against archive bombs.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Write as _},
+    path::{Component, Path, PathBuf},
+};
+
+use thiserror::Error;
+
+/// Archive formats that can be extracted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+pub enum ArchiveFormat {
+    #[strum(serialize = "tar")]
+    Tar,
+
+    #[strum(serialize = "tar.gz")]
+    TarGz,
+
+    #[strum(serialize = "tar.zst")]
+    TarZst,
+
+    #[strum(serialize = "tar.xz")]
+    TarXz,
+
+    #[strum(serialize = "zip")]
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Detects the format from a resource format like `tar.gz` or from a file name
+    pub fn detect(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        // urls may carry a query after the file name
+        let name = name.split(['?', '#']).next().unwrap_or_default();
+        [
+            (".tar.gz", ArchiveFormat::TarGz),
+            (".tgz", ArchiveFormat::TarGz),
+            (".tar.zst", ArchiveFormat::TarZst),
+            (".tzst", ArchiveFormat::TarZst),
+            (".tar.xz", ArchiveFormat::TarXz),
+            (".txz", ArchiveFormat::TarXz),
+            (".tar", ArchiveFormat::Tar),
+            (".zip", ArchiveFormat::Zip),
+        ]
+        .into_iter()
+        .find(|(ext, _)| name == &ext[1..] || name.ends_with(ext))
+        .map(|(_, format)| format)
+    }
+}
+
+/// Bounds of an extraction, exceeding them aborts the extraction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractLimits {
+    /// bytes written for all members together
+    pub max_bytes: u64,
+
+    /// ratio of the uncompressed to the compressed size of a zip member
+    pub max_ratio: u64,
+
+    /// entries read from the archive, including the ones that are not extracted
+    pub max_entries: usize,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self { max_bytes: 64 * 1024 * 1024 * 1024, max_ratio: 1000, max_entries: 1_000_000 }
+    }
+}
+
+/// A member of an archive and where it is extracted to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveMember {
+    /// relative path of the member, archives with a single top level folder are matched as well,
+    /// e.g. `data_batch_1.bin` matches `cifar-10-batches-bin/data_batch_1.bin`
+    pub path: String,
+
+    pub target: PathBuf,
+}
+
+#[derive(Debug, Error)]
+pub enum ExtractError {
+    #[error("Reading the archive failed")]
+    Io(#[from] io::Error),
+
+    #[error("Reading the zip archive failed")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error("Member path '{0}' is not a relative path inside the archive")]
+    UnsafePath(String),
+
+    #[error("Extracting '{0}' exceeds the limit of {1} bytes")]
+    TooLarge(String, u64),
+
+    #[error("Member '{0}' has a suspicious compression ratio")]
+    Ratio(String),
+
+    #[error("The archive has more than {0} entries")]
+    TooManyEntries(usize),
+
+    #[error("Members not found in the archive: {}", .0.join(", "))]
+    Missing(Vec<String>),
+}
+
+/// Extracts the given members of an archive file, returns the number of bytes written
+pub fn extract_members(
+    archive: &Path,
+    format: ArchiveFormat,
+    members: &[ArchiveMember],
+    limits: &ExtractLimits,
+) -> Result<u64, ExtractError> {
+    if let Some(member) = members.iter().find(|m| normalize(&m.path).is_none()) {
+        return Err(ExtractError::UnsafePath(member.path.clone()));
+    }
+    let mut extraction =
+        Extraction { members, found: vec![false; members.len()], written: 0, limits };
+
+    let file = BufReader::new(File::open(archive)?);
+    match format {
+        ArchiveFormat::Tar => extraction.tar(file)?,
+        ArchiveFormat::TarGz => extraction.tar(flate2::read::GzDecoder::new(file))?,
+        ArchiveFormat::TarZst => extraction.tar(zstd::Decoder::with_buffer(file)?)?,
+        ArchiveFormat::TarXz => extraction.tar(liblzma::read::XzDecoder::new(file))?,
+        ArchiveFormat::Zip => extraction.zip(File::open(archive)?)?,
+    }
+
+    let missing: Vec<String> = members
+        .iter()
+        .zip(extraction.found.iter())
+        .filter(|(_, found)| !**found)
+        .map(|(member, _)| member.path.clone())
+        .collect();
+    if !missing.is_empty() {
+        return Err(ExtractError::Missing(missing));
+    }
+    Ok(extraction.written)
+}
+
+struct Extraction<'a> {
+    members: &'a [ArchiveMember],
+
+    found: Vec<bool>,
+
+    written: u64,
+
+    limits: &'a ExtractLimits,
+}
+
+impl Extraction<'_> {
+    fn tar<R: Read>(&mut self, reader: R) -> Result<(), ExtractError> {
+        let mut archive = tar::Archive::new(reader);
+        for (count, entry) in archive.entries()?.enumerate() {
+            if count >= self.limits.max_entries {
+                return Err(ExtractError::TooManyEntries(self.limits.max_entries));
+            }
+            let entry = entry?;
+            // links could point outside of the target folder
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry.path()?.to_string_lossy().into_owned();
+            if let Some(index) = self.member_index(&path) {
+                self.extract(index, entry)?;
+            }
+            if self.found.iter().all(|found| *found) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn zip(&mut self, file: File) -> Result<(), ExtractError> {
+        let mut archive = zip::ZipArchive::new(BufReader::new(file))?;
+        if archive.len() > self.limits.max_entries {
+            return Err(ExtractError::TooManyEntries(self.limits.max_entries));
+        }
+        for index in 0..archive.len() {
+            let entry = archive.by_index(index)?;
+            if !entry.is_file() || entry.is_symlink() {
+                continue;
+            }
+            let path = entry.name().to_string();
+            let Some(index) = self.member_index(&path) else {
+                continue;
+            };
+            // entries that are not extracted are never decompressed
+            if entry.size() > entry.compressed_size().max(1).saturating_mul(self.limits.max_ratio) {
+                return Err(ExtractError::Ratio(path));
+            }
+            self.extract(index, entry)?;
+            if self.found.iter().all(|found| *found) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// The member that an entry matches and that is not extracted yet
+    fn member_index(&self, path: &str) -> Option<usize> {
+        let path = normalize(path)?;
+        self.members.iter().enumerate().position(|(index, m)| {
+            !self.found[index] && normalize(&m.path).is_some_and(|m| matches(&path, &m))
+        })
+    }
+
+    /// Writes the entry to the target of the member
+    fn extract<R: Read>(&mut self, index: usize, entry: R) -> Result<(), ExtractError> {
+        let member = &self.members[index];
+        if let Some(parent) = member.target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // the size in the header may lie, the limit is checked on the decompressed data
+        let remaining = self.limits.max_bytes - self.written;
+        let mut file = File::create(&member.target)?;
+        let copied = io::copy(&mut entry.take(remaining.saturating_add(1)), &mut file)?;
+        file.flush()?;
+        if copied > remaining {
+            drop(file);
+            let _ = std::fs::remove_file(&member.target);
+            return Err(ExtractError::TooLarge(member.path.clone(), self.limits.max_bytes));
+        }
+        self.written += copied;
+        self.found[index] = true;
+        Ok(())
+    }
+}
+
+/// The normal components of a relative path, None if it leaves the archive root
+fn normalize(path: &str) -> Option<Vec<String>> {
+    let mut reval = vec![];
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => reval.push(part.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!reval.is_empty()).then_some(reval)
+}
+
+/// Whether an entry is the member itself or the member below a top level folder
+fn matches(entry: &[String], member: &[String]) -> bool {
+    entry == member || (entry.len() == member.len() + 1 && entry[1..] == *member)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tar_bytes(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        for (path, content) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            // bypasses the path checks of the builder to craft malicious entries
+            let name = &mut header.as_old_mut().name;
+            name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_cksum();
+            builder.append(&header, *content).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn members(folder: &Path, paths: &[&str]) -> Vec<ArchiveMember> {
+        paths
+            .iter()
+            .map(|path| ArchiveMember { path: path.to_string(), target: folder.join(path) })
+            .collect()
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(ArchiveFormat::detect("tar.gz"), Some(ArchiveFormat::TarGz));
+        assert_eq!(
+            ArchiveFormat::detect("bundle/archive.tar.gz?Expires=1"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(ArchiveFormat::detect("cifar.tar.zst"), Some(ArchiveFormat::TarZst));
+        assert_eq!(ArchiveFormat::detect("x.TXZ"), Some(ArchiveFormat::TarXz));
+        assert_eq!(ArchiveFormat::detect("zip"), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::detect("csv"), None);
+        assert_eq!(ArchiveFormat::detect("data.gz"), None);
+    }
+
+    #[test]
+    fn test_extract_tar_formats() {
+        let folder = PathBuf::from("tmp_extract_tar");
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        let tar = tar_bytes(&[
+            ("cifar/readme.txt", b"not declared"),
+            ("cifar/data_batch_1.bin", b"batch 1"),
+            ("./cifar/variables/v.index", b"index"),
+        ]);
+
+        let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::fast());
+        gz.write_all(&tar).unwrap();
+        let mut xz = liblzma::write::XzEncoder::new(vec![], 1);
+        xz.write_all(&tar).unwrap();
+        let archives = [
+            (ArchiveFormat::TarGz, gz.finish().unwrap()),
+            (ArchiveFormat::TarZst, zstd::encode_all(tar.as_slice(), 1).unwrap()),
+            (ArchiveFormat::TarXz, xz.finish().unwrap()),
+        ];
+        for (format, bytes) in archives {
+            let archive = folder.join(format!("archive.{}", format));
+            std::fs::write(&archive, bytes).unwrap();
+            let out = folder.join(format.to_string().replace('.', "_"));
+            let members = members(&out, &["data_batch_1.bin", "variables/v.index"]);
+
+            let written =
+                extract_members(&archive, format, &members, &ExtractLimits::default()).unwrap();
+            assert_eq!(written, 12);
+            assert_eq!(std::fs::read(out.join("data_batch_1.bin")).unwrap(), b"batch 1");
+            assert_eq!(std::fs::read(out.join("variables/v.index")).unwrap(), b"index");
+            assert!(!out.join("readme.txt").exists());
+        }
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn test_extract_rejects_malicious_archives() {
+        let folder = PathBuf::from("tmp_extract_malicious");
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        let archive = folder.join("archive.tar");
+        let limits = ExtractLimits::default();
+
+        // entries that leave the archive root are never matched
+        std::fs::write(&archive, tar_bytes(&[("../escape.txt", b"evil")])).unwrap();
+        let res = extract_members(
+            &archive,
+            ArchiveFormat::Tar,
+            &members(&folder, &["escape.txt"]),
+            &limits,
+        );
+        assert!(matches!(res, Err(ExtractError::Missing(_))));
+        assert!(!Path::new("escape.txt").exists());
+
+        // declared members must stay inside the package folder
+        let res =
+            extract_members(&archive, ArchiveFormat::Tar, &members(&folder, &["../x"]), &limits);
+        assert!(matches!(res, Err(ExtractError::UnsafePath(_))));
+
+        // the size limit is enforced on the extracted data
+        std::fs::write(&archive, tar_bytes(&[("big.bin", &[0; 2048])])).unwrap();
+        let small = ExtractLimits { max_bytes: 1024, ..limits };
+        let res =
+            extract_members(&archive, ArchiveFormat::Tar, &members(&folder, &["big.bin"]), &small);
+        assert!(matches!(res, Err(ExtractError::TooLarge(_, 1024))));
+        assert!(!folder.join("big.bin").exists());
+
+        // highly compressed zip members are refused before they are extracted
+        let zip_path = folder.join("bomb.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        zip.start_file("zeros.bin", options).unwrap();
+        zip.write_all(&vec![0; 1024 * 1024]).unwrap();
+        zip.start_file("ok.txt", options).unwrap();
+        zip.write_all(b"fine").unwrap();
+        zip.finish().unwrap();
+
+        let strict = ExtractLimits { max_ratio: 100, ..limits };
+        let res = extract_members(
+            &zip_path,
+            ArchiveFormat::Zip,
+            &members(&folder, &["zeros.bin"]),
+            &strict,
+        );
+        assert!(matches!(res, Err(ExtractError::Ratio(_))));
+        assert!(!folder.join("zeros.bin").exists());
+        let lax = ExtractLimits { max_ratio: 10_000, ..limits };
+        extract_members(&zip_path, ArchiveFormat::Zip, &members(&folder, &["zeros.bin"]), &lax)
+            .unwrap();
+        assert_eq!(std::fs::metadata(folder.join("zeros.bin")).unwrap().len(), 1024 * 1024);
+
+        // undeclared entries are skipped without a ratio check
+        extract_members(&zip_path, ArchiveFormat::Zip, &members(&folder, &["ok.txt"]), &strict)
+            .unwrap();
+        assert_eq!(std::fs::read(folder.join("ok.txt")).unwrap(), b"fine");
+
+        // the unlimited size does not overflow
+        let unlimited = ExtractLimits { max_bytes: u64::MAX, ..limits };
+        std::fs::write(&archive, tar_bytes(&[("big.bin", &[0; 2048])])).unwrap();
+        extract_members(&archive, ArchiveFormat::Tar, &members(&folder, &["big.bin"]), &unlimited)
+            .unwrap();
+        assert_eq!(std::fs::metadata(folder.join("big.bin")).unwrap().len(), 2048);
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,504 @@
+//! Downloads of package resources
+//!
+//! The [DownloadManager] downloads several files concurrently and resumes partial downloads with
+//! http range requests. Data is written to `<target>.part` and renamed once complete, so a
+//! cancelled download continues where it stopped on the next attempt. Progress is reported as
+//! [DownloadEvent] through a channel, the bandwidth of all downloads together can be limited.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::{Report, eyre};
+use futures::StreamExt as _;
+use reqwest::{StatusCode, header};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt as _,
+    sync::mpsc::UnboundedSender,
+};
+use tracing::warn;
+
+use crate::configuration::cli::DownloadSettings;
+
+/// A file to download
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadRequest {
+    /// identifies the download in the events, e.g. the resource name
+    pub name: String,
+
+    pub url: String,
+
+    pub target: PathBuf,
+
+    /// expected size in bytes, used for progress if the server does not tell
+    pub size: Option<u64>,
+}
+
+/// Progress of a running download
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadProgress {
+    pub name: String,
+
+    /// bytes on disk including the bytes of previous attempts
+    pub downloaded: u64,
+
+    pub total: Option<u64>,
+
+    /// bytes per second of this attempt
+    pub rate: f64,
+
+    /// estimated time until completion, None if total or rate are unknown
+    pub eta: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadEvent {
+    /// a download starts, `resumed` bytes are on disk from a previous attempt
+    Started {
+        name: String,
+        total: Option<u64>,
+        resumed: u64,
+    },
+
+    Progress(DownloadProgress),
+
+    /// the file is complete, `bytes` is its size
+    Finished {
+        name: String,
+        bytes: u64,
+    },
+
+    Failed {
+        name: String,
+        error: String,
+    },
+}
+
+/// Shares a bandwidth between concurrent downloads
+///
+/// Every chunk reserves its transfer time on a virtual clock, a download waits until the clock
+/// reaches its reservation. Idle time is not saved up, so bursts are bounded by one chunk.
+#[derive(Debug)]
+pub struct BandwidthLimiter {
+    bytes_per_sec: u64,
+
+    next: Mutex<Instant>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        BandwidthLimiter { bytes_per_sec: bytes_per_sec.max(1), next: Mutex::new(Instant::now()) }
+    }
+
+    /// Waits until the given number of bytes may be transferred
+    pub async fn acquire(&self, bytes: u64) {
+        let wait = {
+            let mut next = self.next.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let now = Instant::now();
+            let start = (*next).max(now);
+            *next = start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+            start - now
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Downloads files concurrently, see the module documentation
+#[derive(Debug)]
+pub struct DownloadManager {
+    client: reqwest::Client,
+
+    settings: DownloadSettings,
+
+    limiter: Option<BandwidthLimiter>,
+
+    events: Option<UnboundedSender<DownloadEvent>>,
+}
+
+impl DownloadManager {
+    pub fn new(settings: DownloadSettings) -> Result<Self, Report> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(settings.connect_timeout_secs))
+            // applies to every read, a large download takes as long as data keeps arriving
+            .read_timeout(Duration::from_secs(settings.read_timeout_secs))
+            .user_agent(concat!("nebula-cli/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        let limiter = settings.bandwidth_limit.map(BandwidthLimiter::new);
+        Ok(DownloadManager { client, settings, limiter, events: None })
+    }
+
+    /// Reports the progress of all downloads to the given channel
+    pub fn with_events(mut self, events: UnboundedSender<DownloadEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Downloads all files with the configured concurrency, the results keep the order
+    pub async fn download_all(&self, requests: &[DownloadRequest]) -> Vec<Result<u64, Report>> {
+        let mut results: Vec<(usize, Result<u64, Report>)> =
+            futures::stream::iter(requests.iter().enumerate())
+                .map(|(i, request)| async move { (i, self.download(request).await) })
+                .buffer_unordered(self.settings.concurrency.max(1))
+                .collect()
+                .await;
+        results.sort_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Downloads one file and resumes a partial download, returns the size of the file
+    pub async fn download(&self, request: &DownloadRequest) -> Result<u64, Report> {
+        let result = self.try_download(request).await;
+        match &result {
+            Ok(bytes) => {
+                self.emit(DownloadEvent::Finished { name: request.name.clone(), bytes: *bytes })
+            }
+            Err(err) => self
+                .emit(DownloadEvent::Failed { name: request.name.clone(), error: err.to_string() }),
+        }
+        result
+    }
+
+    async fn try_download(&self, request: &DownloadRequest) -> Result<u64, Report> {
+        if let Some(parent) = request.target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        if let Ok(meta) = tokio::fs::metadata(&request.target).await {
+            if request.size.is_none_or(|size| size == meta.len()) {
+                return Ok(meta.len());
+            }
+        }
+
+        let part = part_path(&request.target);
+        let mut resumed = tokio::fs::metadata(&part).await.map(|meta| meta.len()).unwrap_or(0);
+        let response = loop {
+            let mut builder = self.client.get(&request.url);
+            if resumed > 0 {
+                builder = builder.header(header::RANGE, format!("bytes={}-", resumed));
+            }
+            let response = builder.send().await?;
+            if response.status() == StatusCode::RANGE_NOT_SATISFIABLE
+                && resumed > 0
+                && request.size.or_else(|| unsatisfied_size(&response)) != Some(resumed)
+            {
+                // the partial file is longer than the resource, e.g. it changed in the meantime
+                warn!("Partial download of '{}' is stale, restarting", request.url);
+                tokio::fs::remove_file(&part).await?;
+                resumed = 0;
+                continue;
+            }
+            if response.status() == StatusCode::PARTIAL_CONTENT
+                && resumed > 0
+                && range_start(&response) != Some(resumed)
+            {
+                // the data does not continue the partial file, start from the beginning
+                warn!("Download of '{}' returned an unexpected range, restarting", request.url);
+                resumed = 0;
+                continue;
+            }
+            break response;
+        };
+
+        let status = response.status();
+        let mut file = match status {
+            StatusCode::PARTIAL_CONTENT if resumed > 0 => {
+                OpenOptions::new().append(true).open(&part).await?
+            }
+            StatusCode::RANGE_NOT_SATISFIABLE if resumed > 0 => {
+                // the partial file is complete, only the rename is missing
+                tokio::fs::rename(&part, &request.target).await?;
+                return Ok(resumed);
+            }
+            status if status.is_success() => {
+                // the server ignored the range, start from the beginning
+                resumed = 0;
+                File::create(&part).await?
+            }
+            status => return Err(eyre!("Download of '{}' failed with {}", request.url, status)),
+        };
+        let total = response.content_length().map(|len| len + resumed).or(request.size);
+        self.emit(DownloadEvent::Started { name: request.name.clone(), total, resumed });
+
+        let started = Instant::now();
+        let interval = Duration::from_millis(self.settings.progress_interval_ms);
+        let mut reported = started;
+        let mut downloaded = resumed;
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            if let Some(limiter) = self.limiter.as_ref() {
+                limiter.acquire(chunk.len() as u64).await;
+            }
+            file.write_all(&chunk).await?;
+            downloaded += chunk.len() as u64;
+
+            if reported.elapsed() >= interval {
+                reported = Instant::now();
+                self.emit(DownloadEvent::Progress(progress(
+                    &request.name,
+                    downloaded,
+                    total,
+                    downloaded - resumed,
+                    started.elapsed(),
+                )));
+            }
+        }
+        file.flush().await?;
+        drop(file);
+
+        if let Some(total) = total.filter(|total| *total != downloaded) {
+            return Err(eyre!(
+                "Download of '{}' incomplete, {} of {} bytes",
+                request.url,
+                downloaded,
+                total
+            ));
+        }
+        tokio::fs::rename(&part, &request.target).await?;
+        Ok(downloaded)
+    }
+
+    fn emit(&self, event: DownloadEvent) {
+        if let Some(events) = self.events.as_ref() {
+            // a consumer that stopped listening must not abort the downloads
+            let _ = events.send(event);
+        }
+    }
+}
+
+/// The first byte of a partial response according to its `Content-Range: bytes <start>-<end>/<size>`
+fn range_start(response: &reqwest::Response) -> Option<u64> {
+    let range = response.headers().get(header::CONTENT_RANGE)?.to_str().ok()?;
+    range.strip_prefix("bytes ")?.split_once('-')?.0.trim().parse().ok()
+}
+
+/// The size of the resource according to the `Content-Range: bytes */<size>` of a 416 response
+fn unsatisfied_size(response: &reqwest::Response) -> Option<u64> {
+    let range = response.headers().get(header::CONTENT_RANGE)?.to_str().ok()?;
+    range.strip_prefix("bytes */")?.trim().parse().ok()
+}
+
+fn progress(
+    name: &str,
+    downloaded: u64,
+    total: Option<u64>,
+    transferred: u64,
+    elapsed: Duration,
+) -> DownloadProgress {
+    let rate = transferred as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+    let eta = total
+        .filter(|_| rate > 0.0)
+        .map(|total| Duration::from_secs_f64(total.saturating_sub(downloaded) as f64 / rate));
+    DownloadProgress { name: name.into(), downloaded, total, rate, eta }
+}
+
+/// The file that receives the data until the download is complete
+pub fn part_path(target: &Path) -> PathBuf {
+    let mut name = target.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    target.with_file_name(name)
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    use super::*;
+
+    /// Serves the content to every request and supports open ended range requests
+    ///
+    /// A range is answered from `shift` bytes before the requested start, like a server that
+    /// misinterprets the request. A range behind the end is not satisfiable.
+    async fn serve(content: &'static [u8], shift: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let len = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..len]).to_lowercase();
+                let start = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("range: bytes="))
+                    .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok())
+                    .map(|start| start.saturating_sub(shift));
+                let head = match start {
+                    Some(start) if start >= content.len() => format!(
+                        "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Length: 0\r\nContent-Range: bytes */{}\r\n\r\n",
+                        content.len()
+                    ),
+                    Some(start) => format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                        content.len() - start,
+                        start,
+                        content.len() - 1,
+                        content.len()
+                    ),
+                    None => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", content.len()),
+                };
+                socket.write_all(head.as_bytes()).await.unwrap();
+                let start = start.unwrap_or(0).min(content.len());
+                socket.write_all(&content[start..]).await.unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_download_and_resume() {
+        static CONTENT: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+        let url = serve(CONTENT, 0).await;
+        let folder = PathBuf::from("tmp_download");
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+
+        let request = |name: &str| DownloadRequest {
+            name: name.into(),
+            url: format!("{}/{}", url, name),
+            target: folder.join(name),
+            size: Some(CONTENT.len() as u64),
+        };
+        // a previous attempt stopped after 10 bytes
+        std::fs::write(part_path(&folder.join("resumed")), &CONTENT[..10]).unwrap();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let settings = DownloadSettings { concurrency: 2, ..Default::default() };
+        let manager = DownloadManager::new(settings).unwrap().with_events(tx);
+        let results = manager.download_all(&[request("fresh"), request("resumed")]).await;
+        assert!(results.iter().all(|res| res.as_ref().is_ok_and(|bytes| *bytes == 36)));
+        assert_eq!(std::fs::read(folder.join("fresh")).unwrap(), CONTENT);
+        assert_eq!(std::fs::read(folder.join("resumed")).unwrap(), CONTENT);
+        assert!(!part_path(&folder.join("resumed")).exists());
+
+        drop(manager);
+        let mut events = vec![];
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        assert!(events.contains(&DownloadEvent::Started {
+            name: "resumed".into(),
+            total: Some(36),
+            resumed: 10
+        }));
+        assert_eq!(
+            events.iter().filter(|e| matches!(e, DownloadEvent::Finished { .. })).count(),
+            2
+        );
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restart_on_range_mismatch() {
+        static CONTENT: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+        let url = serve(CONTENT, 4).await;
+        let folder = PathBuf::from("tmp_download_mismatch");
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(part_path(&folder.join("data")), b"xxxxxxxxxx").unwrap();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let manager = DownloadManager::new(DownloadSettings::default()).unwrap().with_events(tx);
+        let request = DownloadRequest {
+            name: "data".into(),
+            url: format!("{}/data", url),
+            target: folder.join("data"),
+            size: Some(CONTENT.len() as u64),
+        };
+        assert_eq!(manager.download(&request).await.unwrap(), 36);
+        assert_eq!(std::fs::read(folder.join("data")).unwrap(), CONTENT);
+
+        drop(manager);
+        let mut events = vec![];
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        assert!(events.contains(&DownloadEvent::Started {
+            name: "data".into(),
+            total: Some(36),
+            resumed: 0
+        }));
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unsatisfiable_range() {
+        static CONTENT: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+        let url = serve(CONTENT, 0).await;
+        let folder = PathBuf::from("tmp_download_unsatisfiable");
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        // the size is unknown, the server reports it with the 416
+        let request = |name: &str| DownloadRequest {
+            name: name.into(),
+            url: format!("{}/{}", url, name),
+            target: folder.join(name),
+            size: None,
+        };
+        std::fs::write(part_path(&folder.join("complete")), CONTENT).unwrap();
+        std::fs::write(part_path(&folder.join("stale")), [CONTENT, b"0123"].concat()).unwrap();
+
+        let manager = DownloadManager::new(DownloadSettings::default()).unwrap();
+        assert_eq!(manager.download(&request("complete")).await.unwrap(), 36);
+        assert_eq!(manager.download(&request("stale")).await.unwrap(), 36);
+        for name in ["complete", "stale"] {
+            assert_eq!(std::fs::read(folder.join(name)).unwrap(), CONTENT);
+            assert!(!part_path(&folder.join(name)).exists());
+        }
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/data", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            let head = "HTTP/1.1 200 OK\r\nContent-Length: 36\r\n\r\n0123456789";
+            socket.write_all(head.as_bytes()).await.unwrap();
+            // the connection stalls without being closed
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        });
+        let folder = PathBuf::from("tmp_download_timeout");
+        let _ = std::fs::remove_dir_all(&folder);
+
+        let settings = DownloadSettings { read_timeout_secs: 1, ..Default::default() };
+        let manager = DownloadManager::new(settings).unwrap();
+        let request =
+            DownloadRequest { name: "data".into(), url, target: folder.join("data"), size: None };
+        let started = Instant::now();
+        assert!(manager.download(&request).await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(std::fs::read(part_path(&folder.join("data"))).unwrap(), b"0123456789");
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_limit() {
+        let limiter = BandwidthLimiter::new(1000);
+        let start = Instant::now();
+        // the first chunk passes immediately, the next ones wait 100ms each
+        for _ in 0..3 {
+            limiter.acquire(100).await;
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(190), "{:?}", elapsed);
+
+        let eta = progress("x", 50, Some(150), 50, Duration::from_secs(1)).eta;
+        assert_eq!(eta, Some(Duration::from_secs(2)));
+    }
+}
@@ -1,66 +1,359 @@
 //! Client calls to nebula-registry endpoints
+//!
+//! The connection is configured by [ConnectionSettings], idempotent calls are retried with an
+//! exponential backoff. A lost connection is re-established by the underlying [Channel] on the
+//! next request, which is covered by the retries.
 
-use color_eyre::eyre::Report;
-use tonic::Request;
-use tonic::transport::Channel;
+pub mod download;
 
+use std::time::Duration;
+
+use color_eyre::eyre::{Report, eyre};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Status};
+use tracing::warn;
+
+use crate::PROTOCOL_VERSIONS;
+use crate::configuration::cli::ConnectionSettings;
 use crate::registry::{FieldOptions, ListPackagesRequest, PackageType};
 
 use super::nebula_proto::nebula_package_query_client::NebulaPackageQueryClient;
-use super::nebula_proto::{PackageInfo, PackageList, PackageRequest, SearchPackagesRequest};
+use super::nebula_proto::{
+    PackageInfo, PackageList, PackageRequest, SearchPackagesRequest, ServerInfo, ServerInfoRequest,
+};
+
+/// Retry policy with exponential backoff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub retries: u32,
+
+    pub initial_backoff: Duration,
+
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// The delay before the given retry, starting with 0 for the first retry
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+
+    /// Calls ftor until it succeeds, fails with a non transient error or the retries are exhausted
+    ///
+    /// Every attempt gets its own future that owns its data, such that the retry is `Send` if the
+    /// futures are, e.g. with a clone of the tonic client per attempt.
+    pub async fn retry<T, F, Fut>(&self, mut ftor: F) -> Result<T, Status>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Status>>,
+    {
+        let mut retry = 0;
+        loop {
+            match ftor().await {
+                Err(status) if retry < self.retries && is_transient(&status) => {
+                    let delay = self.backoff(retry);
+                    warn!("Request failed with '{}', retrying in {:?}", status.message(), delay);
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                }
+                reval => return reval,
+            }
+        }
+    }
+}
+
+impl From<&ConnectionSettings> for RetryPolicy {
+    fn from(value: &ConnectionSettings) -> Self {
+        RetryPolicy {
+            retries: value.retries,
+            initial_backoff: Duration::from_millis(value.backoff_initial_ms),
+            max_backoff: Duration::from_millis(value.backoff_max_ms),
+        }
+    }
+}
+
+/// Transient errors may succeed if the request is repeated
+fn is_transient(status: &Status) -> bool {
+    matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded | Code::ResourceExhausted)
+}
+
+/// Whether a request failed because the registry is not reachable, unlike errors it answered with
+pub fn is_unreachable(report: &Report) -> bool {
+    report.chain().any(|cause| {
+        cause.is::<tonic::transport::Error>()
+            || cause.downcast_ref::<Status>().is_some_and(|status| {
+                matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded)
+            })
+    })
+}
+
+/// Picks the newest protocol version that both sides speak
+pub fn negotiate_protocol(ours: &[&str], theirs: &[String]) -> Option<String> {
+    ours.iter().find(|version| theirs.iter().any(|el| el == *version)).map(|v| v.to_string())
+}
+
+/// A client of a nebula registry that retries idempotent requests
+#[derive(Debug, Clone)]
+pub struct RegistryClient {
+    query: NebulaPackageQueryClient<Channel>,
+
+    retry: RetryPolicy,
+
+    server_info: Option<ServerInfo>,
+
+    protocol: String,
+}
+
+impl RegistryClient {
+    pub fn new(channel: Channel, retry: RetryPolicy) -> Self {
+        RegistryClient {
+            query: NebulaPackageQueryClient::new(channel),
+            retry,
+            server_info: None,
+            protocol: PROTOCOL_VERSIONS[0].to_string(),
+        }
+    }
+
+    /// Information about the registry, None if the registry is too old to provide it
+    pub fn server_info(&self) -> Option<&ServerInfo> {
+        self.server_info.as_ref()
+    }
+
+    /// The protocol version that has been negotiated with the registry
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    /// Asks the registry for its protocol versions and fails if there is no common version
+    async fn negotiate(&mut self) -> Result<(), Report> {
+        let request = ServerInfoRequest {
+            protocol_versions: PROTOCOL_VERSIONS.iter().map(|v| v.to_string()).collect(),
+        };
+        let query = &self.query;
+        let response = self
+            .retry
+            .retry(|| {
+                let (mut query, request) = (query.clone(), Request::new(request.clone()));
+                async move { query.get_server_info(request).await }
+            })
+            .await;
+
+        match response {
+            Ok(response) => {
+                let info = response.into_inner();
+                self.protocol = negotiate_protocol(PROTOCOL_VERSIONS, &info.protocol_versions)
+                    .ok_or_else(|| {
+                        eyre!(
+                            "Registry '{}' speaks the protocol versions [{}], but only [{}] are supported",
+                            info.name,
+                            info.protocol_versions.join(", "),
+                            PROTOCOL_VERSIONS.join(", ")
+                        )
+                    })?;
+                self.server_info = Some(info);
+                Ok(())
+            }
+            Err(status) if status.code() == Code::Unimplemented => {
+                // registries that predate the server info speak the first version
+                warn!("Registry provides no server info, assuming protocol v1");
+                self.protocol = "v1".to_string();
+                Ok(())
+            }
+            Err(status) => Err(status.into()),
+        }
+    }
+}
 
 pub async fn init_client(
     host: &str,
     port: u16,
-) -> Result<NebulaPackageQueryClient<Channel>, Report> {
-    let client = NebulaPackageQueryClient::connect(format!("http://{}:{}", host, port)).await?;
+    settings: &ConnectionSettings,
+) -> Result<RegistryClient, Report> {
+    let endpoint = Endpoint::from_shared(format!("http://{}:{}", host, port))?
+        .connect_timeout(Duration::from_secs(settings.connect_timeout_secs))
+        .timeout(Duration::from_secs(settings.request_timeout_secs))
+        .http2_keep_alive_interval(Duration::from_secs(settings.keepalive_interval_secs))
+        .keep_alive_timeout(Duration::from_secs(settings.keepalive_timeout_secs))
+        .keep_alive_while_idle(true);
+
+    let retry = RetryPolicy::from(settings);
+    let mut attempt = 0;
+    let channel = loop {
+        match endpoint.connect().await {
+            Ok(channel) => break channel,
+            Err(err) if attempt < retry.retries => {
+                let delay = retry.backoff(attempt);
+                warn!("Connecting to registry failed with '{}', retrying in {:?}", err, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    };
+
+    let mut client = RegistryClient::new(channel, retry);
+    client.negotiate().await?;
     Ok(client)
 }
 
 pub async fn list_packages(
     field_options: Option<FieldOptions>,
-    client: &mut NebulaPackageQueryClient<Channel>,
+    client: &mut RegistryClient,
+) -> Result<PackageList, Report> {
+    list_packages_page(field_options, 30, 0, client).await
+}
+
+/// The number of packages requested per page by [list_all_packages]
+const PAGE_SIZE: i32 = 100;
+
+/// Lists all packages of the registry by requesting one page after another
+pub async fn list_all_packages(
+    field_options: Option<FieldOptions>,
+    client: &mut RegistryClient,
+) -> Result<Vec<PackageInfo>, Report> {
+    let mut reval = vec![];
+    loop {
+        let page = list_packages_page(field_options, PAGE_SIZE, reval.len() as i32, client).await?;
+        let len = page.packages.len();
+        reval.extend(page.packages);
+        if len < PAGE_SIZE as usize {
+            return Ok(reval);
+        }
+    }
+}
+
+async fn list_packages_page(
+    field_options: Option<FieldOptions>,
+    limit: i32,
+    offset: i32,
+    client: &mut RegistryClient,
 ) -> Result<PackageList, Report> {
-    let request = Request::new(ListPackagesRequest {
+    let request = ListPackagesRequest {
         field_options,
         package_type: PackageType::Both as i32,
         sort: None,
-        limit: Some(30),
-        offset: None,
-    });
-    let response = client.list_packages(request).await?;
+        limit: Some(limit),
+        offset: Some(offset),
+        download_range: None,
+    };
+    let query = &client.query;
+    let response = client
+        .retry
+        .retry(|| {
+            let (mut query, request) = (query.clone(), Request::new(request.clone()));
+            async move { query.list_packages(request).await }
+        })
+        .await?;
     Ok(response.into_inner())
 }
 
 pub async fn get_package_info(
-    client: &mut NebulaPackageQueryClient<Channel>,
+    client: &mut RegistryClient,
     name: String,
 ) -> Result<PackageInfo, Report> {
-    let request = Request::new(PackageRequest { search_query: name, package_type: None });
-    let response = client.get_package_info(request).await?;
+    let request = PackageRequest { search_query: name, package_type: None };
+    let query = &client.query;
+    let response = client
+        .retry
+        .retry(|| {
+            let (mut query, request) = (query.clone(), Request::new(request.clone()));
+            async move { query.get_package_info(request).await }
+        })
+        .await?;
 
     Ok(response.into_inner())
 }
 
 pub async fn search_packages(
-    client: &mut NebulaPackageQueryClient<Channel>,
-    query: String,
+    client: &mut RegistryClient,
+    request: SearchPackagesRequest,
 ) -> Result<PackageList, Report> {
-    let request = Request::new(SearchPackagesRequest {
-        field_options: None,
-        search_query: query,
-        package_type: PackageType::Both as i32,
-        sort: vec![],
-        limit: None,
-        offset: None,
-        created_date: None,
-        updated_date: None,
-        kind: None,
-        authors: vec![],
-        min_downloads: None,
-        max_downloads: None,
-    });
-    let response = client.search_packages(request).await?;
+    let query = &client.query;
+    let response = client
+        .retry
+        .retry(|| {
+            let (mut query, request) = (query.clone(), Request::new(request.clone()));
+            async move { query.search_packages(request).await }
+        })
+        .await?;
 
     Ok(response.into_inner())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            retries: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(3),
+        }
+    }
+
+    #[test]
+    fn test_negotiate_protocol() {
+        let theirs = vec!["v2".to_string(), "v1".to_string()];
+        assert_eq!(negotiate_protocol(&["v1"], &theirs), Some("v1".to_string()));
+        assert_eq!(negotiate_protocol(&["v2", "v1"], &theirs), Some("v2".to_string()));
+        assert_eq!(negotiate_protocol(&["v1"], &["v3".to_string()]), None);
+    }
+
+    #[test]
+    fn test_is_unreachable() {
+        assert!(is_unreachable(&Report::new(Status::unavailable("down"))));
+        assert!(is_unreachable(&Report::new(Status::deadline_exceeded("slow")).wrap_err("search")));
+        assert!(!is_unreachable(&Report::new(Status::invalid_argument("kind"))));
+        assert!(!is_unreachable(&eyre!("other")));
+    }
+
+    #[test]
+    fn test_backoff_is_exponential_and_bounded() {
+        let policy = policy();
+        assert_eq!(policy.backoff(0), Duration::from_millis(1));
+        assert_eq!(policy.backoff(1), Duration::from_millis(2));
+        assert_eq!(policy.backoff(2), Duration::from_millis(3));
+        assert_eq!(policy.backoff(40), Duration::from_millis(3));
+    }
+
+    #[tokio::test]
+    async fn test_retry_transient_errors() {
+        let mut calls = 0;
+        let reval = policy()
+            .retry(|| {
+                calls += 1;
+                let reval = if calls < 3 { Err(Status::unavailable("down")) } else { Ok(calls) };
+                async move { reval }
+            })
+            .await;
+        assert_eq!(reval.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_on_permanent_errors() {
+        let mut calls = 0;
+        let reval: Result<(), Status> = policy()
+            .retry(|| {
+                calls += 1;
+                async { Err(Status::not_found("missing")) }
+            })
+            .await;
+        assert_eq!(reval.unwrap_err().code(), Code::NotFound);
+        assert_eq!(calls, 1);
+    }
+
+    #[tokio::test]
+    async fn test_retries_exhausted() {
+        let mut calls = 0;
+        let reval: Result<(), Status> = policy()
+            .retry(|| {
+                calls += 1;
+                async { Err(Status::deadline_exceeded("slow")) }
+            })
+            .await;
+        assert!(reval.is_err());
+        assert_eq!(calls, 4);
+    }
+}
@@ -1,4 +1,8 @@
-use std::{fs::exists, path::PathBuf, str::FromStr};
+use std::{
+    fs::exists,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 #[derive(Debug, Copy, Clone, PartialEq, strum::EnumString, strum::Display)]
 pub enum Environment {
@@ -12,21 +16,105 @@ pub enum Environment {
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct Settings {
     pub remote_registry: RegistrySettings,
+
+    #[serde(default)]
+    pub download: DownloadSettings,
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct RegistrySettings {
     pub port: u16,
     pub host: String,
+
+    /// base url of the REST/JSON gateway that serves resources hosted by the registry
+    pub gateway_url: Option<String>,
+
+    #[serde(default)]
+    pub connection: ConnectionSettings,
+}
+
+/// Timeouts, retries and keepalive of the connection to the remote registry
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(default)]
+pub struct ConnectionSettings {
+    /// seconds to wait until a connection is established
+    pub connect_timeout_secs: u64,
+
+    /// seconds to wait for the response of a single request
+    pub request_timeout_secs: u64,
+
+    /// number of retries for idempotent requests and for connecting
+    pub retries: u32,
+
+    /// delay before the first retry in milliseconds, doubled for every further retry
+    pub backoff_initial_ms: u64,
+
+    /// upper bound of the delay between two retries in milliseconds
+    pub backoff_max_ms: u64,
+
+    /// seconds between two HTTP/2 keepalive pings
+    pub keepalive_interval_secs: u64,
+
+    /// seconds to wait for the acknowledgement of a keepalive ping before the connection is closed
+    pub keepalive_timeout_secs: u64,
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: 5,
+            request_timeout_secs: 30,
+            retries: 3,
+            backoff_initial_ms: 250,
+            backoff_max_ms: 4000,
+            keepalive_interval_secs: 30,
+            keepalive_timeout_secs: 10,
+        }
+    }
+}
+
+/// Concurrency and bandwidth of resource downloads
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(default)]
+pub struct DownloadSettings {
+    /// number of resources that are downloaded at the same time
+    pub concurrency: usize,
+
+    /// upper bound of the bandwidth of all downloads together in bytes per second
+    pub bandwidth_limit: Option<u64>,
+
+    /// milliseconds between two progress reports of a download
+    pub progress_interval_ms: u64,
+
+    /// seconds to wait for a connection to the server of a resource
+    pub connect_timeout_secs: u64,
+
+    /// seconds without any data after which a download fails, e.g. on a stalled connection
+    pub read_timeout_secs: u64,
+}
+
+impl Default for DownloadSettings {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            bandwidth_limit: None,
+            progress_interval_ms: 200,
+            connect_timeout_secs: 10,
+            read_timeout_secs: 30,
+        }
+    }
 }
 
-fn check_candidates(base_path: &PathBuf, options: impl IntoIterator<Item: AsRef<str>>) -> Option<PathBuf> {
+fn check_candidates(
+    base_path: &Path,
+    options: impl IntoIterator<Item: AsRef<str>>,
+) -> Option<PathBuf> {
     for candidate in options {
         let path_candidate = base_path.join(candidate.as_ref());
         if exists(&path_candidate).unwrap_or(false)
             && exists(path_candidate.join("base.yaml")).unwrap_or(false)
         {
-            return Some(path_candidate)
+            return Some(path_candidate);
         }
     }
 
@@ -39,8 +127,11 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     // cargo run may be invoked from the workspace, so we have a candidate for the configuration path.
     let options = ["configuration", "nebula_cli/configuration"];
     let candidate = check_candidates(&base_path, options);
-    if candidate.is_none()  {
-        return Err(config::ConfigError::NotFound(format!("with base path: {}", base_path.to_str().unwrap_or("/?"))));
+    if candidate.is_none() {
+        return Err(config::ConfigError::NotFound(format!(
+            "with base path: {}",
+            base_path.to_str().unwrap_or("/?")
+        )));
     }
     //~
 
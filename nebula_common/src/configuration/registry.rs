@@ -2,6 +2,11 @@ use std::str::FromStr;
 
 use serde::Deserialize;
 
+use crate::{
+    configuration::cli::ConnectionSettings,
+    storage::blob::{BlobStoreSettings, mirror::MirrorSettings},
+};
+
 #[derive(Debug, Copy, Clone, PartialEq, strum::EnumString, strum::Display)]
 pub enum Environment {
     #[strum(ascii_case_insensitive)]
@@ -15,6 +20,11 @@ pub enum Environment {
 pub struct Settings {
     pub application: ApplicationSettings,
     pub root_folder: Option<RootFolder>,
+    pub gateway: Option<GatewaySettings>,
+    pub blob_store: Option<BlobStore>,
+    pub mirror: Option<MirrorSettings>,
+    pub replication: Option<ReplicationSettings>,
+    pub statistics: Option<StatisticsSettings>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -23,11 +33,116 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String,
+
+    /// name of the registry that is reported to clients
+    #[serde(default = "default_registry_name")]
+    pub name: String,
+}
+
+fn default_registry_name() -> String {
+    "nebula-registry".to_string()
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct RootFolder {
     pub path: String,
+
+    /// reload datapackages when they change on disk
+    #[serde(default = "default_watch")]
+    pub watch: bool,
+
+    /// interval for periodic rescans, used as fallback if watching is not possible
+    pub rescan_interval_secs: Option<u64>,
+}
+
+fn default_watch() -> bool {
+    true
+}
+
+/// Settings of the REST/JSON gateway, the gateway is disabled if not given
+#[derive(Debug, Clone, Deserialize)]
+pub struct GatewaySettings {
+    pub port: u16,
+}
+
+/// Settings of the download statistics, downloads are not counted if not given
+#[derive(Debug, Clone, Deserialize)]
+pub struct StatisticsSettings {
+    /// log file of the download events
+    pub path: String,
+}
+
+/// Settings of the blob store for registry-hosted resources, blobs are not supported if not given
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlobStore {
+    #[serde(flatten)]
+    pub backend: BlobStoreSettings,
+
+    /// interval of the garbage collection, no automatic collection if not given
+    pub gc_interval_secs: Option<u64>,
+
+    /// unreferenced blobs younger than this are kept, they may belong to an ongoing import
+    #[serde(default = "default_gc_min_age_secs")]
+    pub gc_min_age_secs: u64,
+}
+
+fn default_gc_min_age_secs() -> u64 {
+    60 * 60
+}
+
+/// Settings to replicate the packages of an upstream registry, the registry serves only its own
+/// packages if not given
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReplicationSettings {
+    /// gRPC address of the upstream registry
+    pub host: String,
+    pub port: u16,
+
+    #[serde(default)]
+    pub connection: ConnectionSettings,
+
+    /// base url of the REST/JSON gateway of the upstream registry, used for resources hosted upstream
+    pub gateway_url: Option<String>,
+
+    /// folder that stores the replicated descriptors, separated from the root folder
+    pub path: String,
+
+    #[serde(default = "default_replication_interval_secs")]
+    pub interval_secs: u64,
+
+    /// copy resources hosted upstream into the blob store, otherwise clients are redirected upstream
+    #[serde(default)]
+    pub blobs: bool,
+
+    #[serde(default)]
+    pub conflict: ConflictPolicy,
+}
+
+fn default_replication_interval_secs() -> u64 {
+    5 * 60
+}
+
+/// Decides which package is served if a local and a replicated package have the same name
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Deserialize,
+    strum::EnumString,
+    strum::Display
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// the local package shadows the replicated one
+    #[default]
+    PreferLocal,
+
+    /// the replicated package shadows the local one
+    PreferUpstream,
 }
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
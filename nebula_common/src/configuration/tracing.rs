@@ -6,7 +6,9 @@ use color_eyre::eyre::Report;
 use http::Request;
 use tracing::{Level, Span, level_filters::LevelFilter};
 use tracing_error::ErrorLayer;
-use tracing_subscriber::{Layer as _, layer::SubscriberExt as _, util::SubscriberInitExt as _};
+use tracing_subscriber::{
+    Layer, Registry, filter::EnvFilter, layer::SubscriberExt as _, util::SubscriberInitExt as _,
+};
 use uuid::Uuid;
 
 pub struct AppDefaultValuesFromEnv {
@@ -18,6 +20,9 @@ pub struct AppDefaultValuesFromEnv {
     pub crate_name: String,
 }
 
+/// A further subscriber, e.g. one that shows the events in a user interface
+pub type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
 /// initializes a file and optionally a console subscriber for tracing
 ///
 /// console_lvl: Option contains the level that shall be logged onto the console
@@ -31,6 +36,19 @@ pub struct AppDefaultValuesFromEnv {
 pub unsafe fn initialize_logging(
     console_lvl: Option<LevelFilter>,
     env_vars: AppDefaultValuesFromEnv,
+) -> Result<(), Report> {
+    unsafe { initialize_logging_with(console_lvl, None, env_vars) }
+}
+
+/// like [initialize_logging] but forwards the events of the log file to the given layer as well
+///
+/// # Safety
+///
+/// See [initialize_logging]
+pub unsafe fn initialize_logging_with(
+    console_lvl: Option<LevelFilter>,
+    layer: Option<BoxedLayer>,
+    env_vars: AppDefaultValuesFromEnv,
 ) -> Result<(), Report> {
     let directory = env_vars.data_folder;
     std::fs::create_dir_all(directory.clone())?;
@@ -52,23 +70,29 @@ pub unsafe fn initialize_logging(
         .with_writer(log_file)
         .with_target(true)
         .with_ansi(false)
-        .with_filter(tracing_subscriber::filter::EnvFilter::from_default_env());
+        .with_filter(EnvFilter::from_default_env());
+    let layer = layer.map(|layer| layer.with_filter(EnvFilter::from_default_env()));
 
     if let Some(lvl) = console_lvl {
         let console_subscriber = tracing_subscriber::fmt::layer()
-            .with_writer(io::stdout)
+            .with_writer(io::stderr)
             .with_target(false)
             .with_ansi(true)
             .without_time()
             .with_filter(lvl);
 
         tracing_subscriber::registry()
+            .with(layer)
             .with(file_subscriber)
             .with(console_subscriber)
             .with(ErrorLayer::default())
             .init();
     } else {
-        tracing_subscriber::registry().with(file_subscriber).with(ErrorLayer::default()).init();
+        tracing_subscriber::registry()
+            .with(layer)
+            .with(file_subscriber)
+            .with(ErrorLayer::default())
+            .init();
     }
     Ok(())
 }
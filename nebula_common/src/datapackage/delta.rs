@@ -2,6 +2,8 @@
 //!
 //! Nebula add an extension to both the datapackage itself and the resources.
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -13,11 +15,105 @@ pub struct DeltaDataPackageNotValidated {
     pub test_count: Option<u32>,
     pub input_shape: String,
     pub mirror: Option<String>,
+
+    /// whether the package is a dataset or a model, see [super::DataPackageNotValidated::kind]
+    /// for descriptors without it
+    pub kind: Option<PackageKind>,
+
+    /// metadata that only models have
+    pub model: Option<DeltaModelNotValidated>,
+
+    /// other packages by name with a semver requirement on their version, e.g. `"^1.2"`
+    pub dependencies: Option<BTreeMap<String, String>>,
 }
 
+/// The kind of a package, a package is either a dataset or a model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, strum::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum PackageKind {
+    Dataset,
+
+    Model,
+}
+
+/// Model specific part of the delta extension
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeltaModelNotValidated {
+    /// e.g. tensorflow, pytorch or onnx
+    pub framework: Option<String>,
+
+    /// serialization format of the weights, e.g. saved_model, tflite or safetensors
+    pub format: Option<String>,
+
+    #[serde(default)]
+    pub inputs: Vec<TensorSpec>,
+
+    #[serde(default)]
+    pub outputs: Vec<TensorSpec>,
+
+    /// name of the dataset package the model was trained on, optionally as `name@version`
+    pub training_dataset: Option<String>,
+}
+
+/// Describes an input or output tensor of a model
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TensorSpec {
+    pub name: Option<String>,
+
+    /// element type, e.g. float32 or uint8
+    pub dtype: String,
+
+    /// dimensions of the tensor, -1 marks a dynamic dimension like the batch size
+    pub shape: Vec<i64>,
+}
+
+/// Prefixes of the delta format of resources that contain model weights or graphs
+const MODEL_FRAMEWORKS: &[&str] =
+    &["tf", "tf2", "tflite", "keras", "onnx", "torch", "pytorch", "safetensors", "jax"];
+
+/// File formats of resources that contain model weights or graphs
+const MODEL_FORMATS: &[&str] =
+    &["pb", "tflite", "h5", "keras", "onnx", "pt", "pth", "ckpt", "safetensors"];
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DeltaDataResourceNotValidated {
     pub origin: String,
     pub format: Option<String>,
     pub local_storage: String,
+
+    /// name of the archive resource that contains a `local-archive` resource, only needed if the
+    /// package has several archives
+    pub archive: Option<String>,
+}
+
+/// origin of resources that are members of another, downloaded archive resource
+pub const ORIGIN_LOCAL_ARCHIVE: &str = "local-archive";
+
+/// local storage of resources that are kept after the installation
+pub const STORAGE_INSTALLED: &str = "installed";
+
+impl DeltaDataResourceNotValidated {
+    /// Resources that are extracted from an archive are not downloaded by themselves
+    pub fn is_downloaded(&self) -> bool {
+        self.origin != ORIGIN_LOCAL_ARCHIVE
+    }
+
+    /// Temporary resources, like archives, are removed after the installation
+    pub fn is_installed(&self) -> bool {
+        self.local_storage == STORAGE_INSTALLED
+    }
+
+    /// The delta format starts with the framework for model files, e.g. `tf2;pb;...`
+    pub fn is_model_format(&self) -> bool {
+        self.format.as_ref().is_some_and(|format| {
+            let framework = format.split(';').next().unwrap_or_default().to_lowercase();
+            MODEL_FRAMEWORKS.contains(&framework.as_str())
+        })
+    }
+}
+
+/// Whether the file format of a resource is used for model weights or graphs
+pub fn is_model_file_format(format: &str) -> bool {
+    MODEL_FORMATS.contains(&format.to_lowercase().as_str())
 }
@@ -17,8 +17,14 @@ use std::path::Path;
 
 pub use delta::DeltaDataPackageNotValidated;
 pub use delta::DeltaDataResourceNotValidated;
+pub use delta::DeltaModelNotValidated;
+pub use delta::PackageKind;
+pub use delta::TensorSpec;
+pub use pod::DataPackageContributor;
+pub use pod::DataPackageLicense;
 pub use pod::DataPackageNotValidated;
 pub use pod::DataResourceNotValidated;
+pub use pod::PathSingleOrVec;
 pub use pod::datapackage_meta_from_file_not_validated;
 
 pub use validated::DataPackage;
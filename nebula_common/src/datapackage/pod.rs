@@ -13,6 +13,8 @@ use serde::Serialize;
 
 use super::DeltaDataPackageNotValidated;
 use super::DeltaDataResourceNotValidated;
+use super::PackageKind;
+use super::delta::is_model_file_format;
 
 /// Reads a json file that contains the datapackage descriptor as json the received data is not checked for validity
 ///
@@ -63,6 +65,41 @@ pub struct DataPackageNotValidated {
 
     pub delta: Option<DeltaDataPackageNotValidated>,
 }
+impl DataPackageNotValidated {
+    /// The bytes that are transferred to install the package
+    pub fn download_size(&self) -> u64 {
+        self.resources.iter().filter(|dr| dr.is_downloaded()).filter_map(|dr| dr.bytes).sum()
+    }
+
+    /// The bytes that are kept on disk after the installation
+    pub fn installed_size(&self) -> u64 {
+        self.resources.iter().filter(|dr| dr.is_installed()).filter_map(|dr| dr.bytes).sum()
+    }
+
+    /// The dependencies of the package by name with their version requirement
+    pub fn dependencies(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.delta
+            .iter()
+            .flat_map(|delta| delta.dependencies.iter().flatten())
+            .map(|(name, requirement)| (name.as_str(), requirement.as_str()))
+    }
+
+    /// The kind given in the delta extension, inferred for descriptors that don't state it
+    ///
+    /// Model metadata or resources in a model format mark a model, everything else is a dataset.
+    pub fn kind(&self) -> PackageKind {
+        if let Some(kind) = self.delta.as_ref().and_then(|delta| delta.kind) {
+            return kind;
+        }
+        let has_model = self.delta.as_ref().is_some_and(|delta| delta.model.is_some());
+        if has_model || self.resources.iter().any(|dr| dr.is_model()) {
+            PackageKind::Model
+        } else {
+            PackageKind::Dataset
+        }
+    }
+}
+
 /// A mapping for the Data Resource json format that is not validated in respect to the schema.
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct DataResourceNotValidated {
@@ -94,6 +131,24 @@ pub struct DataResourceNotValidated {
     pub delta: Option<DeltaDataResourceNotValidated>,
 }
 
+impl DataResourceNotValidated {
+    /// Resources without delta extension are downloaded from their path, inline data is not
+    pub fn is_downloaded(&self) -> bool {
+        self.path.is_some() && self.delta.as_ref().is_none_or(|delta| delta.is_downloaded())
+    }
+
+    /// Resources without delta extension are kept after the installation
+    pub fn is_installed(&self) -> bool {
+        self.delta.as_ref().is_none_or(|delta| delta.is_installed())
+    }
+
+    /// Resources that contain model weights or graphs
+    pub fn is_model(&self) -> bool {
+        self.delta.as_ref().is_some_and(|delta| delta.is_model_format())
+            || self.format.as_deref().is_some_and(is_model_file_format)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum PathSingleOrVec {
@@ -101,6 +156,16 @@ pub enum PathSingleOrVec {
     Vec(Vec<String>),
 }
 
+impl PathSingleOrVec {
+    /// Gets the path at the given index, a single path only has the index 0
+    pub fn nth(&self, index: usize) -> Option<&str> {
+        match self {
+            PathSingleOrVec::Single(path) => (index == 0).then_some(path.as_str()),
+            PathSingleOrVec::Vec(paths) => paths.get(index).map(|p| p.as_str()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum DataStringOrObj {
@@ -110,6 +175,7 @@ pub enum DataStringOrObj {
     Object(),
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum DataResourcesData {
@@ -225,4 +291,27 @@ mod tests {
         let data_resource: DataResourceNotValidated = serde_json::from_str(json).unwrap();
         assert_eq!(data_resource.name, "Test Resource".to_string());
     }
+
+    #[test]
+    fn test_kind_inference() {
+        let read = |folder: &str| {
+            let path = format!("../nebula_registry/data/{}/datapackage.json", folder);
+            datapackage_meta_from_file_not_validated(Path::new(&path)).unwrap()
+        };
+        assert_eq!(read("iris").kind(), PackageKind::Dataset);
+        assert_eq!(read("cifar10").kind(), PackageKind::Dataset);
+
+        let mut mobilenet = read("mobilenetv3_tf2");
+        assert_eq!(mobilenet.kind(), PackageKind::Model);
+
+        // without explicit kind and model metadata the resource formats tell
+        let delta = mobilenet.delta.as_mut().unwrap();
+        delta.kind = None;
+        delta.model = None;
+        assert_eq!(mobilenet.kind(), PackageKind::Model);
+
+        // explicit kind overrides the inference
+        mobilenet.delta.as_mut().unwrap().kind = Some(PackageKind::Dataset);
+        assert_eq!(mobilenet.kind(), PackageKind::Dataset);
+    }
 }
@@ -35,7 +35,8 @@ pub trait ValidateData {
 }
 
 /// A wrapper typ that marks input data as validated
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(transparent)]
 pub struct Validated<T: Sized + Sync + Send>(T);
 impl<T: Sized + Sync + Send> Validated<T> {
     pub fn into_inner(self) -> T {
@@ -1,6 +1,7 @@
 //! Nebula common library crate with functionality for both registry and cli
 
 pub mod api;
+pub mod archive;
 pub mod client;
 pub mod configuration;
 pub mod datapackage;
@@ -10,10 +11,36 @@ pub mod storage;
 
 pub mod nebula_proto {
     tonic::include_proto!("nebula.v1");
+
+    /// Encoded file descriptor set of the protocol, used for gRPC server reflection
+    pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("nebula_descriptor");
+
+    /// Serializes a [PackageType] field by its lowercase name instead of the number
+    pub(crate) fn serialize_package_type<S: serde::Serializer>(
+        value: &i32,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let name = PackageType::try_from(*value).map(|el| el.as_str_name()).unwrap_or("UNKNOWN");
+        serializer.serialize_str(&name.to_lowercase())
+    }
+
+    /// Serializes the [RegistryFeature] fields by their lowercase names instead of the numbers
+    pub(crate) fn serialize_features<S: serde::Serializer>(
+        value: &[i32],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(value.iter().map(|el| match RegistryFeature::try_from(*el) {
+            Ok(RegistryFeature::Unspecified) | Err(_) => "unknown".to_string(),
+            Ok(feature) => feature.as_str_name().to_lowercase(),
+        }))
+    }
 }
 
 pub mod nebula_proto_fallback {
     // include older version here
 }
 
+/// The protocol versions of the registry interface that this build speaks, newest first
+pub const PROTOCOL_VERSIONS: &[&str] = &["v1"];
+
 pub use api::state::NebulaState as NebulaCliState;
@@ -2,11 +2,20 @@
 //!
 //!
 
-use std::ops::{Deref, DerefMut};
+use std::{
+    cmp::Reverse,
+    ops::{Deref, DerefMut},
+    str::FromStr,
+    time::SystemTime,
+};
 
+use chrono::{DateTime, NaiveDate, Utc};
 use num_enum::TryFromPrimitive;
 
+use crate::datapackage::{DataPackage, DataPackageNotValidated, PackageKind};
+
 pub mod pb_mapper;
+pub mod resolver;
 
 #[repr(u8)]
 #[derive(TryFromPrimitive, Debug, Clone, Copy, Default, PartialEq, Eq)]
@@ -57,12 +66,45 @@ impl From<PackageType> for super::registry::PackageType {
     }
 }
 
+impl From<super::registry::PackageType> for PackageType {
+    fn from(value: super::registry::PackageType) -> Self {
+        // safety: We keep PackageType in sync
+        PackageType::try_from(value as i32 as u8).unwrap()
+    }
+}
+
+impl From<PackageKind> for PackageType {
+    fn from(value: PackageKind) -> Self {
+        match value {
+            PackageKind::Dataset => PackageType::Dataset,
+            PackageKind::Model => PackageType::Model,
+        }
+    }
+}
+
+impl PackageType {
+    /// The kind of packages of this type, None for both
+    pub fn kind(&self) -> Option<PackageKind> {
+        match self {
+            PackageType::Both => None,
+            PackageType::Dataset => Some(PackageKind::Dataset),
+            PackageType::Model => Some(PackageKind::Model),
+        }
+    }
+
+    pub fn matches(&self, kind: PackageKind) -> bool {
+        self.kind().is_none_or(|el| el == kind)
+    }
+}
+
 /// Optional MetaData Fields
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MetaDataField {
     PreviewImages,
 
     DataPackage,
+
+    DownloadHistory,
     // todo more?
 }
 
@@ -100,18 +142,383 @@ impl Default for PagationSettings {
     }
 }
 
-/// multi level sort settinggs, not implemented
+impl PagationSettings {
+    /// Settings that select all entries
+    pub fn unlimited() -> Self {
+        Self { limit: u32::MAX, offset: 0 }
+    }
+}
+
+/// An inclusive range of days, open ends are unbounded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DateRange {
+    pub start: Option<NaiveDate>,
+
+    pub end: Option<NaiveDate>,
+}
+
+impl DateRange {
+    /// The format of dates in the protocol, YYYYMMDD
+    pub const FORMAT: &str = "%Y%m%d";
+
+    /// Parses the bounds in the format YYYYMMDD
+    pub fn from_strs(start: Option<&str>, end: Option<&str>) -> Result<Self, chrono::ParseError> {
+        let parse =
+            |el: Option<&str>| el.map(|el| NaiveDate::parse_from_str(el, Self::FORMAT)).transpose();
+        Ok(DateRange { start: parse(start)?, end: parse(end)? })
+    }
+
+    pub fn contains(&self, day: &NaiveDate) -> bool {
+        self.start.is_none_or(|start| start <= *day) && self.end.is_none_or(|end| *day <= end)
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.start.is_none() && self.end.is_none()
+    }
+
+    /// Like [DateRange::contains] but an unknown day is only contained in an unbounded range
+    pub fn contains_opt(&self, day: Option<NaiveDate>) -> bool {
+        self.is_unbounded() || day.is_some_and(|day| self.contains(&day))
+    }
+}
+
+/// How the search query is matched against the packages
+#[repr(u8)]
+#[derive(TryFromPrimitive, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchKind {
+    /// every word of the query is a substring of the name, title, description, keywords or authors
+    #[default]
+    Relaxed,
+
+    /// the query is a substring of the package name
+    Name,
+
+    /// the query is a substring of an author
+    Author,
+}
+
+impl From<SearchKind> for super::registry::SearchKind {
+    fn from(value: SearchKind) -> Self {
+        // safety: We keep SearchKind in sync
+        super::registry::SearchKind::try_from((value as u8) as i32).unwrap()
+    }
+}
+
+impl From<super::registry::SearchKind> for SearchKind {
+    fn from(value: super::registry::SearchKind) -> Self {
+        // safety: We keep SearchKind in sync
+        SearchKind::try_from(value as i32 as u8).unwrap()
+    }
+}
+
+impl SearchKind {
+    /// Case-insensitive match of the query, an empty query matches every package
+    pub fn matches(&self, query: &str, package: &DataPackageNotValidated) -> bool {
+        let query = query.to_lowercase();
+        let contains = |field: &Option<String>, word: &str| {
+            field.as_ref().is_some_and(|el| el.to_lowercase().contains(word))
+        };
+        let authors = authors(package);
+        match self {
+            SearchKind::Relaxed => query.split_whitespace().all(|word| {
+                contains(&package.name, word)
+                    || contains(&package.title, word)
+                    || contains(&package.description, word)
+                    || package
+                        .keywords
+                        .as_ref()
+                        .is_some_and(|kws| kws.iter().any(|kw| kw.to_lowercase().contains(word)))
+                    || authors.iter().any(|author| author.contains(word))
+            }),
+            SearchKind::Name => contains(&package.name, &query),
+            SearchKind::Author => authors.iter().any(|author| author.contains(&query)),
+        }
+    }
+}
+
+/// Lower case names of the contributors, with the title and organization
+pub(crate) fn authors(package: &DataPackageNotValidated) -> Vec<String> {
+    package
+        .contributor
+        .iter()
+        .flatten()
+        .map(|c| {
+            [&c.title, &c.given_name, &c.family_name, &c.organziation]
+                .into_iter()
+                .flatten()
+                .map(|el| el.to_lowercase())
+                .collect::<Vec<String>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+/// The day a package was created, descriptors store it as ISO 8601 date or date-time
+fn created_day(package: &DataPackageNotValidated) -> Option<NaiveDate> {
+    let created = package.created.as_deref()?;
+    NaiveDate::parse_from_str(created.get(..10)?, "%Y-%m-%d").ok()
+}
+
+/// Properties that packages are sorted by
+#[repr(u8)]
+#[derive(TryFromPrimitive, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    CreationDate,
+
+    /// the download count, known by the registry only
+    Downloads,
+
+    Name,
+
+    /// the first contributor
+    Author,
+}
+
+impl From<SortBy> for super::registry::SortOption {
+    fn from(value: SortBy) -> Self {
+        // safety: We keep SortBy in sync
+        super::registry::SortOption::try_from((value as u8) as i32).unwrap()
+    }
+}
+
+impl From<super::registry::SortOption> for SortBy {
+    fn from(value: super::registry::SortOption) -> Self {
+        // safety: We keep SortBy in sync
+        SortBy::try_from(value as i32 as u8).unwrap()
+    }
+}
+
+/// One level of a multi level sort
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortLevel {
+    pub by: SortBy,
+
+    pub descending: bool,
+}
+
+/// Parses `<field>[:asc|:desc]` with the fields created, downloads, name and author
+impl FromStr for SortLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, direction) = s.split_once(':').unwrap_or((s, "asc"));
+        let by = match field.trim() {
+            "created" => SortBy::CreationDate,
+            "downloads" => SortBy::Downloads,
+            "name" => SortBy::Name,
+            "author" => SortBy::Author,
+            other => {
+                return Err(format!(
+                    "unknown sort field '{}', expected created, downloads, name or author",
+                    other
+                ));
+            }
+        };
+        let descending = match direction.trim() {
+            "asc" => false,
+            "desc" => true,
+            other => {
+                return Err(format!("unknown sort direction '{}', expected asc or desc", other));
+            }
+        };
+        Ok(SortLevel { by, descending })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum SortKey {
+    Text(Option<String>),
+
+    Number(u64),
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum DirectedSortKey {
+    Ascending(SortKey),
+
+    Descending(Reverse<SortKey>),
+}
+
+/// Multi level sort settings, the first level has the highest priority
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
-pub struct SortSettings {}
+pub struct SortSettings {
+    pub levels: Vec<SortLevel>,
+}
 
-/// Filter Settings, not implemnted
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl SortSettings {
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// Sorts stable by the levels, the download counts are given by the caller
+    pub fn sort<F>(&self, packages: &mut [DataPackage], downloads: F)
+    where
+        F: Fn(&DataPackage) -> u64,
+    {
+        if self.is_empty() {
+            return;
+        }
+        packages.sort_by_cached_key(|dp| {
+            self.levels
+                .iter()
+                .map(|level| {
+                    let key = match level.by {
+                        SortBy::CreationDate => SortKey::Text(dp.created.clone()),
+                        SortBy::Downloads => SortKey::Number(downloads(dp)),
+                        SortBy::Name => SortKey::Text(dp.name.as_ref().map(|el| el.to_lowercase())),
+                        SortBy::Author => SortKey::Text(authors(dp).into_iter().next()),
+                    };
+                    if level.descending {
+                        DirectedSortKey::Descending(Reverse(key))
+                    } else {
+                        DirectedSortKey::Ascending(key)
+                    }
+                })
+                .collect::<Vec<_>>()
+        });
+    }
+}
+
+/// Filter Settings, every [crate::storage::MetaDataSource] applies them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct FilterSettings {
     pub package_type: PackageType,
+
+    /// how the query of a search is matched, ignored when listing packages
+    pub kind: SearchKind,
+
+    /// packages with a contributor that contains one of the names, case-insensitive
+    pub authors: Vec<String>,
+
+    pub created: DateRange,
+
+    /// compared with the last modification of the descriptor in the data source
+    pub updated: DateRange,
+
+    /// category of the delta extension, case-insensitive
+    pub category: Option<String>,
+
+    /// number of classes of the delta extension
+    pub classes: Option<u32>,
 }
 
-impl Default for FilterSettings {
-    fn default() -> Self {
-        Self { package_type: PackageType::Both }
+impl FilterSettings {
+    pub fn matches(&self, package: &DataPackageNotValidated) -> bool {
+        let delta = package.delta.as_ref();
+        self.package_type.matches(package.kind())
+            && (self.authors.is_empty() || {
+                let authors = authors(package);
+                self.authors.iter().any(|name| {
+                    let name = name.to_lowercase();
+                    authors.iter().any(|author| author.contains(&name))
+                })
+            })
+            && self.created.contains_opt(created_day(package))
+            && self.category.as_ref().is_none_or(|category| {
+                delta.is_some_and(|delta| delta.category.eq_ignore_ascii_case(category))
+            })
+            && self.classes.is_none_or(|classes| delta.is_some_and(|d| d.classes == Some(classes)))
+    }
+
+    /// Checks the last modification of the descriptor, None if it is unknown
+    pub fn matches_updated(&self, modified: Option<SystemTime>) -> bool {
+        self.updated.contains_opt(modified.map(|el| DateTime::<Utc>::from(el).date_naive()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::datapackage::{
+        DataPackageContributor, DataResourceNotValidated, DeltaDataPackageNotValidated,
+        ValidateData as _,
+    };
+
+    fn package(name: &str, author: &str, created: &str, classes: u32) -> DataPackage {
+        DataPackageNotValidated {
+            name: Some(name.into()),
+            version: Some("1.0.0".into()),
+            created: Some(created.into()),
+            contributor: Some(vec![DataPackageContributor {
+                title: Some(author.into()),
+                given_name: None,
+                family_name: None,
+                path: None,
+                email: None,
+                rules: None,
+                organziation: None,
+            }]),
+            delta: Some(DeltaDataPackageNotValidated {
+                category: "classification".into(),
+                classes: Some(classes),
+                training_count: None,
+                validation_count: None,
+                test_count: None,
+                input_shape: "32x32x3".into(),
+                mirror: None,
+                kind: None,
+                model: None,
+                dependencies: None,
+            }),
+            resources: vec![DataResourceNotValidated::default()],
+            ..Default::default()
+        }
+        .validate()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_filter_and_search_kind() {
+        let cifar = package("cifar-10-binary", "Alex Krizhevsky", "2009-04-08T00:00:00Z", 10);
+
+        assert!(SearchKind::Relaxed.matches("krizhevsky CIFAR", &cifar));
+        assert!(!SearchKind::Relaxed.matches("krizhevsky iris", &cifar));
+        assert!(SearchKind::Name.matches("cifar", &cifar));
+        assert!(!SearchKind::Name.matches("alex", &cifar));
+        assert!(SearchKind::Author.matches("alex", &cifar));
+
+        let mut filter = FilterSettings {
+            authors: vec!["someone".into(), "KRIZHEVSKY".into()],
+            category: Some("Classification".into()),
+            classes: Some(10),
+            ..Default::default()
+        };
+        assert!(filter.matches(&cifar));
+        filter.created = DateRange::from_strs(Some("20100101"), None).unwrap();
+        assert!(!filter.matches(&cifar));
+        filter.created = DateRange::from_strs(None, Some("20091231")).unwrap();
+        assert!(filter.matches(&cifar));
+        filter.classes = Some(100);
+        assert!(!filter.matches(&cifar));
+
+        filter.updated = DateRange::from_strs(Some("20200101"), None).unwrap();
+        assert!(filter.matches_updated(Some(SystemTime::now())));
+        assert!(!filter.matches_updated(Some(SystemTime::UNIX_EPOCH)));
+        assert!(!filter.matches_updated(None));
+    }
+
+    #[test]
+    fn test_multi_level_sort() {
+        let mut packages = vec![
+            package("b", "Zoe", "2020-01-01", 1),
+            package("a", "Zoe", "2021-01-01", 1),
+            package("c", "Adam", "2019-01-01", 1),
+        ];
+        let names = |packages: &[DataPackage]| -> Vec<String> {
+            packages.iter().map(|dp| dp.name.clone().unwrap()).collect()
+        };
+
+        let sort = SortSettings {
+            levels: vec!["author:desc".parse().unwrap(), "created".parse().unwrap()],
+        };
+        sort.sort(&mut packages, |_| 0);
+        assert_eq!(names(&packages), vec!["b", "a", "c"]);
+
+        let sort = SortSettings { levels: vec!["downloads:desc".parse().unwrap()] };
+        sort.sort(&mut packages, |dp| if dp.name.as_deref() == Some("c") { 5 } else { 0 });
+        assert_eq!(names(&packages), vec!["c", "b", "a"]);
+
+        assert!("size".parse::<SortLevel>().is_err());
+        assert!("name:up".parse::<SortLevel>().is_err());
     }
 }
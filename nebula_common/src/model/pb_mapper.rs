@@ -1,8 +1,17 @@
 //! Contains functionality to map protobuf related types to nebulas internal model
 
-use crate::{datapackage::DataPackage, registry::PackageInfo};
+use crate::{
+    datapackage::{
+        DataPackage, DataPackageContributor, DataPackageLicense, DataPackageNotValidated,
+        DeltaDataPackageNotValidated, DeltaModelNotValidated, TensorSpec,
+    },
+    registry::{self, Author, DeltaMetadata, License, ModelMetadata, PackageInfo},
+};
 
-use super::{FieldSettings, FilterSettings, PackageType, PagationSettings, SortSettings};
+use super::{
+    DateRange, FieldSettings, FilterSettings, PackageType, PagationSettings, SearchKind, SortBy,
+    SortLevel, SortSettings,
+};
 
 /// Maps self to Pagation Settings
 pub trait PagationMapper {
@@ -23,6 +32,60 @@ pub trait FieldMapper {
     fn as_fields(&self) -> Result<FieldSettings, Box<dyn std::error::Error>>;
 }
 
+/// Maps self to the range of days in which downloads are counted
+pub trait DownloadRangeMapper {
+    fn as_download_range(&self) -> Result<DateRange, Box<dyn std::error::Error>>;
+}
+
+impl TryFrom<&super::super::registry::DateRange> for DateRange {
+    type Error = chrono::ParseError;
+
+    fn try_from(value: &super::super::registry::DateRange) -> Result<Self, Self::Error> {
+        DateRange::from_strs(value.start.as_deref(), value.end.as_deref())
+    }
+}
+
+impl From<&DateRange> for super::super::registry::DateRange {
+    fn from(value: &DateRange) -> Self {
+        let format = |day: Option<chrono::NaiveDate>| {
+            day.map(|day| day.format(DateRange::FORMAT).to_string())
+        };
+        super::super::registry::DateRange { start: format(value.start), end: format(value.end) }
+    }
+}
+
+impl From<SortLevel> for registry::SortParameter {
+    fn from(value: SortLevel) -> Self {
+        registry::SortParameter {
+            sort_by: registry::SortOption::from(value.by) as i32,
+            descending: Some(value.descending),
+            params: vec![],
+        }
+    }
+}
+
+fn as_date_range(
+    range: Option<&super::super::registry::DateRange>,
+) -> Result<DateRange, Box<dyn std::error::Error>> {
+    Ok(range.map(DateRange::try_from).transpose()?.unwrap_or_default())
+}
+
+fn as_fields(fo: Option<super::super::registry::FieldOptions>) -> FieldSettings {
+    let mut reval = FieldSettings::default();
+    if let Some(fo) = fo {
+        if fo.include_datapackage_json {
+            reval.push(super::MetaDataField::DataPackage);
+        }
+        if fo.include_preview_images {
+            reval.push(super::MetaDataField::PreviewImages);
+        }
+        if fo.include_download_history {
+            reval.push(super::MetaDataField::DownloadHistory);
+        }
+    }
+    reval
+}
+
 impl PagationMapper for super::super::registry::ListPackagesRequest {
     fn as_pagation(&self) -> Result<PagationSettings, Box<dyn std::error::Error>> {
         let mut reval = PagationSettings::default();
@@ -38,16 +101,13 @@ impl PagationMapper for super::super::registry::ListPackagesRequest {
 
 impl FieldMapper for super::super::registry::ListPackagesRequest {
     fn as_fields(&self) -> Result<FieldSettings, Box<dyn std::error::Error>> {
-        let mut reval = FieldSettings::default();
-        if let Some(fo) = self.field_options {
-            if fo.include_datapackage_json {
-                reval.push(super::MetaDataField::DataPackage);
-            }
-            if fo.include_preview_images {
-                reval.push(super::MetaDataField::PreviewImages);
-            }
-        }
-        Ok(reval)
+        Ok(as_fields(self.field_options))
+    }
+}
+
+impl DownloadRangeMapper for super::super::registry::ListPackagesRequest {
+    fn as_download_range(&self) -> Result<DateRange, Box<dyn std::error::Error>> {
+        Ok(self.download_range.as_ref().map(DateRange::try_from).transpose()?.unwrap_or_default())
     }
 }
 
@@ -64,11 +124,23 @@ impl PagationMapper for super::super::registry::SearchPackagesRequest {
     }
 }
 
+impl FieldMapper for super::super::registry::SearchPackagesRequest {
+    fn as_fields(&self) -> Result<FieldSettings, Box<dyn std::error::Error>> {
+        Ok(as_fields(self.field_options))
+    }
+}
+
+impl DownloadRangeMapper for super::super::registry::SearchPackagesRequest {
+    fn as_download_range(&self) -> Result<DateRange, Box<dyn std::error::Error>> {
+        Ok(self.download_range.as_ref().map(DateRange::try_from).transpose()?.unwrap_or_default())
+    }
+}
+
 impl FilterMapper for super::super::registry::PackageRequest {
     fn as_filter(&self) -> Result<FilterSettings, Box<dyn std::error::Error>> {
         let mut reval = FilterSettings::default();
         if let Some(pt) = self.package_type {
-            reval.package_type = PackageType::try_from(pt as u8).unwrap();
+            reval.package_type = as_package_type(pt)?;
         }
         Ok(reval)
     }
@@ -80,17 +152,66 @@ impl FilterMapper for super::super::registry::PackageRequest {
 
 impl FilterMapper for super::super::registry::SearchPackagesRequest {
     fn as_filter(&self) -> Result<FilterSettings, Box<dyn std::error::Error>> {
-        Ok(FilterSettings::default())
+        let kind = match self.kind {
+            Some(kind) => registry::SearchKind::try_from(kind)?.into(),
+            None => SearchKind::default(),
+        };
+        Ok(FilterSettings {
+            package_type: as_package_type(self.package_type)?,
+            kind,
+            authors: self.authors.clone(),
+            created: as_date_range(self.created_date.as_ref())?,
+            updated: as_date_range(self.updated_date.as_ref())?,
+            category: self.category.clone(),
+            classes: self.classes,
+        })
+    }
+
+    fn into_filter(self) -> Result<FilterSettings, Box<dyn std::error::Error>> {
+        self.as_filter()
+    }
+}
+
+impl FilterMapper for super::super::registry::ListPackagesRequest {
+    fn as_filter(&self) -> Result<FilterSettings, Box<dyn std::error::Error>> {
+        Ok(FilterSettings {
+            package_type: as_package_type(self.package_type)?,
+            ..Default::default()
+        })
     }
 
     fn into_filter(self) -> Result<FilterSettings, Box<dyn std::error::Error>> {
-        Ok(FilterSettings::default())
+        self.as_filter()
     }
 }
 
+fn as_package_type(pt: i32) -> Result<PackageType, Box<dyn std::error::Error>> {
+    Ok(registry::PackageType::try_from(pt)?.into())
+}
+
+/// The sort parameters take precedence, the plain sort options sort downloads descending
 impl SortMapper for super::super::registry::SearchPackagesRequest {
     fn as_sort(&self) -> Result<SortSettings, Box<dyn std::error::Error>> {
-        Ok(SortSettings::default())
+        let levels = if self.sort_parameters.is_empty() {
+            self.sort
+                .iter()
+                .map(|by| {
+                    let by: SortBy = registry::SortOption::try_from(*by)?.into();
+                    Ok(SortLevel { by, descending: by == SortBy::Downloads })
+                })
+                .collect::<Result<_, prost::UnknownEnumValue>>()?
+        } else {
+            self.sort_parameters
+                .iter()
+                .map(|param| {
+                    Ok(SortLevel {
+                        by: registry::SortOption::try_from(param.sort_by)?.into(),
+                        descending: param.descending.unwrap_or_default(),
+                    })
+                })
+                .collect::<Result<_, prost::UnknownEnumValue>>()?
+        };
+        Ok(SortSettings { levels })
     }
 }
 
@@ -98,6 +219,8 @@ impl From<DataPackage> for PackageInfo {
     fn from(val: DataPackage) -> PackageInfo {
         let mut inner = val.into_inner();
         PackageInfo {
+            download_size: inner.download_size(),
+            installed_size: inner.installed_size(),
             name: match inner.name.take() {
                 Some(v) => v,
                 None => "No name".to_string(),
@@ -110,17 +233,162 @@ impl From<DataPackage> for PackageInfo {
                 Some(v) => v,
                 None => "No Description".to_string(),
             },
-            license: {
-                let mut reval = String::new();
-                for lic in &inner.licenses {
-                    reval += lic.name.as_str();
-                }
-                if reval.is_empty() {
-                    reval = "UKNOWN".to_string()
-                }
-                reval
-            },
+            title: inner.title.take(),
+            license: legacy_license(&inner.licenses),
+            licenses: inner.licenses.drain(..).map(License::from).collect(),
+            authors: inner
+                .contributor
+                .take()
+                .unwrap_or_default()
+                .into_iter()
+                .map(Author::from)
+                .collect(),
+            keywords: inner.keywords.take().unwrap_or_default(),
+            package_type: registry::PackageType::from(PackageType::from(inner.kind())) as i32,
+            created: inner.created.take(),
+            delta: inner.delta.take().map(DeltaMetadata::from),
+            ..Default::default()
+        }
+    }
+}
+
+/// Maps the structured fields back to a descriptor, resources are only part of the datapackage json
+impl From<PackageInfo> for DataPackageNotValidated {
+    fn from(val: PackageInfo) -> Self {
+        let package_type = val.package_type();
+        DataPackageNotValidated {
+            name: Some(val.name),
+            version: Some(val.version),
+            description: Some(val.description),
+            title: val.title,
+            licenses: val.licenses.into_iter().map(DataPackageLicense::from).collect(),
+            contributor: (!val.authors.is_empty())
+                .then(|| val.authors.into_iter().map(DataPackageContributor::from).collect()),
+            keywords: (!val.keywords.is_empty()).then_some(val.keywords),
+            created: val.created,
+            delta: val.delta.map(|delta| DeltaDataPackageNotValidated {
+                kind: PackageType::from(package_type).kind(),
+                ..delta.into()
+            }),
             ..Default::default()
         }
     }
 }
+
+/// The license names as v1 clients read them from the former only license field
+fn legacy_license(licenses: &[DataPackageLicense]) -> String {
+    if licenses.is_empty() {
+        return "UNKNOWN".to_string();
+    }
+    licenses.iter().map(|lic| lic.name.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+impl From<DataPackageLicense> for License {
+    fn from(val: DataPackageLicense) -> Self {
+        License { name: val.name, path: val.path, title: val.title }
+    }
+}
+
+impl From<License> for DataPackageLicense {
+    fn from(val: License) -> Self {
+        DataPackageLicense { name: val.name, path: val.path, title: val.title }
+    }
+}
+
+impl From<DataPackageContributor> for Author {
+    fn from(val: DataPackageContributor) -> Self {
+        Author {
+            title: val.title,
+            given_name: val.given_name,
+            family_name: val.family_name,
+            path: val.path,
+            email: val.email,
+            roles: val.rules.unwrap_or_default(),
+            organization: val.organziation,
+        }
+    }
+}
+
+impl From<Author> for DataPackageContributor {
+    fn from(val: Author) -> Self {
+        DataPackageContributor {
+            title: val.title,
+            given_name: val.given_name,
+            family_name: val.family_name,
+            path: val.path,
+            email: val.email,
+            rules: (!val.roles.is_empty()).then_some(val.roles),
+            organziation: val.organization,
+        }
+    }
+}
+
+impl From<DeltaDataPackageNotValidated> for DeltaMetadata {
+    fn from(val: DeltaDataPackageNotValidated) -> Self {
+        DeltaMetadata {
+            category: val.category,
+            classes: val.classes,
+            input_shape: val.input_shape,
+            training_count: val.training_count,
+            validation_count: val.validation_count,
+            test_count: val.test_count,
+            model: val.model.map(ModelMetadata::from),
+            dependencies: val.dependencies.unwrap_or_default().into_iter().collect(),
+        }
+    }
+}
+
+impl From<DeltaModelNotValidated> for ModelMetadata {
+    fn from(val: DeltaModelNotValidated) -> Self {
+        ModelMetadata {
+            framework: val.framework,
+            format: val.format,
+            inputs: val.inputs.into_iter().map(registry::TensorSpec::from).collect(),
+            outputs: val.outputs.into_iter().map(registry::TensorSpec::from).collect(),
+            training_dataset: val.training_dataset,
+        }
+    }
+}
+
+impl From<ModelMetadata> for DeltaModelNotValidated {
+    fn from(val: ModelMetadata) -> Self {
+        DeltaModelNotValidated {
+            framework: val.framework,
+            format: val.format,
+            inputs: val.inputs.into_iter().map(TensorSpec::from).collect(),
+            outputs: val.outputs.into_iter().map(TensorSpec::from).collect(),
+            training_dataset: val.training_dataset,
+        }
+    }
+}
+
+impl From<TensorSpec> for registry::TensorSpec {
+    fn from(val: TensorSpec) -> Self {
+        registry::TensorSpec { name: val.name, dtype: val.dtype, shape: val.shape }
+    }
+}
+
+impl From<registry::TensorSpec> for TensorSpec {
+    fn from(val: registry::TensorSpec) -> Self {
+        TensorSpec { name: val.name, dtype: val.dtype, shape: val.shape }
+    }
+}
+
+/// The mirror and the kind are not part of the delta metadata, they are left empty
+impl From<DeltaMetadata> for DeltaDataPackageNotValidated {
+    fn from(val: DeltaMetadata) -> Self {
+        DeltaDataPackageNotValidated {
+            category: val.category,
+            classes: val.classes,
+            training_count: val.training_count,
+            validation_count: val.validation_count,
+            test_count: val.test_count,
+            input_shape: val.input_shape,
+            mirror: None,
+            kind: None,
+            model: val.model.map(DeltaModelNotValidated::from),
+            dependencies: (!val.dependencies.is_empty())
+                .then(|| val.dependencies.into_iter().collect()),
+        }
+    }
+}
@@ -0,0 +1,406 @@
+//! Resolves the dependencies of a package to a consistent set of package versions
+//!
+//! Dependencies are given in the delta extension by package name and a [semver] requirement.
+//! The resolver picks the newest version of each package that satisfies every requirement on it
+//! and backtracks if a choice leads to a conflict later on. Each package is chosen in one
+//! version only, requirements that no single version satisfies are a conflict.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use semver::{Version, VersionReq};
+use tracing::warn;
+
+use crate::datapackage::DataPackage;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    #[error("Package '{0}' not found")]
+    NotFound(String),
+
+    #[error("Invalid package '{0}', expected name or name@requirement")]
+    InvalidSpec(String),
+
+    #[error("Invalid requirement '{requirement}' on '{dependency}' in '{package}'")]
+    InvalidRequirement { package: String, dependency: String, requirement: String },
+
+    #[error("No version of '{name}' satisfies {}", format_requirements(.requirements))]
+    Conflict {
+        name: String,
+
+        /// the requiring package and its requirement
+        requirements: Vec<(String, String)>,
+    },
+
+    #[error("Dependency cycle {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+}
+
+fn format_requirements(requirements: &[(String, String)]) -> String {
+    let reqs: Vec<String> =
+        requirements.iter().map(|(from, req)| format!("'{}' of {}", req, from)).collect();
+    reqs.join(", ")
+}
+
+/// Splits `name@requirement` into the name and the requirement, the requirement defaults to any
+pub fn parse_package_spec(spec: &str) -> Result<(String, VersionReq), ResolveError> {
+    let (name, req) = match spec.split_once('@') {
+        Some((name, req)) => {
+            (name, VersionReq::parse(req).map_err(|_| ResolveError::InvalidSpec(spec.into()))?)
+        }
+        None => (spec, VersionReq::STAR),
+    };
+    if name.is_empty() {
+        return Err(ResolveError::InvalidSpec(spec.into()));
+    }
+    Ok((name.to_string(), req))
+}
+
+/// A requirement that still has to be resolved
+#[derive(Debug, Clone)]
+struct Pending {
+    from: String,
+
+    name: String,
+
+    req: VersionReq,
+}
+
+/// The choices made so far, cloned on every choice to allow backtracking
+#[derive(Debug, Clone, Default)]
+struct Partial {
+    chosen: BTreeMap<String, usize>,
+
+    requirements: HashMap<String, Vec<(String, VersionReq)>>,
+}
+
+/// Resolves dependencies against the packages it is created from, e.g. the local cache
+#[derive(Debug)]
+pub struct Resolver {
+    /// versions of the packages by name, newest first
+    index: HashMap<String, Vec<(Version, DataPackage)>>,
+}
+
+impl Resolver {
+    /// Packages without name or with a version that is no semver are ignored
+    pub fn new(packages: impl IntoIterator<Item = DataPackage>) -> Self {
+        let mut index: HashMap<String, Vec<(Version, DataPackage)>> = HashMap::new();
+        for package in packages {
+            let (Some(name), Some(version)) = (package.name.as_ref(), package.version.as_ref())
+            else {
+                continue;
+            };
+            match Version::parse(version) {
+                Ok(version) => index.entry(name.clone()).or_default().push((version, package)),
+                Err(err) => warn!("Ignoring '{}' with version '{}': {}", name, version, err),
+            }
+        }
+        for versions in index.values_mut() {
+            versions.sort_by(|a, b| b.0.cmp(&a.0));
+        }
+        Resolver { index }
+    }
+
+    /// Resolves the package and its transitive dependencies
+    pub fn resolve(&self, name: &str, req: &VersionReq) -> Result<Resolution, ResolveError> {
+        let root = Pending { from: "the request".into(), name: name.into(), req: req.clone() };
+        let partial = self.solve(Partial::default(), VecDeque::from([root]))?;
+
+        let mut packages = BTreeMap::new();
+        for (name, index) in partial.chosen {
+            packages.insert(name.clone(), self.index[&name][index].1.clone());
+        }
+        let order = topological_order(name, &packages)?;
+        Ok(Resolution { root: name.into(), packages, order })
+    }
+
+    fn solve(
+        &self,
+        mut partial: Partial,
+        mut pending: VecDeque<Pending>,
+    ) -> Result<Partial, ResolveError> {
+        let Some(next) = pending.pop_front() else {
+            return Ok(partial);
+        };
+        let versions =
+            self.index.get(&next.name).ok_or(ResolveError::NotFound(next.name.clone()))?;
+        partial.requirements.entry(next.name.clone()).or_default().push((next.from, next.req));
+        let reqs = &partial.requirements[&next.name];
+
+        if let Some(index) = partial.chosen.get(&next.name) {
+            return if reqs.iter().all(|(_, req)| req.matches(&versions[*index].0)) {
+                self.solve(partial, pending)
+            } else {
+                Err(conflict(&next.name, reqs))
+            };
+        }
+
+        let mut first_err = None;
+        for (index, (version, package)) in versions.iter().enumerate() {
+            if !reqs.iter().all(|(_, req)| req.matches(version)) {
+                continue;
+            }
+            let mut attempt = partial.clone();
+            attempt.chosen.insert(next.name.clone(), index);
+            let mut queue = pending.clone();
+            for (dependency, requirement) in dependencies(package)? {
+                queue.push_back(Pending {
+                    from: next.name.clone(),
+                    name: dependency,
+                    req: requirement,
+                });
+            }
+            match self.solve(attempt, queue) {
+                Ok(done) => return Ok(done),
+                // missing packages and invalid descriptors don't depend on the choice
+                Err(
+                    err @ (ResolveError::NotFound(_) | ResolveError::InvalidRequirement { .. }),
+                ) => {
+                    return Err(err);
+                }
+                Err(err) => {
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+        Err(first_err.unwrap_or_else(|| conflict(&next.name, reqs)))
+    }
+}
+
+fn conflict(name: &str, reqs: &[(String, VersionReq)]) -> ResolveError {
+    ResolveError::Conflict {
+        name: name.into(),
+        requirements: reqs.iter().map(|(from, req)| (from.clone(), req.to_string())).collect(),
+    }
+}
+
+/// The dependencies of a package with parsed requirements
+fn dependencies(package: &DataPackage) -> Result<Vec<(String, VersionReq)>, ResolveError> {
+    package
+        .dependencies()
+        .map(|(name, requirement)| match VersionReq::parse(requirement) {
+            Ok(req) => Ok((name.to_string(), req)),
+            Err(_) => Err(ResolveError::InvalidRequirement {
+                package: package.name.clone().unwrap_or_default(),
+                dependency: name.into(),
+                requirement: requirement.into(),
+            }),
+        })
+        .collect()
+}
+
+/// Orders the packages such that dependencies come before their dependents, fails on cycles
+fn topological_order(
+    root: &str,
+    packages: &BTreeMap<String, DataPackage>,
+) -> Result<Vec<String>, ResolveError> {
+    fn visit(
+        name: &str,
+        packages: &BTreeMap<String, DataPackage>,
+        path: &mut Vec<String>,
+        done: &mut HashSet<String>,
+        order: &mut Vec<String>,
+    ) -> Result<(), ResolveError> {
+        if done.contains(name) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|el| el == name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(name.into());
+            return Err(ResolveError::Cycle(cycle));
+        }
+        path.push(name.into());
+        for (dependency, _) in packages[name].dependencies() {
+            visit(dependency, packages, path, done, order)?;
+        }
+        path.pop();
+        done.insert(name.into());
+        order.push(name.into());
+        Ok(())
+    }
+
+    let mut order = vec![];
+    visit(root, packages, &mut vec![], &mut HashSet::new(), &mut order)?;
+    Ok(order)
+}
+
+/// A consistent set of package versions that satisfies all requirements
+#[derive(Debug, Clone)]
+pub struct Resolution {
+    root: String,
+
+    packages: BTreeMap<String, DataPackage>,
+
+    /// package names, dependencies before their dependents
+    order: Vec<String>,
+}
+
+impl Resolution {
+    /// The packages in installation order, dependencies come first and the requested package last
+    pub fn install_order(&self) -> impl Iterator<Item = &DataPackage> {
+        self.order.iter().map(|name| &self.packages[name])
+    }
+
+    /// The dependency graph starting at the requested package
+    pub fn tree(&self) -> DependencyTree {
+        self.subtree(&self.root, None, &mut HashSet::new())
+    }
+
+    fn subtree(
+        &self,
+        name: &str,
+        requirement: Option<String>,
+        seen: &mut HashSet<String>,
+    ) -> DependencyTree {
+        let package = &self.packages[name];
+        let mut reval = DependencyTree {
+            name: name.into(),
+            version: package.version.clone().unwrap_or_default(),
+            requirement,
+            repeated: !seen.insert(name.into()),
+            dependencies: vec![],
+        };
+        if !reval.repeated {
+            reval.dependencies = package
+                .dependencies()
+                .map(|(dep, req)| self.subtree(dep, Some(req.into()), seen))
+                .collect();
+        }
+        reval
+    }
+}
+
+/// A node of the dependency graph, shared dependencies are expanded at their first occurrence
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct DependencyTree {
+    pub name: String,
+
+    /// the resolved version
+    pub version: String,
+
+    /// the requirement of the parent, None for the root
+    pub requirement: Option<String>,
+
+    /// true if the package is expanded elsewhere in the tree
+    pub repeated: bool,
+
+    pub dependencies: Vec<DependencyTree>,
+}
+
+/// The names of the packages that depend on the given package version
+pub fn dependents<'a>(
+    packages: impl IntoIterator<Item = &'a DataPackage>,
+    name: &str,
+    version: &str,
+) -> Vec<String> {
+    let version = Version::parse(version).ok();
+    let mut reval: Vec<String> = packages
+        .into_iter()
+        .filter(|package| {
+            package.dependencies().any(|(dep, req)| {
+                dep == name
+                    && match (VersionReq::parse(req), version.as_ref()) {
+                        (Ok(req), Some(version)) => req.matches(version),
+                        // be cautious if it cannot be decided
+                        _ => true,
+                    }
+            })
+        })
+        .filter_map(|package| package.name.clone())
+        .collect();
+    reval.sort();
+    reval.dedup();
+    reval
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::datapackage::{
+        DataPackageNotValidated, DataResourceNotValidated, DeltaDataPackageNotValidated,
+        ValidateData as _,
+    };
+
+    fn package(name: &str, version: &str, dependencies: &[(&str, &str)]) -> DataPackage {
+        let delta = DeltaDataPackageNotValidated {
+            category: "classification".into(),
+            classes: None,
+            training_count: None,
+            validation_count: None,
+            test_count: None,
+            input_shape: "1".into(),
+            mirror: None,
+            kind: None,
+            model: None,
+            dependencies: Some(
+                dependencies.iter().map(|(n, r)| (n.to_string(), r.to_string())).collect(),
+            ),
+        };
+        DataPackageNotValidated {
+            name: Some(name.into()),
+            version: Some(version.into()),
+            resources: vec![DataResourceNotValidated::default()],
+            delta: Some(delta),
+            ..Default::default()
+        }
+        .validate()
+        .unwrap()
+    }
+
+    fn resolved(resolution: &Resolution) -> Vec<(String, String)> {
+        resolution
+            .install_order()
+            .map(|dp| (dp.name.clone().unwrap(), dp.version.clone().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_newest_and_backtrack() {
+        let resolver = Resolver::new([
+            package("labels", "1.0.0", &[]),
+            package("labels", "1.2.0", &[]),
+            package("labels", "2.0.0", &[]),
+            package("tokenizer", "1.0.0", &[("labels", "^1")]),
+            // the newest tokenizer needs labels 3 which does not exist
+            package("tokenizer", "1.1.0", &[("labels", "^3")]),
+            package("model", "1.0.0", &[("labels", ">=1.1"), ("tokenizer", "^1")]),
+        ]);
+        let resolution = resolver.resolve("model", &VersionReq::STAR).unwrap();
+        let expected = [("labels", "1.2.0"), ("tokenizer", "1.0.0"), ("model", "1.0.0")];
+        let expected: Vec<(String, String)> =
+            expected.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect();
+        assert_eq!(resolved(&resolution), expected);
+
+        let tree = resolution.tree();
+        assert_eq!(tree.dependencies.len(), 2);
+        assert!(!tree.dependencies[0].repeated);
+        assert!(tree.dependencies[1].dependencies[0].repeated);
+    }
+
+    #[test]
+    fn test_conflict_and_cycle() {
+        let resolver = Resolver::new([
+            package("labels", "1.0.0", &[]),
+            package("labels", "2.0.0", &[]),
+            package("a", "1.0.0", &[("labels", "^1")]),
+            package("b", "1.0.0", &[("labels", "^2")]),
+            package("model", "1.0.0", &[("a", "*"), ("b", "*")]),
+            package("x", "1.0.0", &[("y", "*")]),
+            package("y", "1.0.0", &[("x", "*")]),
+        ]);
+        let err = resolver.resolve("model", &VersionReq::STAR).unwrap_err();
+        assert!(matches!(err, ResolveError::Conflict { ref name, .. } if name == "labels"));
+
+        let err = resolver.resolve("x", &VersionReq::STAR).unwrap_err();
+        assert_eq!(err, ResolveError::Cycle(vec!["x".into(), "y".into(), "x".into()]));
+
+        let err = resolver.resolve("missing", &VersionReq::STAR).unwrap_err();
+        assert_eq!(err, ResolveError::NotFound("missing".into()));
+
+        let (name, req) = parse_package_spec("labels@^1.0").unwrap();
+        assert_eq!(resolver.resolve(&name, &req).unwrap().tree().version, "1.0.0");
+        assert_eq!(
+            dependents([&package("a", "1.0.0", &[("labels", "^1")])], "labels", "2.0.0").len(),
+            0
+        );
+    }
+}
@@ -2,17 +2,30 @@
 //!
 //! An endpoint has to implement an autogenerated trait of grpc.
 
+use crate::datapackage::DataPackage;
+use crate::model::pb_mapper::DownloadRangeMapper as _;
 use crate::model::pb_mapper::FieldMapper;
 use crate::model::pb_mapper::FilterMapper as _;
 use crate::model::pb_mapper::PagationMapper as _;
-use crate::model::{FilterSettings, SortSettings};
-use crate::storage::MetaDataSource;
+use crate::model::pb_mapper::SortMapper as _;
+use crate::model::{DateRange, FieldSettings, MetaDataField, PagationSettings, SortSettings};
+use crate::storage::blob::{BlobStore, mirror::Mirror};
+use crate::storage::stats::DownloadStats;
+use crate::storage::{MetaDataSource, paginate};
 
 use super::nebula_package_query_server::NebulaPackageQuery;
-use super::{ListPackagesRequest, PackageInfo, PackageList, PackageRequest, SearchPackagesRequest};
+use super::preview::PreviewRenderer;
+use super::{
+    DownloadCount, ListPackagesRequest, PackageInfo, PackageList, PackageRequest,
+    SearchPackagesRequest, ServerInfo, ServerInfoRequest, SortOption,
+};
+
+use futures::future::join_all;
+use std::cmp::Reverse;
+use std::sync::Arc;
 
 use tonic::{Request, Response, Status};
-use tracing::instrument;
+use tracing::{instrument, warn};
 
 #[derive(Debug)]
 pub struct NebulaPackageQueryMockImpl<T>
@@ -20,17 +33,153 @@ where
     T: MetaDataSource + Send + Sync,
 {
     inner_ds: T,
+
+    server_info: ServerInfo,
+
+    blob_store: Option<Arc<dyn BlobStore>>,
+
+    mirror: Option<Arc<Mirror>>,
+
+    stats: Option<Arc<DownloadStats>>,
+
+    previews: PreviewRenderer,
 }
 
 impl<T> NebulaPackageQueryMockImpl<T>
 where
     T: MetaDataSource + Send + Sync,
 {
-    pub fn new(ds: T) -> Self {
-        Self { inner_ds: ds }
+    pub fn new(ds: T, server_info: ServerInfo) -> Self {
+        Self {
+            inner_ds: ds,
+            server_info,
+            blob_store: None,
+            mirror: None,
+            stats: None,
+            previews: PreviewRenderer::default(),
+        }
+    }
+
+    /// Uses the blob store to host resources that are referenced as blobs
+    pub fn with_blob_store(mut self, blob_store: Arc<dyn BlobStore>) -> Self {
+        self.blob_store = Some(blob_store);
+        self
+    }
+
+    /// Serves remote resources through the pull-through mirror
+    pub fn with_mirror(mut self, mirror: Arc<Mirror>) -> Self {
+        self.mirror = Some(mirror);
+        self
+    }
+
+    /// Counts downloads and reports the counts in the package infos
+    pub fn with_download_stats(mut self, stats: Arc<DownloadStats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    pub fn data_source(&self) -> &T {
+        &self.inner_ds
+    }
+
+    pub fn blob_store(&self) -> Option<&Arc<dyn BlobStore>> {
+        self.blob_store.as_ref()
+    }
+
+    pub fn mirror(&self) -> Option<&Arc<Mirror>> {
+        self.mirror.as_ref()
+    }
+
+    pub fn download_stats(&self) -> Option<&Arc<DownloadStats>> {
+        self.stats.as_ref()
+    }
+
+    /// Records a download of the package, if downloads are counted
+    pub fn record_download(&self, package: &DataPackage) {
+        let (Some(stats), Some(name)) = (self.stats.as_ref(), package.name.as_ref()) else {
+            return;
+        };
+        let version = package.version.as_deref().unwrap_or_default();
+        if let Err(err) = stats.record(name, version) {
+            warn!("Could not record the download of '{}': {}", name, err);
+        }
+    }
+
+    /// Downloads of all versions of the package within the range
+    fn downloads(&self, package: &DataPackage, range: &DateRange) -> u64 {
+        match (self.stats.as_ref(), package.name.as_ref()) {
+            (Some(stats), Some(name)) => stats.downloads(name, None, range),
+            _ => 0,
+        }
+    }
+
+    /// Orders by download count descending, ties are ordered by name and version
+    fn sort_by_downloads(&self, packages: &mut [DataPackage], range: &DateRange) {
+        packages.sort_by_cached_key(|dp| {
+            (Reverse(self.downloads(dp, range)), dp.name.clone(), dp.version.clone())
+        });
+    }
+
+    async fn package_info(
+        &self,
+        package: DataPackage,
+        fields: &FieldSettings,
+        range: &DateRange,
+    ) -> PackageInfo {
+        let json = if fields.contains(&MetaDataField::DataPackage) {
+            Some(serde_json::to_string(&package.clone().into_inner()).unwrap())
+        } else {
+            None
+        };
+        let previews = if fields.contains(&MetaDataField::PreviewImages) {
+            let folder = match (package.name.as_ref(), package.version.as_ref()) {
+                (Some(name), Some(version)) => self.inner_ds.package_folder(name, version).await,
+                _ => None,
+            };
+            self.previews.previews(&package, folder.as_deref()).await
+        } else {
+            vec![]
+        };
+        let (name, version) = (package.name.clone(), package.version.clone());
+        let mut reval: PackageInfo = package.into();
+        reval.datapackage_json = json;
+        reval.preview_images = previews;
+
+        if let (Some(stats), Some(name)) = (self.stats.as_ref(), name) {
+            reval.downloads = stats.downloads(&name, None, range);
+            reval.version_downloads =
+                version.map(|v| stats.downloads(&name, Some(&v), range)).unwrap_or_default();
+            if fields.contains(&MetaDataField::DownloadHistory) {
+                reval.download_history = stats
+                    .history(&name, range)
+                    .into_iter()
+                    .map(|(day, count)| DownloadCount {
+                        date: day.format(DateRange::FORMAT).to_string(),
+                        count,
+                    })
+                    .collect();
+            }
+        }
+        reval
     }
 }
 
+fn invalid_package_type(err: Box<dyn std::error::Error>) -> Status {
+    Status::invalid_argument(format!("Invalid package type: {}", err))
+}
+
+fn invalid_filter(err: Box<dyn std::error::Error>) -> Status {
+    Status::invalid_argument(format!("Invalid filter: {}", err))
+}
+
+fn invalid_sort(err: Box<dyn std::error::Error>) -> Status {
+    Status::invalid_argument(format!("Invalid sort option: {}", err))
+}
+
+fn invalid_download_range(err: Box<dyn std::error::Error>) -> Status {
+    Status::invalid_argument(format!("Invalid download range, expected YYYYMMDD: {}", err))
+}
+
 #[tonic::async_trait]
 impl<T> NebulaPackageQuery for NebulaPackageQueryMockImpl<T>
 where
@@ -43,11 +192,26 @@ where
     ) -> Result<Response<PackageInfo>, Status> {
         let mut package = self
             .inner_ds
-            .get_package(&request.get_ref().search_query, request.get_ref().as_filter().unwrap())
+            .get_package_by_name(
+                &request.get_ref().search_query,
+                request.get_ref().as_filter().map_err(invalid_package_type)?,
+            )
             .await;
 
-        let pi: PackageInfo = package.take().unwrap().into();
-        Ok(Response::new(pi))
+        let mut fields = FieldSettings::default();
+        fields.push(MetaDataField::DataPackage);
+        fields.push(MetaDataField::DownloadHistory);
+        fields.push(MetaDataField::PreviewImages);
+        match package.take() {
+            Some(package) => {
+                let info = self.package_info(package, &fields, &DateRange::default()).await;
+                Ok(Response::new(info))
+            }
+            None => Err(Status::not_found(format!(
+                "Package '{}' not found",
+                request.get_ref().search_query
+            ))),
+        }
     }
 
     #[instrument(name = "List Packages", skip(self))]
@@ -57,27 +221,23 @@ where
     ) -> Result<Response<PackageList>, Status> {
         let pagation = request.get_ref().as_pagation().unwrap();
         let fields = request.get_ref().as_fields().unwrap();
-        let filter = FilterSettings::default();
+        let filter = request.get_ref().as_filter().map_err(invalid_package_type)?;
         let sort = SortSettings::default();
+        let range = request.get_ref().as_download_range().map_err(invalid_download_range)?;
 
-        let wants_json = fields.contains(&crate::model::MetaDataField::DataPackage);
-
-        let body = self.inner_ds.list_packages(sort, filter, pagation, fields).await;
+        let body = if request.get_ref().sort == Some(SortOption::Downloads as i32) {
+            let unlimited = PagationSettings::unlimited();
+            let mut all =
+                self.inner_ds.list_packages(sort, filter, unlimited, fields.clone()).await;
+            self.sort_by_downloads(&mut all, &range);
+            paginate(all, pagation)
+        } else {
+            self.inner_ds.list_packages(sort, filter, pagation, fields.clone()).await
+        };
         let len = body.len();
         let body = PackageList {
-            packages: body
-                .into_iter()
-                .map(|el| {
-                    let json = if wants_json {
-                        Some(serde_json::to_string(&el.clone().into_inner()).unwrap())
-                    } else {
-                        None
-                    };
-                    let mut res: PackageInfo = el.into();
-                    res.datapackage_json = json;
-                    res
-                })
-                .collect(),
+            packages: join_all(body.into_iter().map(|el| self.package_info(el, &fields, &range)))
+                .await,
             total_count: len as i32,
             limit: None,
             offset: None,
@@ -89,8 +249,188 @@ where
     #[instrument(name = "Search Packages", skip(self))]
     async fn search_packages(
         &self,
-        _request: Request<SearchPackagesRequest>,
+        request: Request<SearchPackagesRequest>,
     ) -> Result<Response<PackageList>, Status> {
-        Err(Status::internal("not implemented"))
+        let request = request.get_ref();
+        let pagation = request.as_pagation().unwrap();
+        let fields = request.as_fields().unwrap();
+        let filter = request.as_filter().map_err(invalid_filter)?;
+        let sort = request.as_sort().map_err(invalid_sort)?;
+        let range = request.as_download_range().map_err(invalid_download_range)?;
+
+        // downloads are known by the registry only, therefore the source returns all matches
+        let mut packages = self
+            .inner_ds
+            .search_package(
+                &request.search_query,
+                sort.clone(),
+                filter,
+                PagationSettings::unlimited(),
+            )
+            .await;
+        let (min, max) = (request.min_downloads, request.max_downloads);
+        if min.is_some() || max.is_some() {
+            packages.retain(|dp| {
+                let downloads = self.downloads(dp, &range);
+                min.is_none_or(|min| downloads >= min.max(0) as u64)
+                    && max.is_none_or(|max| downloads <= max.max(0) as u64)
+            });
+        }
+        sort.sort(&mut packages, |dp| self.downloads(dp, &range));
+
+        let total_count = packages.len() as i32;
+        let body = PackageList {
+            packages: join_all(
+                paginate(packages, pagation)
+                    .into_iter()
+                    .map(|el| self.package_info(el, &fields, &range)),
+            )
+            .await,
+            total_count,
+            limit: request.limit,
+            offset: request.offset,
+        };
+        Ok(Response::new(body))
+    }
+
+    #[instrument(name = "Get Server Info", skip(self))]
+    async fn get_server_info(
+        &self,
+        _request: Request<ServerInfoRequest>,
+    ) -> Result<Response<ServerInfo>, Status> {
+        Ok(Response::new(self.server_info.clone()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{NaiveDate, Utc};
+
+    use super::*;
+    use crate::{
+        datapackage::DataPackageNotValidated,
+        registry::{DateRange as PbDateRange, SearchKind, SortParameter},
+        storage::root_folder::RootFolderSource,
+    };
+
+    fn registry(stats: &str) -> NebulaPackageQueryMockImpl<RootFolderSource> {
+        let _ = std::fs::remove_file(stats);
+        let ds = RootFolderSource::new_from_folder("../nebula_registry/data".into());
+        let stats = Arc::new(DownloadStats::open(stats).unwrap());
+        let day = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        stats.record_at("iris-classical", "1.0.0", day.and_utc()).unwrap();
+        stats.record_at("mobilenet_v3_tf2", "1.0.0", day.and_utc()).unwrap();
+        stats.record("mobilenet_v3_tf2", "1.0.0").unwrap();
+        NebulaPackageQueryMockImpl::new(ds, ServerInfo::default()).with_download_stats(stats)
+    }
+
+    fn names(list: PackageList) -> Vec<(String, u64)> {
+        list.packages.into_iter().map(|pi| (pi.name, pi.downloads)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_sort_and_filter_by_downloads() {
+        let registry = registry("tmp_endpoint_downloads.jsonl");
+
+        let request =
+            ListPackagesRequest { sort: Some(SortOption::Downloads as i32), ..Default::default() };
+        let list = registry.list_packages(Request::new(request)).await.unwrap().into_inner();
+        assert_eq!(
+            names(list),
+            vec![
+                ("mobilenet_v3_tf2".to_string(), 2),
+                ("iris-classical".to_string(), 1),
+                ("cifar-10-binary".to_string(), 0)
+            ]
+        );
+
+        // only downloads since yesterday are counted
+        let yesterday = (Utc::now() - chrono::Duration::days(1)).format(DateRange::FORMAT);
+        let request = SearchPackagesRequest {
+            min_downloads: Some(1),
+            download_range: Some(PbDateRange { start: Some(yesterday.to_string()), end: None }),
+            ..Default::default()
+        };
+        let list = registry.search_packages(Request::new(request)).await.unwrap().into_inner();
+        assert_eq!(list.total_count, 1);
+        assert_eq!(names(list), vec![("mobilenet_v3_tf2".to_string(), 1)]);
+
+        let request = SearchPackagesRequest {
+            download_range: Some(PbDateRange { start: Some("2025-01-01".into()), end: None }),
+            ..Default::default()
+        };
+        let status = registry.search_packages(Request::new(request)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        std::fs::remove_file("tmp_endpoint_downloads.jsonl").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_history() {
+        let registry = registry("tmp_endpoint_history.jsonl");
+
+        let request =
+            PackageRequest { search_query: "mobilenet_v3_tf2".into(), package_type: None };
+        let info = registry.get_package_info(Request::new(request)).await.unwrap().into_inner();
+        assert_eq!(info.downloads, 2);
+        assert_eq!(info.version_downloads, 2);
+        assert_eq!(info.download_history.len(), 2);
+        assert_eq!(info.download_history[0].date, "20250101");
+
+        std::fs::remove_file("tmp_endpoint_history.jsonl").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_search_filters() {
+        let registry = registry("tmp_endpoint_search.jsonl");
+
+        // multi level sort with direction and delta metadata filters
+        let request = SearchPackagesRequest {
+            sort_parameters: vec![SortParameter {
+                sort_by: SortOption::Name as i32,
+                descending: Some(true),
+                params: vec![],
+            }],
+            category: Some("Classification".into()),
+            ..Default::default()
+        };
+        let list = registry.search_packages(Request::new(request)).await.unwrap().into_inner();
+        let expected = ["mobilenet_v3_tf2", "iris-classical", "cifar-10-binary"];
+        assert_eq!(list.packages.iter().map(|pi| pi.name.as_str()).collect::<Vec<_>>(), expected);
+
+        let request = SearchPackagesRequest {
+            classes: Some(10),
+            kind: Some(SearchKind::SubstrPackageName as i32),
+            ..Default::default()
+        };
+        let list = registry.search_packages(Request::new(request)).await.unwrap().into_inner();
+        assert_eq!(names(list), vec![("cifar-10-binary".to_string(), 0)]);
+
+        let request = SearchPackagesRequest { kind: Some(42), ..Default::default() };
+        let status = registry.search_packages(Request::new(request)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        std::fs::remove_file("tmp_endpoint_search.jsonl").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_package_info_metadata() {
+        let registry = registry("tmp_endpoint_info.jsonl");
+
+        let request =
+            PackageRequest { search_query: "mobilenet_v3_tf2".into(), package_type: None };
+        let info = registry.get_package_info(Request::new(request)).await.unwrap().into_inner();
+        assert!(info.datapackage_json.is_some());
+        assert_eq!(info.licenses.len(), 1);
+        assert_eq!(info.licenses[0].name, "APACHE 2.0");
+        assert_eq!(info.license, "APACHE 2.0");
+        let delta = info.delta.as_ref().unwrap();
+        assert_eq!((delta.classes, delta.input_shape.as_str()), (Some(1000), "224x224x3"));
+
+        let descriptor = DataPackageNotValidated::from(info);
+        assert_eq!(descriptor.licenses[0].path, "https://www.apache.org/licenses/LICENSE-2.0");
+        assert_eq!(descriptor.delta.unwrap().category, "classification");
+
+        std::fs::remove_file("tmp_endpoint_info.jsonl").unwrap();
     }
 }
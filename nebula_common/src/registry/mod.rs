@@ -6,4 +6,6 @@ pub use super::nebula_proto::nebula_package_query_server::NebulaPackageQueryServ
 pub(crate) use super::nebula_proto::*;
 
 pub mod endpoints;
+pub mod preview;
+pub mod replication;
 pub use endpoints::NebulaPackageQueryMockImpl;
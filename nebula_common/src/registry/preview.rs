@@ -0,0 +1,176 @@
+//! Preview images of packages
+//!
+//! A package has previews from two places: the `image` field of the descriptor and image files in
+//! the folder `preview` next to the descriptor. Remote images are returned as their url. Files
+//! hosted by the registry are scaled down to thumbnails and inlined as base64 data urls, such
+//! that clients can show them without further requests.
+
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use color_eyre::eyre::Report;
+use image::{ImageFormat, ImageReader};
+use tracing::warn;
+
+use crate::datapackage::DataPackage;
+
+/// folder next to the descriptor that contains bundled preview images
+pub const PREVIEW_FOLDER: &str = "preview";
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg"];
+
+/// Renders thumbnails of bundled images and caches them until the file changes
+#[derive(Debug)]
+pub struct PreviewRenderer {
+    /// the longer side of a thumbnail in pixels
+    max_dimension: u32,
+
+    cache: Mutex<HashMap<PathBuf, (Option<SystemTime>, String)>>,
+}
+
+impl Default for PreviewRenderer {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl PreviewRenderer {
+    pub fn new(max_dimension: u32) -> Self {
+        PreviewRenderer { max_dimension, cache: Mutex::new(HashMap::new()) }
+    }
+
+    /// Collects the previews of a package, folder is the folder of the descriptor if it is hosted
+    pub async fn previews(&self, package: &DataPackage, folder: Option<&Path>) -> Vec<String> {
+        let mut files = vec![];
+        let mut reval = vec![];
+        if let Some(image) = package.image.as_ref() {
+            if is_remote(image) {
+                reval.push(image.clone());
+            } else if let Some(folder) = folder.filter(|_| is_relative(image)) {
+                files.push(folder.join(image));
+            } else {
+                warn!(
+                    "Image '{}' of '{}' is not hosted",
+                    image,
+                    package.name.as_deref().unwrap_or_default()
+                );
+            }
+        }
+        if let Some(folder) = folder {
+            files.extend(bundled_images(&folder.join(PREVIEW_FOLDER)));
+        }
+
+        for file in files {
+            match self.thumbnail(&file).await {
+                Ok(thumbnail) => reval.push(thumbnail),
+                Err(err) => warn!("No preview of '{}': {}", file.display(), err),
+            }
+        }
+        reval
+    }
+
+    /// The thumbnail of an image file as data url
+    pub async fn thumbnail(&self, file: &Path) -> Result<String, Report> {
+        let modified = std::fs::metadata(file)?.modified().ok();
+        {
+            let cache = self.cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some((_, thumbnail)) = cache.get(file).filter(|(at, _)| *at == modified) {
+                return Ok(thumbnail.clone());
+            }
+        }
+
+        let (path, max) = (file.to_path_buf(), self.max_dimension);
+        let thumbnail = tokio::task::spawn_blocking(move || render(&path, max)).await??;
+        let mut cache = self.cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        cache.insert(file.to_path_buf(), (modified, thumbnail.clone()));
+        Ok(thumbnail)
+    }
+}
+
+fn is_remote(image: &str) -> bool {
+    ["http://", "https://", "data:"].iter().any(|scheme| image.starts_with(scheme))
+}
+
+/// Relative paths must not leave the folder of the package
+fn is_relative(path: &str) -> bool {
+    Path::new(path).components().all(|c| matches!(c, std::path::Component::Normal(_)))
+}
+
+/// The image files of the folder ordered by name, empty if the folder does not exist
+fn bundled_images(folder: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(folder) else {
+        return vec![];
+    };
+    let mut reval: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        })
+        .collect();
+    reval.sort();
+    reval
+}
+
+fn render(file: &Path, max_dimension: u32) -> Result<String, Report> {
+    let image = ImageReader::open(file)?.with_guessed_format()?.decode()?;
+    let thumbnail = if image.width() > max_dimension || image.height() > max_dimension {
+        image.thumbnail(max_dimension, max_dimension)
+    } else {
+        image
+    };
+    let mut png = Cursor::new(vec![]);
+    thumbnail.write_to(&mut png, ImageFormat::Png)?;
+    Ok(format!("data:image/png;base64,{}", STANDARD.encode(png.into_inner())))
+}
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, RgbImage};
+
+    use super::*;
+    use crate::datapackage::{
+        DataPackageNotValidated, DataResourceNotValidated, ValidateData as _,
+    };
+
+    #[tokio::test]
+    async fn test_previews() {
+        let folder = PathBuf::from("tmp_preview");
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(folder.join(PREVIEW_FOLDER)).unwrap();
+        let image = DynamicImage::ImageRgb8(RgbImage::new(1024, 512));
+        image.save(folder.join("cover.png")).unwrap();
+        image.save(folder.join(PREVIEW_FOLDER).join("sample.jpg")).unwrap();
+        std::fs::write(folder.join(PREVIEW_FOLDER).join("notes.txt"), "no image").unwrap();
+
+        let package = |image: &str| {
+            DataPackageNotValidated {
+                image: Some(image.into()),
+                resources: vec![DataResourceNotValidated::default()],
+                ..Default::default()
+            }
+            .validate()
+            .unwrap()
+        };
+        let renderer = PreviewRenderer::new(64);
+
+        let previews = renderer.previews(&package("cover.png"), Some(&folder)).await;
+        assert_eq!(previews.len(), 2);
+        let png = STANDARD.decode(previews[0].strip_prefix("data:image/png;base64,").unwrap());
+        let thumbnail = image::load_from_memory(&png.unwrap()).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (64, 32));
+
+        let url = "https://example.com/cover.png";
+        let previews = renderer.previews(&package(url), None).await;
+        assert_eq!(previews, vec![url.to_string()]);
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
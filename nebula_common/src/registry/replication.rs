@@ -0,0 +1,315 @@
+//! Replicates the packages of an upstream registry into a local replica
+//!
+//! The replication pulls the descriptors with the same protocol as `nebula sync`, stores them in
+//! a separate [RootFolderSource] and removes packages that vanished upstream. Resources hosted by
+//! the upstream registry are either copied into the local blob store or referenced by their
+//! download url at the upstream gateway. The replica is served next to the local packages by a
+//! [crate::storage::federated::FederatedSource].
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use color_eyre::eyre::{Report, eyre};
+use futures::TryStreamExt;
+use tokio::task::JoinHandle;
+use tokio_util::io::StreamReader;
+use tracing::{error, info, warn};
+
+use crate::{
+    client::{RegistryClient, init_client, list_all_packages},
+    configuration::registry::ReplicationSettings,
+    datapackage::{DataPackage, DataPackageNotValidated, PathSingleOrVec, ValidateData},
+    storage::{
+        MetaDataSource,
+        blob::{BLOB_REFERENCE_PREFIX, BlobDigest, BlobStore},
+        root_folder::RootFolderSource,
+    },
+};
+
+use super::{FieldOptions, PackageInfo};
+
+/// The changes applied by [Replicator::replicate]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReplicationReport {
+    pub added: Vec<String>,
+
+    pub updated: Vec<String>,
+
+    pub removed: Vec<String>,
+
+    pub unchanged: usize,
+
+    /// packages that could not be replicated with the reason
+    pub failed: Vec<(String, String)>,
+}
+
+/// Keeps a replica in sync with an upstream registry
+#[derive(Debug)]
+pub struct Replicator {
+    settings: ReplicationSettings,
+
+    replica: RootFolderSource,
+
+    blobs: Option<Arc<dyn BlobStore>>,
+
+    client: Option<RegistryClient>,
+
+    http: reqwest::Client,
+
+    /// the upstream descriptors that are replicated, used to skip unchanged packages
+    state: HashMap<String, BlobDigest>,
+}
+
+/// `name@version` identifies a replicated package
+fn key(name: &str, version: &str) -> String {
+    format!("{}@{}", name, version)
+}
+
+impl Replicator {
+    pub fn new(
+        settings: ReplicationSettings,
+        replica: RootFolderSource,
+        blobs: Option<Arc<dyn BlobStore>>,
+    ) -> Result<Self, Report> {
+        if settings.blobs && (blobs.is_none() || settings.gateway_url.is_none()) {
+            return Err(eyre!(
+                "Replicating blobs needs a blob store and the upstream 'gateway_url'"
+            ));
+        }
+
+        let state = match std::fs::read_to_string(Self::state_path(&replica)) {
+            Ok(json) => serde_json::from_str::<HashMap<String, String>>(&json)?
+                .into_iter()
+                .filter_map(|(key, digest)| digest.parse().ok().map(|digest| (key, digest)))
+                .collect(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Replicator {
+            settings,
+            replica,
+            blobs,
+            client: None,
+            http: reqwest::Client::new(),
+            state,
+        })
+    }
+
+    fn state_path(replica: &RootFolderSource) -> PathBuf {
+        replica.path().join("replication.json")
+    }
+
+    fn save_state(&self) -> Result<(), Report> {
+        let plain: HashMap<&String, String> =
+            self.state.iter().map(|(key, digest)| (key, digest.to_string())).collect();
+        let path = Self::state_path(&self.replica);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(&plain)?)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Pulls the descriptors of the upstream registry and applies the changes to the replica
+    pub async fn replicate(&mut self) -> Result<ReplicationReport, Report> {
+        let upstream = match self.list_upstream().await {
+            Ok(upstream) => upstream,
+            Err(err) => {
+                // reconnect on the next run
+                self.client = None;
+                return Err(err);
+            }
+        };
+
+        let mut report = ReplicationReport::default();
+        let mut seen = HashSet::new();
+        for pi in upstream {
+            let key = key(&pi.name, &pi.version);
+            seen.insert(key.clone());
+            let digest = BlobDigest::of_bytes(pi.datapackage_json().as_bytes());
+            // a new version of a replicated package is added, not updated
+            let known = self.replica.get_package_version(&pi.name, &pi.version).await.is_some();
+            if known && self.state.get(&key) == Some(&digest) {
+                report.unchanged += 1;
+                continue;
+            }
+
+            match self.replicate_package(pi).await {
+                Ok(()) => {
+                    self.state.insert(key.clone(), digest);
+                    if known {
+                        report.updated.push(key);
+                    } else {
+                        report.added.push(key);
+                    }
+                }
+                Err(err) => {
+                    warn!("Could not replicate '{}': {}", key, err);
+                    report.failed.push((key, err.to_string()));
+                }
+            }
+        }
+
+        let vanished: Vec<String> =
+            self.state.keys().filter(|key| !seen.contains(*key)).cloned().collect();
+        for key in vanished {
+            if let Some((name, version)) = key.split_once('@') {
+                self.replica.remove_package_metadata(name, version)?;
+            }
+            self.state.remove(&key);
+            report.removed.push(key);
+        }
+
+        self.save_state()?;
+        Ok(report)
+    }
+
+    async fn list_upstream(&mut self) -> Result<Vec<PackageInfo>, Report> {
+        if self.client.is_none() {
+            let settings = &self.settings;
+            match init_client(&settings.host, settings.port, &settings.connection).await {
+                Ok(client) => self.client = Some(client),
+                Err(err) => {
+                    return Err(eyre!(
+                        "Upstream registry at '{}:{}' failed: {}",
+                        settings.host,
+                        settings.port,
+                        err
+                    ));
+                }
+            }
+        }
+        let fo = FieldOptions {
+            include_datapackage_json: true,
+            include_preview_images: false,
+            include_download_history: false,
+        };
+        let client = self.client.as_mut().expect("connected above");
+        list_all_packages(Some(fo), client).await
+    }
+
+    async fn replicate_package(&mut self, pi: PackageInfo) -> Result<(), Report> {
+        let name = pi.name.clone();
+        let mut package = DataPackage::try_from(pi)?.into_inner();
+        self.localize_resources(&name, &mut package).await?;
+        let package = package.validate().map_err(|err| eyre!(err.to_string()))?;
+        self.replica.put_package_metadata(&package).await
+    }
+
+    /// Rewrites the paths of resources that are hosted by the upstream registry
+    ///
+    /// Remote urls stay untouched. Blobs and files next to the upstream descriptor are copied into
+    /// the blob store if blobs are replicated, otherwise they reference the upstream gateway.
+    async fn localize_resources(
+        &self,
+        name: &str,
+        package: &mut DataPackageNotValidated,
+    ) -> Result<(), Report> {
+        for dr in package.resources.iter_mut() {
+            let path = match dr.path.take() {
+                Some(PathSingleOrVec::Single(path)) => {
+                    let mut paths = self.localize_paths(name, &dr.name, vec![path]).await?;
+                    PathSingleOrVec::Single(paths.remove(0))
+                }
+                Some(PathSingleOrVec::Vec(paths)) => {
+                    PathSingleOrVec::Vec(self.localize_paths(name, &dr.name, paths).await?)
+                }
+                None => continue,
+            };
+            dr.path = Some(path);
+        }
+        Ok(())
+    }
+
+    async fn localize_paths(
+        &self,
+        name: &str,
+        resource: &str,
+        paths: Vec<String>,
+    ) -> Result<Vec<String>, Report> {
+        let mut reval = Vec::with_capacity(paths.len());
+        for (index, path) in paths.into_iter().enumerate() {
+            if path.contains("://") {
+                reval.push(path);
+                continue;
+            }
+            let Some(gateway) = self.settings.gateway_url.as_ref() else {
+                warn!("'{}' of '{}' is hosted upstream, but no 'gateway_url' is set", path, name);
+                reval.push(path);
+                continue;
+            };
+            let url = format!(
+                "{}/v1/packages/{}/resources/{}?index={}",
+                gateway.trim_end_matches('/'),
+                name,
+                resource,
+                index
+            );
+
+            reval.push(match (self.settings.blobs, self.blobs.as_ref()) {
+                (true, Some(blobs)) => {
+                    let expected = BlobDigest::from_reference(&path);
+                    self.copy_blob(blobs.as_ref(), &url, expected).await?.reference()
+                }
+                _ => url,
+            });
+        }
+        Ok(reval)
+    }
+
+    /// Downloads a resource from the upstream gateway into the blob store, unless it is stored
+    async fn copy_blob(
+        &self,
+        blobs: &dyn BlobStore,
+        url: &str,
+        expected: Option<BlobDigest>,
+    ) -> Result<BlobDigest, Report> {
+        if let Some(digest) = expected {
+            if blobs.stat(&digest).await?.is_some() {
+                return Ok(digest);
+            }
+        }
+
+        let response = self.http.get(url).send().await?.error_for_status()?;
+        let stream = response.bytes_stream().map_err(std::io::Error::other);
+        let meta = blobs.put(Box::pin(StreamReader::new(stream))).await?;
+        match expected {
+            Some(digest) if digest != meta.digest => Err(eyre!(
+                "'{}' has the digest {}, but upstream references {}{}",
+                url,
+                meta.digest,
+                BLOB_REFERENCE_PREFIX,
+                digest
+            )),
+            _ => Ok(meta.digest),
+        }
+    }
+}
+
+/// Runs the replication periodically as a task of the server runtime, the first run starts
+/// immediately
+pub fn spawn_replication(mut replicator: Replicator, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            match replicator.replicate().await {
+                Ok(report) => info!(
+                    "Replication: {} added, {} updated, {} removed, {} unchanged, {} failed",
+                    report.added.len(),
+                    report.updated.len(),
+                    report.removed.len(),
+                    report.unchanged,
+                    report.failed.len()
+                ),
+                // the replica keeps serving the last state
+                Err(err) => error!("Replication failed: {}", err),
+            }
+        }
+    })
+}
@@ -0,0 +1,181 @@
+//! Blob store on the local filesystem
+
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use color_eyre::eyre::{Report, eyre};
+use tokio::io::AsyncSeekExt;
+use uuid::Uuid;
+
+use super::{BlobDigest, BlobMeta, BlobReader, BlobStore, copy_hashed};
+
+/// Stores blobs in `<root>/sha256/<fan-out>/<hex>`
+///
+/// Content is written to `<root>/tmp` first and renamed into place, so readers never see a
+/// partially written blob.
+#[derive(Debug, Clone)]
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self, Report> {
+        let root = root.into();
+        std::fs::create_dir_all(root.join("sha256"))?;
+        std::fs::create_dir_all(root.join("tmp"))?;
+        Ok(LocalBlobStore { root })
+    }
+
+    pub fn path_of(&self, digest: &BlobDigest) -> PathBuf {
+        self.root.join(digest.location())
+    }
+
+    async fn meta_of(path: &Path, digest: BlobDigest) -> Result<Option<BlobMeta>, Report> {
+        match tokio::fs::metadata(path).await {
+            Ok(meta) => {
+                Ok(Some(BlobMeta { digest, size: meta.len(), modified: meta.modified().ok() }))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn put(&self, mut content: BlobReader) -> Result<BlobMeta, Report> {
+        let tmp = self.root.join("tmp").join(Uuid::new_v4().to_string());
+        let mut file = tokio::fs::File::create(&tmp).await?;
+        let copied = copy_hashed(&mut content, &mut file).await;
+        drop(file);
+        let (digest, _) = match copied {
+            Ok(el) => el,
+            Err(err) => {
+                let _ = tokio::fs::remove_file(&tmp).await;
+                return Err(err.into());
+            }
+        };
+
+        let target = self.path_of(&digest);
+        if target.is_file() {
+            // deduplicated, the content is already stored
+            tokio::fs::remove_file(&tmp).await?;
+        } else {
+            if let Some(parent) = target.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::rename(&tmp, &target).await?;
+        }
+
+        Self::meta_of(&target, digest)
+            .await?
+            .ok_or_else(|| eyre!("Blob {} vanished while it was stored", digest))
+    }
+
+    async fn get(&self, digest: &BlobDigest) -> Result<Option<BlobReader>, Report> {
+        match tokio::fs::File::open(self.path_of(digest)).await {
+            Ok(file) => Ok(Some(Box::pin(file))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn get_from(
+        &self,
+        digest: &BlobDigest,
+        offset: u64,
+    ) -> Result<Option<BlobReader>, Report> {
+        let mut file = match tokio::fs::File::open(self.path_of(digest)).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+        Ok(Some(Box::pin(file)))
+    }
+
+    async fn stat(&self, digest: &BlobDigest) -> Result<Option<BlobMeta>, Report> {
+        Self::meta_of(&self.path_of(digest), *digest).await
+    }
+
+    async fn delete(&self, digest: &BlobDigest) -> Result<bool, Report> {
+        match tokio::fs::remove_file(self.path_of(digest)).await {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<BlobMeta>, Report> {
+        let mut reval = vec![];
+        let mut fan_outs = tokio::fs::read_dir(self.root.join("sha256")).await?;
+        while let Some(fan_out) = fan_outs.next_entry().await? {
+            if !fan_out.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut blobs = tokio::fs::read_dir(fan_out.path()).await?;
+            while let Some(blob) = blobs.next_entry().await? {
+                let name = blob.file_name();
+                let Some(digest) = name.to_str().and_then(BlobDigest::from_hex) else {
+                    continue;
+                };
+                if let Some(meta) = Self::meta_of(&blob.path(), digest).await? {
+                    reval.push(meta);
+                }
+            }
+        }
+        Ok(reval)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashSet, time::Duration};
+
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::storage::blob::collect_garbage;
+
+    #[tokio::test]
+    async fn test_local_put_dedupe_and_gc() {
+        let root = PathBuf::from("tmp_blobs_local");
+        let _ = std::fs::remove_dir_all(&root);
+        let store = LocalBlobStore::new(&root).unwrap();
+
+        let a = store.put(Box::pin(&b"content a"[..])).await.unwrap();
+        let again = store.put(Box::pin(&b"content a"[..])).await.unwrap();
+        let b = store.put(Box::pin(&b"content b"[..])).await.unwrap();
+        assert_eq!(a.digest, again.digest);
+        assert_eq!(a.size, 9);
+        assert_eq!(store.list().await.unwrap().len(), 2);
+        assert_eq!(std::fs::read_dir(root.join("tmp")).unwrap().count(), 0);
+
+        let mut content = String::new();
+        store.get(&a.digest).await.unwrap().unwrap().read_to_string(&mut content).await.unwrap();
+        assert_eq!(content, "content a");
+        let mut rest = String::new();
+        store
+            .get_from(&a.digest, 8)
+            .await
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut rest)
+            .await
+            .unwrap();
+        assert_eq!(rest, "a");
+
+        let referenced = HashSet::from([a.digest]);
+        let report = collect_garbage(&store, &referenced, Duration::ZERO, true).await.unwrap();
+        assert_eq!(report.deleted, vec![b.digest]);
+        assert!(store.stat(&b.digest).await.unwrap().is_some());
+
+        let report = collect_garbage(&store, &referenced, Duration::ZERO, false).await.unwrap();
+        assert_eq!(report.deleted, vec![b.digest]);
+        assert_eq!(report.kept, 1);
+        assert!(store.stat(&b.digest).await.unwrap().is_none());
+        assert!(!store.delete(&b.digest).await.unwrap());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,364 @@
+//! Pull-through mirror for resources that are hosted remotely
+//!
+//! On the first request a remote resource is downloaded, verified against the `bytes` and `hash`
+//! of its descriptor and stored in the blob store. Later requests are served from the blob
+//! store, such that clients do not depend on the availability of the upstream url anymore. This
+//! includes signed urls that expire, as the url in the descriptor stays the key of the cache.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use color_eyre::eyre::{Report, eyre};
+use futures::TryStreamExt;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio_util::io::StreamReader;
+use tracing::{info, warn};
+
+use crate::datapackage::{DataPackage, DataResourceNotValidated};
+
+use super::{BlobDigest, BlobMeta, BlobStore, paths};
+
+/// Settings of the pull-through mirror, the mirror requires a blob store
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct MirrorSettings {
+    /// file that maps the mirrored urls to their blobs
+    pub index_path: String,
+
+    /// timeout for the download of one resource
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// larger resources are not mirrored but redirected to their url
+    pub max_size_bytes: Option<u64>,
+}
+
+fn default_timeout_secs() -> u64 {
+    60 * 60
+}
+
+/// The outcome of a mirror request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mirrored {
+    /// the blob that contains the resource
+    Blob(BlobMeta),
+
+    /// the resource is too large, clients should fetch the url themselves
+    ///
+    /// Holds the announced size, or the bytes received until the limit was exceeded.
+    TooLarge(u64),
+}
+
+/// Downloads remote resources into a blob store and remembers them by url
+#[derive(Debug)]
+pub struct Mirror {
+    blobs: Arc<dyn BlobStore>,
+
+    client: reqwest::Client,
+
+    settings: MirrorSettings,
+
+    index: RwLock<HashMap<String, BlobDigest>>,
+
+    /// serializes downloads of the same url, concurrent requests wait for the first one
+    inflight: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+impl Mirror {
+    pub fn new(blobs: Arc<dyn BlobStore>, settings: MirrorSettings) -> Result<Self, Report> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .user_agent(concat!("nebula-registry/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+
+        let index = match std::fs::read_to_string(&settings.index_path) {
+            Ok(json) => serde_json::from_str::<HashMap<String, String>>(&json)?
+                .into_iter()
+                .filter_map(|(url, digest)| digest.parse().ok().map(|digest| (url, digest)))
+                .collect(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+        info!("Mirror index '{}' contains {} urls", settings.index_path, index.len());
+
+        Ok(Mirror {
+            blobs,
+            client,
+            settings,
+            index: RwLock::new(index),
+            inflight: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Gets the cached blob of a remote resource, None if it has not been mirrored yet
+    pub async fn cached(
+        &self,
+        url: &str,
+        dr: &DataResourceNotValidated,
+    ) -> Result<Option<BlobMeta>, Report> {
+        let known = self.lookup(url).or_else(|| expected_digest(dr));
+        match known {
+            Some(digest) => self.blobs.stat(&digest).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Gets the blob of a remote resource and downloads it first if needed
+    pub async fn fetch(
+        &self,
+        url: &str,
+        dr: &DataResourceNotValidated,
+    ) -> Result<Mirrored, Report> {
+        let guard = {
+            let mut inflight = self.inflight.lock().await;
+            inflight.entry(url.to_string()).or_default().clone()
+        };
+        let reval = {
+            let _lock = guard.lock().await;
+            match self.cached(url, dr).await? {
+                Some(meta) => {
+                    // the blob may be known by the hash of the descriptor only
+                    if self.lookup(url).is_none() {
+                        self.insert(url, meta.digest)?;
+                    }
+                    Ok(Mirrored::Blob(meta))
+                }
+                None => self.download(url, dr).await,
+            }
+        };
+        self.inflight.lock().await.remove(url);
+        reval
+    }
+
+    async fn download(&self, url: &str, dr: &DataResourceNotValidated) -> Result<Mirrored, Report> {
+        let size = dr.bytes;
+        if let (Some(size), Some(max)) = (size, self.settings.max_size_bytes) {
+            if size > max {
+                return Ok(Mirrored::TooLarge(size));
+            }
+        }
+
+        info!("Mirroring '{}'", url);
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        if let (Some(length), Some(max)) = (response.content_length(), self.settings.max_size_bytes)
+        {
+            if length > max {
+                return Ok(Mirrored::TooLarge(length));
+            }
+        }
+
+        // the length may be unknown, the limit is enforced while streaming and a failed put
+        // leaves no partial blob behind
+        let max = self.settings.max_size_bytes.unwrap_or(u64::MAX);
+        let received = Arc::new(AtomicU64::new(0));
+        let counter = received.clone();
+        let stream =
+            response.bytes_stream().map_err(std::io::Error::other).and_then(move |chunk| {
+                let total =
+                    counter.fetch_add(chunk.len() as u64, Ordering::Relaxed) + chunk.len() as u64;
+                let too_large = std::io::ErrorKind::FileTooLarge;
+                futures::future::ready(if total > max {
+                    Err(std::io::Error::new(too_large, format!("more than {} bytes", max)))
+                } else {
+                    Ok(chunk)
+                })
+            });
+        let meta = match self.blobs.put(Box::pin(StreamReader::new(stream))).await {
+            Ok(meta) => meta,
+            Err(_) if received.load(Ordering::Relaxed) > max => {
+                return Ok(Mirrored::TooLarge(received.load(Ordering::Relaxed)));
+            }
+            Err(err) => return Err(err),
+        };
+        // a blob that fails the verification is not indexed and removed by the next collection
+        verify(dr, &meta)?;
+
+        self.insert(url, meta.digest)?;
+        info!("Mirrored '{}' as {} ({} bytes)", url, meta.digest, meta.size);
+        Ok(Mirrored::Blob(meta))
+    }
+
+    fn lookup(&self, url: &str) -> Option<BlobDigest> {
+        self.index.read().unwrap_or_else(|poisoned| poisoned.into_inner()).get(url).copied()
+    }
+
+    fn insert(&self, url: &str, digest: BlobDigest) -> Result<(), Report> {
+        let json = {
+            let mut index = self.index.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+            index.insert(url.to_string(), digest);
+            let plain: HashMap<&String, String> =
+                index.iter().map(|(url, digest)| (url, digest.to_string())).collect();
+            serde_json::to_string_pretty(&plain)?
+        };
+
+        // write and rename, such that a crash never leaves a truncated index
+        let path = PathBuf::from(&self.settings.index_path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Collects the mirrored blobs of the remote resources of the given packages
+    pub fn referenced_blobs(&self, packages: &[DataPackage]) -> HashSet<BlobDigest> {
+        let index = self.index.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        packages
+            .iter()
+            .flat_map(|dp| dp.resources.iter())
+            .filter_map(|dr| dr.path.as_ref())
+            .flat_map(paths)
+            .filter_map(|url| index.get(url).copied())
+            .collect()
+    }
+}
+
+/// The digest a resource must have, if the descriptor gives a sha256 hash
+fn expected_digest(dr: &DataResourceNotValidated) -> Option<BlobDigest> {
+    dr.hash.as_ref().and_then(|hash| hash.parse().ok())
+}
+
+/// Checks the downloaded blob against the `bytes` and `hash` of the descriptor
+fn verify(dr: &DataResourceNotValidated, meta: &BlobMeta) -> Result<(), Report> {
+    if let Some(bytes) = dr.bytes {
+        if bytes != meta.size {
+            return Err(eyre!(
+                "Resource '{}' has {} bytes, but the descriptor expects {}",
+                dr.name,
+                meta.size,
+                bytes
+            ));
+        }
+    }
+
+    match (dr.hash.as_ref(), expected_digest(dr)) {
+        (Some(_), Some(digest)) if digest != meta.digest => Err(eyre!(
+            "Resource '{}' has the digest {}, but the descriptor expects {}",
+            dr.name,
+            meta.digest,
+            digest
+        )),
+        (Some(hash), None) => {
+            warn!("Cannot verify the hash '{}' of '{}', only sha256 is supported", hash, dr.name);
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::{
+        datapackage::{PathSingleOrVec, ValidateData},
+        storage::blob::local::LocalBlobStore,
+    };
+
+    /// Serves the body for every request and counts the requests
+    ///
+    /// Without `announce_length` the body is delimited by closing the connection.
+    async fn upstream(body: &'static [u8], announce_length: bool) -> (String, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/archive.tar.gz?Signature=abc", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await;
+                let head = match announce_length {
+                    true => format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                        body.len()
+                    ),
+                    false => "HTTP/1.1 200 OK\r\nconnection: close\r\n\r\n".to_string(),
+                };
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(body).await;
+            }
+        });
+        (url, hits)
+    }
+
+    fn resource(url: &str, bytes: Option<u64>, hash: Option<String>) -> DataResourceNotValidated {
+        DataResourceNotValidated {
+            name: "complete-archive".into(),
+            path: Some(PathSingleOrVec::Single(url.into())),
+            bytes,
+            hash,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mirror_fetches_once_and_verifies() {
+        let root = PathBuf::from("tmp_mirror");
+        let _ = std::fs::remove_dir_all(&root);
+        let blobs: Arc<dyn BlobStore> = Arc::new(LocalBlobStore::new(root.join("blobs")).unwrap());
+        let settings = MirrorSettings {
+            index_path: root.join("mirror.json").display().to_string(),
+            timeout_secs: 10,
+            max_size_bytes: None,
+        };
+        let (url, hits) = upstream(b"remote content", true).await;
+        let digest = BlobDigest::of_bytes(b"remote content");
+
+        // a wrong size is rejected and not indexed
+        let mirror = Mirror::new(blobs.clone(), settings.clone()).unwrap();
+        assert!(mirror.fetch(&url, &resource(&url, Some(3), None)).await.is_err());
+        assert!(mirror.lookup(&url).is_none());
+
+        let dr = resource(&url, Some(14), Some(digest.to_string()));
+        let Mirrored::Blob(meta) = mirror.fetch(&url, &dr).await.unwrap() else {
+            panic!("expected a blob");
+        };
+        assert_eq!(meta.digest, digest);
+        assert_eq!(mirror.fetch(&url, &dr).await.unwrap(), Mirrored::Blob(meta.clone()));
+        // the content of the rejected download is found by the sha256 of the descriptor
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // the index is persisted, a new instance serves from the cache without the upstream
+        let mirror = Mirror::new(blobs.clone(), settings.clone()).unwrap();
+        let dr = resource(&url, None, None);
+        assert_eq!(mirror.cached(&url, &dr).await.unwrap(), Some(meta));
+        let package = crate::datapackage::DataPackageNotValidated {
+            resources: vec![dr],
+            ..Default::default()
+        };
+        let package = package.validate().unwrap();
+        assert_eq!(mirror.referenced_blobs(&[package]), HashSet::from([digest]));
+
+        // too large resources are not mirrored
+        let limited = MirrorSettings { max_size_bytes: Some(4), ..settings };
+        let mirror = Mirror::new(blobs.clone(), limited).unwrap();
+        let (other, _) = upstream(b"other remote content", true).await;
+        let dr = resource(&other, None, None);
+        assert_eq!(mirror.fetch(&other, &dr).await.unwrap(), Mirrored::TooLarge(20));
+
+        // without a content length the download stops at the limit and stores nothing
+        let (unannounced, _) = upstream(b"unannounced remote content", false).await;
+        let dr = resource(&unannounced, None, None);
+        assert!(
+            matches!(mirror.fetch(&unannounced, &dr).await, Ok(Mirrored::TooLarge(n)) if n > 4)
+        );
+        assert!(mirror.lookup(&unannounced).is_none());
+        assert_eq!(blobs.list().await.unwrap().len(), 1);
+        assert_eq!(std::fs::read_dir(root.join("blobs").join("tmp")).unwrap().count(), 0);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
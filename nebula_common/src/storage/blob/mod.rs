@@ -0,0 +1,395 @@
+//! Content-addressed storage for the resources hosted by a registry
+//!
+//! Blobs are stored by the sha256 of their content, such that identical files of several
+//! packages or versions are stored only once. Descriptors reference a blob by a path of the form
+//! `blob:sha256:<hex>` instead of a filename next to the descriptor or an absolute url.
+//!
+//! There are two backends: [local::LocalBlobStore] stores the blobs on the filesystem and
+//! [s3::S3BlobStore] uses a S3 compatible object storage. Blobs that are not referenced by any
+//! descriptor anymore are removed by [collect_garbage]. Remote resources can be cached in the
+//! blob store by the pull-through [mirror::Mirror].
+
+pub mod local;
+pub mod mirror;
+pub mod s3;
+
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    path::{Path, PathBuf},
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use color_eyre::eyre::{Report, eyre};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::info;
+
+use crate::datapackage::{DataPackage, DataPackageNotValidated, PathSingleOrVec};
+
+/// The prefix of resource paths that reference a blob
+pub const BLOB_REFERENCE_PREFIX: &str = "blob:";
+
+/// A reader for the content of a blob
+pub type BlobReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// The sha256 digest of a blob, displayed as `sha256:<hex>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlobDigest([u8; 32]);
+
+impl BlobDigest {
+    pub fn of_bytes(content: &[u8]) -> Self {
+        BlobDigest(Sha256::digest(content).into())
+    }
+
+    pub fn hex(&self) -> String {
+        hex::encode(self.0)
+    }
+
+    /// Parses a resource path of the form `blob:sha256:<hex>`
+    pub fn from_reference(path: &str) -> Option<Self> {
+        path.strip_prefix(BLOB_REFERENCE_PREFIX).and_then(|el| el.parse().ok())
+    }
+
+    /// The resource path that references the blob
+    pub fn reference(&self) -> String {
+        format!("{}{}", BLOB_REFERENCE_PREFIX, self)
+    }
+
+    /// The relative location of the blob in a store, e.g. `sha256/ab/abcdef...`
+    ///
+    /// The first byte is used as a fan-out folder to keep directory listings small.
+    pub fn location(&self) -> String {
+        let hex = self.hex();
+        format!("sha256/{}/{}", &hex[..2], hex)
+    }
+
+    /// Parses the hex digest, i.e. the last part of [BlobDigest::location]
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let mut digest = [0u8; 32];
+        hex::decode_to_slice(hex, &mut digest).ok()?;
+        Some(BlobDigest(digest))
+    }
+}
+
+impl Display for BlobDigest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sha256:{}", self.hex())
+    }
+}
+
+impl FromStr for BlobDigest {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.strip_prefix("sha256:")
+            .and_then(BlobDigest::from_hex)
+            .ok_or_else(|| eyre!("'{}' is not a sha256 digest of the form 'sha256:<hex>'", s))
+    }
+}
+
+/// Metadata about a stored blob
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobMeta {
+    pub digest: BlobDigest,
+
+    pub size: u64,
+
+    pub modified: Option<SystemTime>,
+}
+
+/// Trait for backends that store blobs by their content
+#[async_trait]
+pub trait BlobStore: std::fmt::Debug + Send + Sync {
+    /// Stores the content and returns its metadata, existing blobs are not written again
+    async fn put(&self, content: BlobReader) -> Result<BlobMeta, Report>;
+
+    /// Opens the content of a blob, None if the blob does not exist
+    async fn get(&self, digest: &BlobDigest) -> Result<Option<BlobReader>, Report>;
+
+    /// Opens the content of a blob from the byte `offset` on, used to answer range requests
+    ///
+    /// The default reads and discards the skipped bytes, backends that can seek override it.
+    async fn get_from(
+        &self,
+        digest: &BlobDigest,
+        offset: u64,
+    ) -> Result<Option<BlobReader>, Report> {
+        let Some(mut reader) = self.get(digest).await? else {
+            return Ok(None);
+        };
+        tokio::io::copy(&mut (&mut reader).take(offset), &mut tokio::io::sink()).await?;
+        Ok(Some(reader))
+    }
+
+    async fn stat(&self, digest: &BlobDigest) -> Result<Option<BlobMeta>, Report>;
+
+    /// Deletes a blob, returns false if the blob did not exist
+    async fn delete(&self, digest: &BlobDigest) -> Result<bool, Report>;
+
+    async fn list(&self) -> Result<Vec<BlobMeta>, Report>;
+
+    async fn put_file(&self, path: &Path) -> Result<BlobMeta, Report> {
+        let file = tokio::fs::File::open(path).await?;
+        self.put(Box::pin(file)).await
+    }
+}
+
+/// Settings to choose and configure the blob store backend
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum BlobStoreSettings {
+    Local {
+        path: String,
+    },
+    S3 {
+        /// url of the service, e.g. `http://localhost:9000` for a local MinIO, None for AWS
+        endpoint: Option<String>,
+        bucket: String,
+        #[serde(default = "default_region")]
+        region: String,
+        access_key_id: Option<String>,
+        secret_access_key: Option<String>,
+        /// prefix for the keys of the blobs inside the bucket
+        #[serde(default)]
+        prefix: String,
+    },
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+impl BlobStoreSettings {
+    pub fn open(&self) -> Result<Arc<dyn BlobStore>, Report> {
+        Ok(match self {
+            BlobStoreSettings::Local { path } => Arc::new(local::LocalBlobStore::new(path)?),
+            BlobStoreSettings::S3 {
+                endpoint,
+                bucket,
+                region,
+                access_key_id,
+                secret_access_key,
+                prefix,
+            } => {
+                let mut builder = object_store::aws::AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .with_region(region);
+                if let Some(endpoint) = endpoint {
+                    builder = builder
+                        .with_endpoint(endpoint)
+                        .with_allow_http(endpoint.starts_with("http://"))
+                        .with_virtual_hosted_style_request(false);
+                }
+                if let Some(key) = access_key_id {
+                    builder = builder.with_access_key_id(key);
+                }
+                if let Some(secret) = secret_access_key {
+                    builder = builder.with_secret_access_key(secret);
+                }
+                Arc::new(s3::S3BlobStore::new(Arc::new(builder.build()?), prefix))
+            }
+        })
+    }
+}
+
+/// Copies the content to the writer and computes its digest and size on the way
+pub(crate) async fn copy_hashed<R, W>(
+    content: &mut R,
+    writer: &mut W,
+) -> Result<(BlobDigest, u64), std::io::Error>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = content.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        writer.write_all(&buf[..n]).await?;
+        size += n as u64;
+    }
+    writer.flush().await?;
+    Ok((BlobDigest(hasher.finalize().into()), size))
+}
+
+fn paths(path: &PathSingleOrVec) -> Vec<&str> {
+    match path {
+        PathSingleOrVec::Single(path) => vec![path.as_str()],
+        PathSingleOrVec::Vec(paths) => paths.iter().map(|p| p.as_str()).collect(),
+    }
+}
+
+/// Collects the blobs that are referenced by the resources of the given packages
+pub fn referenced_blobs(packages: &[DataPackage]) -> HashSet<BlobDigest> {
+    packages
+        .iter()
+        .flat_map(|dp| dp.resources.iter())
+        .filter_map(|dr| dr.path.as_ref())
+        .flat_map(paths)
+        .filter_map(BlobDigest::from_reference)
+        .collect()
+}
+
+/// The result of [collect_garbage]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GcReport {
+    pub kept: usize,
+
+    pub deleted: Vec<BlobDigest>,
+
+    pub freed_bytes: u64,
+}
+
+/// Deletes blobs that are not referenced
+///
+/// Blobs younger than min_age are kept, as they may belong to a descriptor that is about to be
+/// written. With dry_run the blobs are only reported.
+pub async fn collect_garbage(
+    store: &dyn BlobStore,
+    referenced: &HashSet<BlobDigest>,
+    min_age: Duration,
+    dry_run: bool,
+) -> Result<GcReport, Report> {
+    let now = SystemTime::now();
+    let mut report = GcReport::default();
+    for blob in store.list().await? {
+        let young =
+            blob.modified.is_none_or(|el| now.duration_since(el).unwrap_or_default() < min_age);
+        if referenced.contains(&blob.digest) || young {
+            report.kept += 1;
+            continue;
+        }
+
+        if !dry_run && !store.delete(&blob.digest).await? {
+            continue;
+        }
+        info!("Collected blob {} ({} bytes)", blob.digest, blob.size);
+        report.freed_bytes += blob.size;
+        report.deleted.push(blob.digest);
+    }
+    Ok(report)
+}
+
+/// Moves the files of a package folder into the blob store and rewrites the descriptor
+///
+/// Relative paths of the resources are replaced by blob references and `bytes` and `hash` are
+/// filled in. Remote resources and existing references stay untouched. The files are not
+/// deleted, the caller decides whether to keep them. Returns the imported files with their blobs.
+pub async fn import_package(
+    store: &dyn BlobStore,
+    folder: &Path,
+) -> Result<Vec<(PathBuf, BlobMeta)>, Report> {
+    let dp_path = folder.join("datapackage.json");
+    let mut package: DataPackageNotValidated =
+        serde_json::from_str(&tokio::fs::read_to_string(&dp_path).await?)?;
+
+    let mut imported = vec![];
+    for dr in package.resources.iter_mut() {
+        let Some(path) = dr.path.as_mut() else {
+            continue;
+        };
+        let mut metas = vec![];
+        let entries: Vec<&mut String> = match path {
+            PathSingleOrVec::Single(path) => vec![path],
+            PathSingleOrVec::Vec(paths) => paths.iter_mut().collect(),
+        };
+        for entry in entries {
+            let local = entry.as_str();
+            if local.contains("://") || local.starts_with(BLOB_REFERENCE_PREFIX) {
+                continue;
+            }
+            let file = folder.join(local);
+            if !file.is_file() {
+                return Err(eyre!("Resource file '{}' does not exist", file.display()));
+            }
+            let meta = store.put_file(&file).await?;
+            info!("Imported '{}' as {}", file.display(), meta.digest);
+            *entry = meta.digest.reference();
+            metas.push((file, meta));
+        }
+
+        // the descriptor fields describe a resource with exactly one file
+        if let (PathSingleOrVec::Single(_), [(_, meta)]) = (&*path, metas.as_slice()) {
+            dr.bytes = Some(meta.size);
+            dr.hash = Some(meta.digest.to_string());
+        }
+        imported.extend(metas);
+    }
+
+    let json = serde_json::ser::to_string_pretty(&package)?;
+    tokio::fs::write(&dp_path, json).await?;
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_digest_roundtrip() {
+        let digest = BlobDigest::of_bytes(b"nebula");
+        let reference = digest.reference();
+        assert!(reference.starts_with("blob:sha256:"));
+        assert_eq!(BlobDigest::from_reference(&reference), Some(digest));
+        assert_eq!(digest.to_string().parse::<BlobDigest>().unwrap(), digest);
+        assert_eq!(digest.location(), format!("sha256/{}/{}", &digest.hex()[..2], digest.hex()));
+
+        assert!(BlobDigest::from_reference("https://example.com/file").is_none());
+        assert!("sha256:abc".parse::<BlobDigest>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_package() {
+        let root = PathBuf::from("tmp_blobs_import");
+        let _ = std::fs::remove_dir_all(&root);
+        let folder = root.join("iris");
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::copy(
+            "../nebula_registry/data/iris/datapackage.json",
+            folder.join("datapackage.json"),
+        )
+        .unwrap();
+        std::fs::copy("../nebula_registry/data/iris/iris.csv", folder.join("iris.csv")).unwrap();
+        let store = local::LocalBlobStore::new(root.join("blobs")).unwrap();
+
+        let imported = import_package(&store, &folder).await.unwrap();
+        assert_eq!(imported.len(), 1);
+        let (file, meta) = &imported[0];
+        assert_eq!(file, &folder.join("iris.csv"));
+
+        let package =
+            crate::datapackage::datapackage_meta_from_file(&folder.join("datapackage.json"))
+                .unwrap();
+        let dr = package.resources.iter().find(|dr| dr.name == "iris-table").unwrap();
+        assert_eq!(dr.path.as_ref().and_then(|p| p.nth(0)), Some(meta.digest.reference().as_str()));
+        assert_eq!(dr.bytes, Some(meta.size));
+        assert_eq!(dr.hash, Some(meta.digest.to_string()));
+        assert_eq!(referenced_blobs(&[package]), HashSet::from([meta.digest]));
+
+        // importing again keeps the references
+        assert!(import_package(&store, &folder).await.unwrap().is_empty());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_copy_hashed() {
+        let content = b"hello blob".to_vec();
+        let mut out = vec![];
+        let (digest, size) = copy_hashed(&mut content.as_slice(), &mut out).await.unwrap();
+        assert_eq!(digest, BlobDigest::of_bytes(&content));
+        assert_eq!(size, content.len() as u64);
+        assert_eq!(out, content);
+    }
+}
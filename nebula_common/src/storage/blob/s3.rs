@@ -0,0 +1,207 @@
+//! Blob store on a S3 compatible object storage, e.g. AWS S3 or MinIO
+//!
+//! The store is implemented on top of [object_store], such that it can be tested against the
+//! in-memory stand-in [object_store::memory::InMemory].
+
+use std::{path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use color_eyre::eyre::Report;
+use futures::TryStreamExt;
+use object_store::{
+    GetOptions, GetRange, ObjectMeta, ObjectStore, WriteMultipart, path::Path as ObjectPath,
+};
+use tokio::io::AsyncReadExt;
+use tokio_util::io::StreamReader;
+use uuid::Uuid;
+
+use super::{BlobDigest, BlobMeta, BlobReader, BlobStore, copy_hashed};
+
+/// Stores blobs with the key `<prefix>/sha256/<fan-out>/<hex>`
+///
+/// The digest of the content is unknown until it is read completely, therefore the content is
+/// spooled to a temporary file first and only uploaded if the bucket does not contain the blob.
+#[derive(Debug, Clone)]
+pub struct S3BlobStore {
+    store: Arc<dyn ObjectStore>,
+
+    prefix: String,
+
+    spool: PathBuf,
+}
+
+impl S3BlobStore {
+    pub fn new(store: Arc<dyn ObjectStore>, prefix: &str) -> Self {
+        S3BlobStore {
+            store,
+            prefix: prefix.trim_matches('/').to_string(),
+            spool: std::env::temp_dir(),
+        }
+    }
+
+    fn key_of(&self, digest: &BlobDigest) -> ObjectPath {
+        self.with_prefix(&digest.location())
+    }
+
+    fn with_prefix(&self, key: &str) -> ObjectPath {
+        if self.prefix.is_empty() {
+            ObjectPath::from(key)
+        } else {
+            ObjectPath::from(format!("{}/{}", self.prefix, key))
+        }
+    }
+
+    async fn head(&self, digest: &BlobDigest) -> Result<Option<ObjectMeta>, Report> {
+        match self.store.head(&self.key_of(digest)).await {
+            Ok(meta) => Ok(Some(meta)),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn upload(&self, spooled: &std::path::Path, digest: &BlobDigest) -> Result<(), Report> {
+        let upload = self.store.put_multipart(&self.key_of(digest)).await?;
+        let mut writer = WriteMultipart::new(upload);
+        let mut file = tokio::fs::File::open(spooled).await?;
+        let mut buf = vec![0u8; 1024 * 1024];
+        loop {
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            writer.write(&buf[..n]);
+        }
+        writer.finish().await?;
+        Ok(())
+    }
+}
+
+fn to_blob_meta(digest: BlobDigest, meta: &ObjectMeta) -> BlobMeta {
+    BlobMeta { digest, size: meta.size, modified: Some(meta.last_modified.into()) }
+}
+
+#[async_trait]
+impl BlobStore for S3BlobStore {
+    async fn put(&self, mut content: BlobReader) -> Result<BlobMeta, Report> {
+        let spooled = self.spool.join(format!("nebula-blob-{}", Uuid::new_v4()));
+        let mut file = tokio::fs::File::create(&spooled).await?;
+        let copied = copy_hashed(&mut content, &mut file).await;
+        drop(file);
+
+        let reval = match copied {
+            Ok((digest, size)) => match self.head(&digest).await {
+                Ok(Some(meta)) => Ok(to_blob_meta(digest, &meta)),
+                Ok(None) => self.upload(&spooled, &digest).await.map(|_| BlobMeta {
+                    digest,
+                    size,
+                    modified: Some(std::time::SystemTime::now()),
+                }),
+                Err(err) => Err(err),
+            },
+            Err(err) => Err(err.into()),
+        };
+        let _ = tokio::fs::remove_file(&spooled).await;
+        reval
+    }
+
+    async fn get(&self, digest: &BlobDigest) -> Result<Option<BlobReader>, Report> {
+        match self.store.get(&self.key_of(digest)).await {
+            Ok(result) => {
+                let stream = result.into_stream().map_err(std::io::Error::other);
+                Ok(Some(Box::pin(StreamReader::new(stream))))
+            }
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn get_from(
+        &self,
+        digest: &BlobDigest,
+        offset: u64,
+    ) -> Result<Option<BlobReader>, Report> {
+        let options = GetOptions { range: Some(GetRange::Offset(offset)), ..Default::default() };
+        match self.store.get_opts(&self.key_of(digest), options).await {
+            Ok(result) => {
+                let stream = result.into_stream().map_err(std::io::Error::other);
+                Ok(Some(Box::pin(StreamReader::new(stream))))
+            }
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn stat(&self, digest: &BlobDigest) -> Result<Option<BlobMeta>, Report> {
+        Ok(self.head(digest).await?.map(|meta| to_blob_meta(*digest, &meta)))
+    }
+
+    async fn delete(&self, digest: &BlobDigest) -> Result<bool, Report> {
+        // S3 reports success for missing keys
+        if self.head(digest).await?.is_none() {
+            return Ok(false);
+        }
+        self.store.delete(&self.key_of(digest)).await?;
+        Ok(true)
+    }
+
+    async fn list(&self) -> Result<Vec<BlobMeta>, Report> {
+        let prefix = self.with_prefix("sha256");
+        let metas: Vec<ObjectMeta> = self.store.list(Some(&prefix)).try_collect().await?;
+        Ok(metas
+            .iter()
+            .filter_map(|meta| {
+                let digest = meta.location.filename().and_then(BlobDigest::from_hex)?;
+                Some(to_blob_meta(digest, meta))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashSet, time::Duration};
+
+    use futures::StreamExt;
+    use object_store::memory::InMemory;
+
+    use super::*;
+    use crate::storage::blob::collect_garbage;
+
+    #[tokio::test]
+    async fn test_s3_put_dedupe_and_gc() {
+        let backend = Arc::new(InMemory::new());
+        let store = S3BlobStore::new(backend.clone(), "/nebula/");
+
+        let a = store.put(Box::pin(&b"content a"[..])).await.unwrap();
+        let again = store.put(Box::pin(&b"content a"[..])).await.unwrap();
+        let b = store.put(Box::pin(&b"content b"[..])).await.unwrap();
+        assert_eq!(a.digest, again.digest);
+        assert_eq!(a.size, 9);
+
+        let keys: Vec<_> =
+            backend.list(None).map(|el| el.unwrap().location.to_string()).collect().await;
+        assert_eq!(keys.len(), 2);
+        assert!(keys.contains(&format!("nebula/{}", a.digest.location())));
+
+        let mut content = String::new();
+        store.get(&a.digest).await.unwrap().unwrap().read_to_string(&mut content).await.unwrap();
+        assert_eq!(content, "content a");
+        let mut rest = String::new();
+        store
+            .get_from(&a.digest, 8)
+            .await
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut rest)
+            .await
+            .unwrap();
+        assert_eq!(rest, "a");
+
+        let referenced = HashSet::from([a.digest]);
+        let report = collect_garbage(&store, &referenced, Duration::ZERO, false).await.unwrap();
+        assert_eq!(report.deleted, vec![b.digest]);
+        assert!(store.stat(&b.digest).await.unwrap().is_none());
+        assert!(store.get(&b.digest).await.unwrap().is_none());
+        assert!(!store.delete(&b.digest).await.unwrap());
+    }
+}
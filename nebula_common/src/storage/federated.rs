@@ -0,0 +1,285 @@
+//! Serves the packages of the local root folder together with packages replicated from an
+//! upstream registry
+//!
+//! The replicated packages are stored in a separate [RootFolderSource] that is written by the
+//! replication only, for clients they are read-only. If both sources contain a package with the
+//! same name, the [ConflictPolicy] decides which one is served, the other one is hidden.
+
+use std::{collections::HashSet, path::PathBuf};
+
+use async_trait::async_trait;
+use color_eyre::eyre::{Report, eyre};
+
+use crate::{
+    configuration::registry::ConflictPolicy,
+    datapackage::DataPackage,
+    model::{FieldSettings, FilterSettings, PagationSettings, SortSettings},
+};
+
+use super::{MetaDataSource, paginate, root_folder::RootFolderSource, sort_packages};
+
+#[derive(Debug, Clone)]
+pub struct FederatedSource {
+    local: RootFolderSource,
+
+    replica: Option<RootFolderSource>,
+
+    conflict: ConflictPolicy,
+}
+
+impl FederatedSource {
+    /// A source that serves the local packages only
+    pub fn local(local: RootFolderSource) -> Self {
+        FederatedSource { local, replica: None, conflict: ConflictPolicy::default() }
+    }
+
+    pub fn with_replica(mut self, replica: RootFolderSource, conflict: ConflictPolicy) -> Self {
+        self.replica = Some(replica);
+        self.conflict = conflict;
+        self
+    }
+
+    pub fn local_source(&self) -> &RootFolderSource {
+        &self.local
+    }
+
+    pub fn replica_source(&self) -> Option<&RootFolderSource> {
+        self.replica.as_ref()
+    }
+
+    /// The sources ordered by precedence
+    fn ordered(&self) -> Vec<&RootFolderSource> {
+        match (&self.replica, self.conflict) {
+            (None, _) => vec![&self.local],
+            (Some(replica), ConflictPolicy::PreferLocal) => vec![&self.local, replica],
+            (Some(replica), ConflictPolicy::PreferUpstream) => vec![replica, &self.local],
+        }
+    }
+
+    /// Replicated packages are read-only unless a local package shadows them
+    fn is_replicated(&self, name: &str) -> bool {
+        let Some(replica) = self.replica.as_ref() else {
+            return false;
+        };
+        replica.package_names().contains(name)
+            && (self.conflict == ConflictPolicy::PreferUpstream
+                || !self.local.package_names().contains(name))
+    }
+}
+
+/// Merges the packages of several sources, packages of later sources are hidden if an earlier
+/// source contains a package with the same name
+fn merge(sources: Vec<Vec<DataPackage>>, pagation: PagationSettings) -> Vec<DataPackage> {
+    let mut reval = vec![];
+    let mut shadowed: HashSet<String> = HashSet::new();
+    for packages in sources {
+        let names: HashSet<String> = packages.iter().filter_map(|dp| dp.name.clone()).collect();
+        reval.extend(
+            packages
+                .into_iter()
+                .filter(|dp| dp.name.as_ref().is_none_or(|n| !shadowed.contains(n))),
+        );
+        shadowed.extend(names);
+    }
+    sort_packages(&mut reval);
+    paginate(reval, pagation)
+}
+
+#[async_trait]
+impl MetaDataSource for FederatedSource {
+    async fn list_packages(
+        &self,
+        sort: SortSettings,
+        filter: FilterSettings,
+        pagation: PagationSettings,
+        fields: FieldSettings,
+    ) -> Vec<DataPackage> {
+        let mut sources = vec![];
+        for source in self.ordered() {
+            let packages = source
+                .list_packages(
+                    sort.clone(),
+                    filter.clone(),
+                    PagationSettings::unlimited(),
+                    fields.clone(),
+                )
+                .await;
+            sources.push(packages);
+        }
+        merge(sources, pagation)
+    }
+
+    async fn get_package(&self, query: &str, filter: FilterSettings) -> Option<DataPackage> {
+        // an exact name in any source wins over a partial match in a source of higher precedence
+        if let Some(package) = self.get_package_by_name(query, filter.clone()).await {
+            return Some(package);
+        }
+        for source in self.ordered() {
+            if let Some(package) = source.get_package(query, filter.clone()).await {
+                return Some(package);
+            }
+        }
+        None
+    }
+
+    async fn get_package_by_name(&self, name: &str, filter: FilterSettings) -> Option<DataPackage> {
+        for source in self.ordered() {
+            if let Some(package) = source.get_package_by_name(name, filter.clone()).await {
+                return Some(package);
+            }
+        }
+        None
+    }
+
+    async fn get_package_version(&self, name: &str, version: &str) -> Option<DataPackage> {
+        for source in self.ordered() {
+            if let Some(package) = source.get_package_version(name, version).await {
+                return Some(package);
+            }
+        }
+        None
+    }
+
+    async fn search_package(
+        &self,
+        search_query: &str,
+        sort: SortSettings,
+        filter: FilterSettings,
+        pagation: PagationSettings,
+    ) -> Vec<DataPackage> {
+        let mut sources = vec![];
+        for source in self.ordered() {
+            let packages = source
+                .search_package(
+                    search_query,
+                    sort.clone(),
+                    filter.clone(),
+                    PagationSettings::unlimited(),
+                )
+                .await;
+            sources.push(packages);
+        }
+        merge(sources, pagation)
+    }
+
+    async fn put_package_metadata(&mut self, package: &DataPackage) -> Result<(), Report> {
+        if let Some(name) = package.name.as_ref().filter(|name| self.is_replicated(name)) {
+            return Err(eyre!("Package '{}' is replicated from upstream and read-only", name));
+        }
+        self.local.put_package_metadata(package).await
+    }
+
+    async fn package_folder(&self, name: &str, version: &str) -> Option<PathBuf> {
+        for source in self.ordered() {
+            if let Some(folder) = source.package_folder(name, version).await {
+                return Some(folder);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::create_dir_all;
+
+    use super::*;
+    use crate::datapackage::{
+        DataPackageNotValidated, DataResourceNotValidated, PathSingleOrVec, ValidateData,
+    };
+
+    fn write(root: &str, name: &str, title: &str) {
+        let folder = PathBuf::from(root).join(name);
+        create_dir_all(&folder).unwrap();
+        let package = DataPackageNotValidated {
+            name: Some(name.into()),
+            title: Some(title.into()),
+            version: Some("1.0.0".into()),
+            resources: vec![DataResourceNotValidated {
+                name: "data".into(),
+                path: Some(PathSingleOrVec::Single("https://example.com/data.csv".into())),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        package.clone().validate().unwrap();
+        std::fs::write(folder.join("datapackage.json"), serde_json::to_string(&package).unwrap())
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_conflict_policy() {
+        let (local, replica) = ("tmp_federated_local", "tmp_federated_replica");
+        let _ = std::fs::remove_dir_all(local);
+        let _ = std::fs::remove_dir_all(replica);
+        write(local, "iris", "local iris");
+        write(local, "own", "local only");
+        write(replica, "iris", "upstream iris");
+        write(replica, "cifar", "upstream only");
+
+        let source = || {
+            FederatedSource::local(RootFolderSource::new_from_folder(local.into()))
+                .with_replica(RootFolderSource::new_from_folder(replica.into()), Default::default())
+        };
+        let title = |dp: Option<DataPackage>| dp.and_then(|dp| dp.title.clone());
+        let list = async |source: &FederatedSource| {
+            let packages = source
+                .list_packages(
+                    SortSettings::default(),
+                    FilterSettings::default(),
+                    PagationSettings::default(),
+                    FieldSettings::default(),
+                )
+                .await;
+            packages.iter().map(|dp| dp.title.clone().unwrap()).collect::<Vec<_>>()
+        };
+
+        let mut prefer_local = source();
+        assert_eq!(list(&prefer_local).await, vec!["upstream only", "local iris", "local only"]);
+        let iris = prefer_local.get_package("iris", FilterSettings::default()).await;
+        assert_eq!(title(iris.clone()), Some("local iris".into()));
+
+        // replicated packages are read-only
+        let cifar = prefer_local.get_package("cifar", FilterSettings::default()).await;
+        assert!(prefer_local.put_package_metadata(&cifar.unwrap()).await.is_err());
+
+        let prefer_upstream = source().with_replica(
+            RootFolderSource::new_from_folder(replica.into()),
+            ConflictPolicy::PreferUpstream,
+        );
+        assert_eq!(
+            list(&prefer_upstream).await,
+            vec!["upstream only", "upstream iris", "local only"]
+        );
+        let iris = prefer_upstream.get_package("iris", FilterSettings::default()).await;
+        assert_eq!(title(iris), Some("upstream iris".into()));
+
+        std::fs::remove_dir_all(local).unwrap();
+        std::fs::remove_dir_all(replica).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_exact_name_before_precedence() {
+        let (local, replica) = ("tmp_federated_exact_local", "tmp_federated_exact_replica");
+        let _ = std::fs::remove_dir_all(local);
+        let _ = std::fs::remove_dir_all(replica);
+        write(local, "iris-classical", "local iris-classical");
+        write(replica, "iris", "upstream iris");
+
+        let source = FederatedSource::local(RootFolderSource::new_from_folder(local.into()))
+            .with_replica(
+                RootFolderSource::new_from_folder(replica.into()),
+                ConflictPolicy::PreferLocal,
+            );
+        let title = |dp: Option<DataPackage>| dp.and_then(|dp| dp.title.clone());
+        let iris = source.get_package("iris", FilterSettings::default()).await;
+        assert_eq!(title(iris), Some("upstream iris".into()));
+        let iris = source.get_package_by_name("iris", FilterSettings::default()).await;
+        assert_eq!(title(iris), Some("upstream iris".into()));
+        let classical = source.get_package("iris-cl", FilterSettings::default()).await;
+        assert_eq!(title(classical), Some("local iris-classical".into()));
+
+        std::fs::remove_dir_all(local).unwrap();
+        std::fs::remove_dir_all(replica).unwrap();
+    }
+}
@@ -0,0 +1,417 @@
+//! Full-text search index over the local cache
+//!
+//! An inverted index maps the terms of names, titles, descriptions, keywords, authors and the delta
+//! category to the packages that contain them. It is stored as json in the data folder and updated
+//! incrementally, only packages whose descriptor changed are indexed again. Results are ranked by the
+//! weight of the fields and the rarity of the terms. Query terms also match as prefix or with typos,
+//! at a lower score.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+};
+
+use color_eyre::eyre::Report;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use tracing::warn;
+
+use crate::{datapackage::DataPackage, model::authors};
+
+/// Fields of a descriptor that are indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Name,
+
+    Title,
+
+    Keyword,
+
+    Author,
+
+    Category,
+
+    Description,
+}
+
+impl Field {
+    fn weight(self) -> f32 {
+        match self {
+            Field::Name => 5.0,
+            Field::Title | Field::Keyword => 3.0,
+            Field::Author | Field::Category => 2.0,
+            Field::Description => 1.0,
+        }
+    }
+}
+
+/// Score factors of the ways a query term matches a term of the index
+const EXACT: f32 = 1.0;
+const PREFIX: f32 = 0.7;
+const ONE_TYPO: f32 = 0.6;
+const TWO_TYPOS: f32 = 0.4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Document {
+    /// sha256 of the descriptor, to detect changes
+    fingerprint: String,
+
+    /// the terms of the document, to remove its postings
+    terms: Vec<String>,
+}
+
+/// Number of documents that changed by an update
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexChanges {
+    pub added: usize,
+
+    pub updated: usize,
+
+    pub removed: usize,
+}
+
+impl IndexChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added == 0 && self.updated == 0 && self.removed == 0
+    }
+}
+
+/// A package that matches a query, see [SearchIndex::key]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub key: String,
+
+    pub score: f32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    format: u32,
+
+    documents: BTreeMap<String, Document>,
+
+    /// term -> document -> weight of the term in the document
+    postings: BTreeMap<String, BTreeMap<String, f32>>,
+}
+
+impl SearchIndex {
+    /// Name of the index file in the data folder
+    pub const FILE_NAME: &str = "search-index.json";
+
+    const FORMAT: u32 = 1;
+
+    /// Loads the index, a missing, broken or outdated index is replaced by an empty one
+    pub fn load(path: &Path) -> Self {
+        let index = match std::fs::read(path) {
+            Ok(content) => serde_json::from_slice::<SearchIndex>(&content)
+                .map_err(|err| warn!("Rebuilding the search index '{}': {}", path.display(), err))
+                .ok(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => {
+                warn!("Rebuilding the search index '{}': {}", path.display(), err);
+                None
+            }
+        };
+        index
+            .filter(|index| index.format == Self::FORMAT)
+            .unwrap_or(SearchIndex { format: Self::FORMAT, ..Default::default() })
+    }
+
+    /// Stores the index, the file is replaced atomically such that readers never see a partial index
+    pub fn save(&self, path: &Path) -> Result<(), Report> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Identifies a package in the index as `name@version`, packages without name are not indexed
+    pub fn key(package: &DataPackage) -> Option<String> {
+        let name = package.name.as_ref()?;
+        Some(format!("{}@{}", name, package.version.as_deref().unwrap_or_default()))
+    }
+
+    /// Number of indexed packages
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Brings the index in line with the packages, unchanged packages are not indexed again
+    pub fn update(&mut self, packages: &[DataPackage]) -> IndexChanges {
+        let mut changes = IndexChanges::default();
+        let mut seen = BTreeSet::new();
+        for package in packages {
+            let Some(key) = Self::key(package) else {
+                continue;
+            };
+            let fingerprint = fingerprint(package);
+            match self.documents.get(&key) {
+                Some(doc) if doc.fingerprint == fingerprint => {}
+                Some(_) => {
+                    self.remove(&key);
+                    self.insert(&key, package, fingerprint);
+                    changes.updated += 1;
+                }
+                None => {
+                    self.insert(&key, package, fingerprint);
+                    changes.added += 1;
+                }
+            }
+            seen.insert(key);
+        }
+
+        let stale: Vec<String> =
+            self.documents.keys().filter(|key| !seen.contains(*key)).cloned().collect();
+        for key in stale {
+            self.remove(&key);
+            changes.removed += 1;
+        }
+        changes
+    }
+
+    /// Finds the packages that match every term of the query, the best match first
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let tokens: BTreeSet<String> = tokenize(query).collect();
+        if tokens.is_empty() {
+            return vec![];
+        }
+
+        let documents = self.documents.len() as f32;
+        let mut scores: HashMap<&str, (usize, f32)> = HashMap::new();
+        for token in tokens.iter() {
+            // the best matching term of the document counts
+            let mut best: HashMap<&str, f32> = HashMap::new();
+            for (term, quality) in self.candidates(token) {
+                let postings = &self.postings[term];
+                let idf = (1.0 + documents / postings.len() as f32).ln();
+                for (key, weight) in postings.iter() {
+                    let score = quality * idf * (1.0 + weight).ln();
+                    let entry = best.entry(key.as_str()).or_default();
+                    *entry = entry.max(score);
+                }
+            }
+            for (key, score) in best {
+                let entry = scores.entry(key).or_default();
+                entry.0 += 1;
+                entry.1 += score;
+            }
+        }
+
+        let mut hits: Vec<SearchHit> = scores
+            .into_iter()
+            .filter(|(_, (matched, _))| *matched == tokens.len())
+            .map(|(key, (_, score))| SearchHit { key: key.to_string(), score })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.key.cmp(&b.key)));
+        hits
+    }
+
+    /// Terms of the index that match the token exactly, as prefix or with typos
+    fn candidates<'a>(&'a self, token: &str) -> Vec<(&'a str, f32)> {
+        let mut reval = vec![];
+        if let Some((term, _)) = self.postings.get_key_value(token) {
+            reval.push((term.as_str(), EXACT));
+        }
+        if token.chars().count() >= 2 {
+            let prefixed = self
+                .postings
+                .range::<str, _>((std::ops::Bound::Excluded(token), std::ops::Bound::Unbounded))
+                .take_while(|(term, _)| term.starts_with(token))
+                .map(|(term, _)| (term.as_str(), PREFIX));
+            reval.extend(prefixed);
+        }
+
+        let len = token.chars().count();
+        let max_typos = match len {
+            0..4 => 0,
+            4..8 => 1,
+            _ => 2,
+        };
+        if max_typos > 0 {
+            let typos = self
+                .postings
+                .keys()
+                .filter(|term| term.as_str() != token && !term.starts_with(token))
+                .filter(|term| term.chars().count().abs_diff(len) <= max_typos)
+                .filter_map(|term| match edit_distance(token, term) {
+                    1 => Some((term.as_str(), ONE_TYPO)),
+                    2 if max_typos >= 2 => Some((term.as_str(), TWO_TYPOS)),
+                    _ => None,
+                });
+            reval.extend(typos);
+        }
+        reval
+    }
+
+    fn insert(&mut self, key: &str, package: &DataPackage, fingerprint: String) {
+        let mut weights: BTreeMap<String, f32> = BTreeMap::new();
+        for (field, text) in fields(package) {
+            for term in tokenize(&text) {
+                *weights.entry(term).or_default() += field.weight();
+            }
+        }
+        let terms = weights.keys().cloned().collect();
+        for (term, weight) in weights {
+            self.postings.entry(term).or_default().insert(key.to_string(), weight);
+        }
+        self.documents.insert(key.to_string(), Document { fingerprint, terms });
+    }
+
+    fn remove(&mut self, key: &str) {
+        let Some(doc) = self.documents.remove(key) else {
+            return;
+        };
+        for term in doc.terms {
+            if let Some(postings) = self.postings.get_mut(&term) {
+                postings.remove(key);
+                if postings.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+}
+
+fn fingerprint(package: &DataPackage) -> String {
+    let json = serde_json::to_vec(package).unwrap_or_default();
+    hex::encode(Sha256::digest(json))
+}
+
+fn fields(package: &DataPackage) -> Vec<(Field, String)> {
+    let mut reval = vec![];
+    let mut push = |field: Field, text: Option<&String>| {
+        if let Some(text) = text {
+            reval.push((field, text.clone()));
+        }
+    };
+    push(Field::Name, package.name.as_ref());
+    push(Field::Title, package.title.as_ref());
+    push(Field::Description, package.description.as_ref());
+    for keyword in package.keywords.iter().flatten() {
+        push(Field::Keyword, Some(keyword));
+    }
+    for author in authors(package) {
+        push(Field::Author, Some(&author));
+    }
+    push(Field::Category, package.delta.as_ref().map(|delta| &delta.category));
+    reval
+}
+
+/// Lower case alphanumeric words
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|t| !t.is_empty()).map(str::to_lowercase)
+}
+
+/// Optimal string alignment distance, a transposition of neighbours counts as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut value =
+                (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                value = value.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = value;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::datapackage::{
+        DataPackageNotValidated, DataResourceNotValidated, ValidateData as _,
+    };
+
+    fn package(name: &str, title: &str, description: &str) -> DataPackage {
+        DataPackageNotValidated {
+            name: Some(name.into()),
+            version: Some("1.0.0".into()),
+            title: Some(title.into()),
+            description: Some(description.into()),
+            resources: vec![DataResourceNotValidated::default()],
+            ..Default::default()
+        }
+        .validate()
+        .unwrap()
+    }
+
+    fn keys(hits: Vec<SearchHit>) -> Vec<String> {
+        hits.into_iter().map(|hit| hit.key).collect()
+    }
+
+    #[test]
+    fn test_ranking_prefix_and_typos() {
+        let mut index = SearchIndex::default();
+        let packages = vec![
+            package("iris-classical", "Iris flower dataset", "Measurements of flowers"),
+            package("flowers-102", "Oxford flowers", "Images of 102 flower categories like iris"),
+            package("mobilenet_v3_tf2", "Mobilenet V3", "Image classification model"),
+        ];
+        let changes = index.update(&packages);
+        assert_eq!(changes, IndexChanges { added: 3, updated: 0, removed: 0 });
+
+        // a term in the name outweighs a term in the description
+        assert_eq!(keys(index.search("iris")), vec!["iris-classical@1.0.0", "flowers-102@1.0.0"]);
+        // every query term has to match
+        assert_eq!(keys(index.search("iris oxford")), vec!["flowers-102@1.0.0"]);
+        assert_eq!(keys(index.search("mobile")), vec!["mobilenet_v3_tf2@1.0.0"]);
+        assert_eq!(keys(index.search("mobilnet")), vec!["mobilenet_v3_tf2@1.0.0"]);
+        assert_eq!(keys(index.search("clasification")).len(), 1);
+        assert!(index.search("zebra").is_empty());
+        assert!(index.search("  ").is_empty());
+
+        assert_eq!(edit_distance("flwoer", "flower"), 1);
+        assert_eq!(edit_distance("iris", "irsi"), 1);
+    }
+
+    #[test]
+    fn test_incremental_update_and_persistence() {
+        let folder = PathBuf::from("tmp_search_index");
+        let _ = std::fs::remove_dir_all(&folder);
+        let path = folder.join(SearchIndex::FILE_NAME);
+
+        let mut index = SearchIndex::load(&path);
+        assert!(index.is_empty());
+        let iris = package("iris-classical", "Iris flower dataset", "Measurements");
+        let cifar = package("cifar-10-binary", "Cifar-10", "Tiny images");
+        index.update(&[iris.clone(), cifar.clone()]);
+        index.save(&path).unwrap();
+
+        let mut index = SearchIndex::load(&path);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.update(&[iris.clone(), cifar]), IndexChanges::default());
+
+        let iris = package("iris-classical", "Iris plants", "Measurements");
+        let changes = index.update(&[iris]);
+        assert_eq!(changes, IndexChanges { added: 0, updated: 1, removed: 1 });
+        assert!(index.search("flower").is_empty());
+        assert!(index.search("cifar").is_empty());
+        assert_eq!(keys(index.search("plants")), vec!["iris-classical@1.0.0"]);
+        assert!(!index.postings.contains_key("tiny"));
+
+        std::fs::write(&path, "{ broken").unwrap();
+        assert!(SearchIndex::load(&path).is_empty());
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -1,4 +1,10 @@
+pub mod blob;
+pub mod federated;
+pub mod index;
 pub mod root_folder;
+pub mod stats;
+
+use std::path::PathBuf;
 
 use async_trait::async_trait;
 use color_eyre::eyre::Report;
@@ -21,6 +27,12 @@ pub trait MetaDataSource: std::fmt::Debug {
 
     async fn get_package(&self, query: &str, filter: FilterSettings) -> Option<DataPackage>;
 
+    /// Gets the package with exactly the given name, the newest version if there are several
+    async fn get_package_by_name(&self, name: &str, filter: FilterSettings) -> Option<DataPackage>;
+
+    /// Gets the package with exactly the given name and version
+    async fn get_package_version(&self, name: &str, version: &str) -> Option<DataPackage>;
+
     async fn search_package(
         &self,
         search_query: &str,
@@ -30,4 +42,27 @@ pub trait MetaDataSource: std::fmt::Debug {
     ) -> Vec<DataPackage>;
 
     async fn put_package_metadata(&mut self, package: &DataPackage) -> Result<(), Report>;
+
+    /// Gets the folder that contains the descriptor of a package version, if it is stored on a
+    /// filesystem
+    async fn package_folder(&self, _name: &str, _version: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Orders packages by name and version, such that pages are stable
+pub(crate) fn sort_packages(packages: &mut [DataPackage]) {
+    packages.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+}
+
+/// The package with the highest version, versions that are no valid semver are the lowest
+pub(crate) fn newest<'a>(
+    packages: impl Iterator<Item = &'a DataPackage>,
+) -> Option<&'a DataPackage> {
+    packages.max_by_key(|dp| dp.version.as_deref().and_then(|v| semver::Version::parse(v).ok()))
+}
+
+/// Selects the page of the pagation settings
+pub(crate) fn paginate(packages: Vec<DataPackage>, pagation: PagationSettings) -> Vec<DataPackage> {
+    packages.into_iter().skip(pagation.offset as usize).take(pagation.limit as usize).collect()
 }
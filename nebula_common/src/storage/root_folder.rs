@@ -1,7 +1,17 @@
-use std::{collections::HashMap, fs::create_dir_all, ops::Deref, path::PathBuf, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::create_dir_all,
+    ops::Deref,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{Duration, SystemTime},
+};
 
 use color_eyre::eyre::{Report, eyre};
-use tracing::{error, info};
+use notify::{RecursiveMode, Watcher as _};
+use tokio::{sync::mpsc, task::JoinHandle, time::Interval};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
 use crate::{
@@ -11,14 +21,57 @@ use crate::{
 
 use async_trait::async_trait;
 
-use super::MetaDataSource;
+use super::{MetaDataSource, newest, paginate, sort_packages};
 
 /// Reads datapackage.json files from the filesystem
-#[derive(Debug)]
+///
+/// The source is cheap to clone, clones share the buffer. That allows a background task to keep
+/// the buffer in sync with the filesystem, see [spawn_watcher].
+#[derive(Debug, Clone)]
 pub struct RootFolderSource {
     path: PathBuf,
 
-    buf: HashMap<PathBuf, (Uuid, DataPackage)>,
+    buf: Arc<RwLock<Buffer>>,
+}
+
+#[derive(Debug, Default)]
+struct Buffer {
+    /// valid packages by the path of their descriptor
+    packages: HashMap<PathBuf, Entry>,
+
+    /// descriptors that failed to load with their modification time, to report them only once
+    rejected: HashMap<PathBuf, Option<SystemTime>>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    id: Uuid,
+
+    modified: Option<SystemTime>,
+
+    package: DataPackage,
+}
+
+/// The changes applied by [RootFolderSource::rescan]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RescanReport {
+    pub added: Vec<PathBuf>,
+
+    pub updated: Vec<PathBuf>,
+
+    pub removed: Vec<PathBuf>,
+
+    /// descriptors that could not be loaded, a previously loaded version is kept
+    pub failed: Vec<PathBuf>,
+}
+
+impl RescanReport {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.updated.is_empty()
+            && self.removed.is_empty()
+            && self.failed.is_empty()
+    }
 }
 
 fn get_datapackage_file_candidates_from_folder(
@@ -47,37 +100,234 @@ fn get_datapackage_file_candidates_from_folder(
     Ok(())
 }
 
+fn modification_time(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
 impl RootFolderSource {
     pub fn new_from_folder(path: PathBuf) -> Self {
         info!("Using root-folder data source at '{}'", path.display());
-        let mut reval = RootFolderSource { path, buf: HashMap::new() };
-        reval.sync_all().unwrap();
+        let reval = RootFolderSource { path, buf: Arc::new(RwLock::new(Buffer::default())) };
+        reval.rescan();
         reval
     }
 
-    fn sync_all(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Buffer> {
+        // a panic while holding the lock cannot leave the buffer in an inconsistent state
+        self.buf.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Buffer> {
+        self.buf.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Scans the folder for new, changed and deleted descriptors and updates the buffer incrementally
+    ///
+    /// Only descriptors with a changed modification time are parsed. The files are read without
+    /// holding the lock, so concurrent requests are only blocked while the changes are applied.
+    /// Invalid descriptors are reported and the previously loaded version is kept.
+    pub fn rescan(&self) -> RescanReport {
+        let mut report = RescanReport::default();
         let mut candidates = vec![];
-        get_datapackage_file_candidates_from_folder(&self.path, true, &mut candidates)?;
+        if let Err(err) =
+            get_datapackage_file_candidates_from_folder(&self.path, true, &mut candidates)
+        {
+            error!("Could not scan '{}' for root folder data source: {}", self.path.display(), err);
+            return report;
+        }
 
-        for candidate in candidates {
-            let path = candidate.clone();
-            match self.sync_file(candidate) {
-                Ok(_) => {
-                    info!("Loaded {}", path.display());
-                }
-                Err(_) => {
-                    error!("Could not load '{}' for root folder data source.", path.display());
+        let (known, rejected) = {
+            let buf = self.read();
+            let known: HashMap<PathBuf, Option<SystemTime>> =
+                buf.packages.iter().map(|(k, v)| (k.clone(), v.modified)).collect();
+            (known, buf.rejected.clone())
+        };
+
+        let mut changes = vec![];
+        let mut failures = vec![];
+        for candidate in candidates.iter() {
+            let modified = modification_time(candidate);
+            let unchanged = |seen: Option<&Option<SystemTime>>| {
+                modified.is_some() && seen.is_some_and(|el| *el == modified)
+            };
+            if unchanged(known.get(candidate)) || unchanged(rejected.get(candidate)) {
+                continue;
+            }
+
+            match datapackage_meta_from_file(candidate) {
+                Ok(dp) => changes.push((candidate.clone(), modified, dp)),
+                Err(err) => {
+                    error!(
+                        "Could not load '{}' for root folder data source: {}",
+                        candidate.display(),
+                        err
+                    );
+                    failures.push((candidate.clone(), modified));
                 }
             }
         }
-        Ok(())
+
+        let candidates: HashSet<PathBuf> = candidates.into_iter().collect();
+        let mut buf = self.write();
+        for (path, modified, package) in changes {
+            buf.rejected.remove(&path);
+            if let Some(entry) = buf.packages.get_mut(&path) {
+                entry.modified = modified;
+                entry.package = package;
+                info!("Reloaded {}", path.display());
+                report.updated.push(path);
+            } else {
+                let entry = Entry { id: Uuid::new_v4(), modified, package };
+                buf.packages.insert(path.clone(), entry);
+                info!("Loaded {}", path.display());
+                report.added.push(path);
+            }
+        }
+        for (path, modified) in failures {
+            buf.rejected.insert(path.clone(), modified);
+            report.failed.push(path);
+        }
+        buf.rejected.retain(|path, _| candidates.contains(path));
+        let removed: Vec<PathBuf> =
+            buf.packages.keys().filter(|path| !candidates.contains(*path)).cloned().collect();
+        for path in removed {
+            buf.packages.remove(&path);
+            info!("Removed {}", path.display());
+            report.removed.push(path);
+        }
+
+        report
     }
 
-    fn sync_file(&mut self, file_path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-        let dp = datapackage_meta_from_file(&file_path)?;
-        let id = if let Some((id, _)) = self.buf.get(&file_path) { *id } else { Uuid::new_v4() };
-        self.buf.insert(file_path, (id, dp));
-        Ok(())
+    /// The names of the loaded packages
+    pub fn package_names(&self) -> HashSet<String> {
+        self.read().packages.values().filter_map(|el| el.package.name.clone()).collect()
+    }
+
+    /// Deletes the descriptor folder of a package version as written by [MetaDataSource::put_package_metadata]
+    ///
+    /// Returns false if the package version is unknown.
+    pub fn remove_package_metadata(&self, name: &str, version: &str) -> Result<bool, Report> {
+        let dp_path = self.path.join(name).join(version).join("datapackage.json");
+        if self.write().packages.remove(&dp_path).is_none() {
+            return Ok(false);
+        }
+
+        let folder = self.path.join(name);
+        std::fs::remove_dir_all(folder.join(version))?;
+        if std::fs::read_dir(&folder)?.next().is_none() {
+            std::fs::remove_dir(&folder)?;
+        }
+        Ok(true)
+    }
+}
+
+/// Settings to keep a [RootFolderSource] in sync with the filesystem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchSettings {
+    /// use filesystem notifications to detect changes
+    pub watch: bool,
+
+    /// interval for periodic rescans, used as fallback if watching is disabled or fails
+    pub rescan_interval: Option<Duration>,
+
+    /// time to wait for further notifications before rescanning, e.g. while a file is copied
+    pub debounce: Duration,
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        Self { watch: true, rescan_interval: None, debounce: Duration::from_millis(500) }
+    }
+}
+
+/// The rescan interval if filesystem notifications are not available and no interval is given
+const FALLBACK_RESCAN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Spawns a task that rescans the folder of the source on filesystem notifications or periodically
+///
+/// The rescans run on the blocking thread pool, the task runs until it is aborted.
+pub fn spawn_watcher(source: RootFolderSource, settings: WatchSettings) -> JoinHandle<()> {
+    let (tx, mut rx) = mpsc::channel::<()>(1);
+
+    let watcher = if settings.watch {
+        let watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            if res.is_ok() {
+                // a full channel already triggers a rescan
+                let _ = tx.try_send(());
+            }
+        })
+        .and_then(|mut watcher| {
+            watcher.watch(&source.path, RecursiveMode::Recursive).map(|_| watcher)
+        });
+        match watcher {
+            Ok(watcher) => {
+                info!("Watching '{}' for changes", source.path.display());
+                Some(watcher)
+            }
+            Err(err) => {
+                warn!("Cannot watch '{}', using periodic rescans: {}", source.path.display(), err);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let interval = match (settings.rescan_interval, &watcher) {
+        (Some(interval), _) => Some(interval),
+        (None, None) => Some(FALLBACK_RESCAN_INTERVAL),
+        (None, Some(_)) => None,
+    };
+    let mut ticker = interval.map(|interval| {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        ticker
+    });
+
+    tokio::spawn(async move {
+        // the watcher stops on drop, so it has to live as long as the task
+        let _watcher = watcher;
+        if let Some(ticker) = ticker.as_mut() {
+            // the first tick completes immediately, but the source is already scanned
+            ticker.tick().await;
+        }
+
+        loop {
+            tokio::select! {
+                Some(()) = rx.recv() => {
+                    tokio::time::sleep(settings.debounce).await;
+                    while rx.try_recv().is_ok() {}
+                }
+                _ = tick(&mut ticker) => {}
+            }
+
+            let source = source.clone();
+            match tokio::task::spawn_blocking(move || source.rescan()).await {
+                Ok(report) if !report.is_empty() => info!(
+                    "Rescan: {} added, {} updated, {} removed, {} failed",
+                    report.added.len(),
+                    report.updated.len(),
+                    report.removed.len(),
+                    report.failed.len()
+                ),
+                Ok(_) => {}
+                Err(err) => error!("Rescan of root folder failed: {}", err),
+            }
+        }
+    })
+}
+
+async fn tick(ticker: &mut Option<Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => std::future::pending().await,
     }
 }
 
@@ -86,36 +336,76 @@ impl MetaDataSource for RootFolderSource {
     async fn list_packages(
         &self,
         _sort: SortSettings,
-        _filter: FilterSettings,
-        _pagation: PagationSettings,
+        filter: FilterSettings,
+        pagation: PagationSettings,
         _fields: FieldSettings,
     ) -> Vec<DataPackage> {
-        self.buf.values().map(|(_, v)| v).cloned().collect()
+        let mut packages: Vec<DataPackage> = self
+            .read()
+            .packages
+            .values()
+            .filter(|el| filter.matches(&el.package) && filter.matches_updated(el.modified))
+            .map(|el| el.package.clone())
+            .collect();
+        sort_packages(&mut packages);
+        paginate(packages, pagation)
     }
 
-    async fn get_package(&self, query: &str, _filter: FilterSettings) -> Option<DataPackage> {
+    async fn get_package(&self, query: &str, filter: FilterSettings) -> Option<DataPackage> {
         info!("get_package");
-        self.buf
-            .values()
-            .for_each(|el| info!("id: {}, name='{}'", el.0, el.1.name.clone().unwrap()));
+        let buf = self.read();
+        buf.packages.values().for_each(|el| {
+            info!("id: {}, name='{}'", el.id, el.package.name.clone().unwrap_or_default())
+        });
+
+        let packages = || buf.packages.values().map(|el| &el.package).filter(|v| filter.matches(v));
+        if let Some(package) = newest(packages().filter(|v| v.name.as_deref() == Some(query))) {
+            return Some(package.clone());
+        }
+        // the buffer has no order, the shortest name that contains the query is the closest match
+        packages()
+            .filter(|v| v.name.as_ref().is_some_and(|el| el.contains(query)))
+            .min_by_key(|v| (v.name.as_ref().map(String::len), v.name.clone()))
+            .cloned()
+    }
+
+    async fn get_package_by_name(&self, name: &str, filter: FilterSettings) -> Option<DataPackage> {
+        let buf = self.read();
+        newest(
+            buf.packages
+                .values()
+                .map(|el| &el.package)
+                .filter(|v| filter.matches(v) && v.name.as_deref() == Some(name)),
+        )
+        .cloned()
+    }
 
-        self.buf
+    async fn get_package_version(&self, name: &str, version: &str) -> Option<DataPackage> {
+        self.read()
+            .packages
             .values()
-            .filter_map(|(_, v)| {
-                if v.name.clone().is_some_and(|el| el.contains(query)) { Some(v) } else { None }
-            })
-            .nth(0)
+            .map(|el| &el.package)
+            .find(|v| v.name.as_deref() == Some(name) && v.version.as_deref() == Some(version))
             .cloned()
     }
 
     async fn search_package(
         &self,
-        _search_query: &str,
+        search_query: &str,
         _sort: SortSettings,
-        _filter: FilterSettings,
-        _pagation: PagationSettings,
+        filter: FilterSettings,
+        pagation: PagationSettings,
     ) -> Vec<DataPackage> {
-        todo!()
+        let mut packages: Vec<DataPackage> = self
+            .read()
+            .packages
+            .values()
+            .filter(|el| filter.matches(&el.package) && filter.matches_updated(el.modified))
+            .filter(|el| filter.kind.matches(search_query, &el.package))
+            .map(|el| el.package.clone())
+            .collect();
+        sort_packages(&mut packages);
+        paginate(packages, pagation)
     }
 
     async fn put_package_metadata(&mut self, package: &DataPackage) -> Result<(), Report> {
@@ -151,10 +441,24 @@ impl MetaDataSource for RootFolderSource {
         std::fs::write(&dp_path, json).unwrap();
 
         // save to local buffer:
-        self.buf.insert(dp_path, (id, package.clone()));
+        let modified = modification_time(&dp_path);
+        let entry = Entry { id, modified, package: package.clone() };
+        self.write().packages.insert(dp_path, entry);
 
         Ok(())
     }
+
+    async fn package_folder(&self, name: &str, version: &str) -> Option<PathBuf> {
+        // the folder of the same entry that get_package_version returns
+        self.read()
+            .packages
+            .iter()
+            .find(|(_, v)| {
+                v.package.name.as_deref() == Some(name)
+                    && v.package.version.as_deref() == Some(version)
+            })
+            .and_then(|(path, _)| path.parent().map(|p| p.to_path_buf()))
+    }
 }
 
 #[cfg(test)]
@@ -162,7 +466,10 @@ mod test {
     use std::str::FromStr;
 
     use super::*;
-    use crate::datapackage::{DataPackageNotValidated, DataResourceNotValidated, ValidateData};
+    use crate::{
+        datapackage::{DataPackageNotValidated, DataResourceNotValidated, ValidateData},
+        model::PackageType,
+    };
 
     fn generate_example_dp() -> DataPackageNotValidated {
         let res = DataResourceNotValidated { name: "iris.csv".into(), ..Default::default() };
@@ -177,7 +484,8 @@ mod test {
     #[tokio::test]
     #[should_panic]
     pub async fn test_put_missing_version() {
-        let mut rf = RootFolderSource::new_from_folder(PathBuf::from_str("tmp").unwrap());
+        let mut rf =
+            RootFolderSource::new_from_folder(PathBuf::from_str("tmp_put_missing").unwrap());
         let package = generate_example_dp();
 
         let dp = package.validate().unwrap();
@@ -186,13 +494,143 @@ mod test {
 
     #[tokio::test]
     pub async fn test_put_valid() {
-        let mut rf = RootFolderSource::new_from_folder(PathBuf::from_str("tmp").unwrap());
+        let root = PathBuf::from_str("tmp_put_valid").unwrap();
+        let mut rf = RootFolderSource::new_from_folder(root.clone());
         let mut package = generate_example_dp();
         package.version = Some("0.1.0".into());
 
         let dp = package.validate().unwrap();
         rf.put_package_metadata(&dp).await.unwrap();
 
-        assert!(std::fs::exists(PathBuf::from_str("./tmp/datapackage.json").unwrap()).is_ok())
+        assert!(root.join("iris").join("0.1.0").join("datapackage.json").is_file());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_get_package_by_exact_name() {
+        let root = PathBuf::from_str("tmp_exact_name").unwrap();
+        let _ = std::fs::remove_dir_all(&root);
+        let mut rf = RootFolderSource::new_from_folder(root.clone());
+        for (name, version) in [("iris-classical", "2.0.0"), ("iris", "0.9.0"), ("iris", "0.10.0")]
+        {
+            let mut package = generate_example_dp();
+            package.name = Some(name.into());
+            package.version = Some(version.into());
+            rf.put_package_metadata(&package.validate().unwrap()).await.unwrap();
+        }
+
+        let version = |dp: Option<DataPackage>| dp.and_then(|dp| dp.version.clone());
+        let iris = rf.get_package_by_name("iris", FilterSettings::default()).await;
+        assert_eq!(version(iris), Some("0.10.0".into()));
+        assert!(rf.get_package_by_name("iris-c", FilterSettings::default()).await.is_none());
+
+        // the partial lookup prefers the exact name as well
+        let iris = rf.get_package("iris", FilterSettings::default()).await;
+        assert_eq!(version(iris), Some("0.10.0".into()));
+        let classical = rf.get_package("iris-c", FilterSettings::default()).await;
+        assert_eq!(version(classical), Some("2.0.0".into()));
+
+        // older versions are found with their own folder
+        let old = rf.get_package_version("iris", "0.9.0").await;
+        assert_eq!(version(old), Some("0.9.0".into()));
+        assert!(rf.get_package_version("iris", "1.0.0").await.is_none());
+        let folder = rf.package_folder("iris", "0.9.0").await;
+        assert_eq!(folder, Some(root.join("iris").join("0.9.0")));
+        assert!(rf.package_folder("iris-classical", "0.9.0").await.is_none());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_search_local() {
+        let mut rf = RootFolderSource::new_from_folder(PathBuf::from_str("tmp_search").unwrap());
+        let mut package = generate_example_dp();
+        package.version = Some("0.1.0".into());
+        package.title = Some("Iris flower dataset".into());
+
+        let dp = package.validate().unwrap();
+        rf.put_package_metadata(&dp).await.unwrap();
+
+        let search = async |query: &str| {
+            rf.search_package(
+                query,
+                SortSettings::default(),
+                FilterSettings::default(),
+                PagationSettings::default(),
+            )
+            .await
+        };
+        assert_eq!(search("IRIS").await.len(), 1);
+        assert_eq!(search("flower").await.len(), 1);
+        assert!(search("cifar").await.is_empty());
+
+        std::fs::remove_dir_all("tmp_search").unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_filter_package_type() {
+        let rf = RootFolderSource::new_from_folder("../nebula_registry/data".into());
+        let list = async |package_type: PackageType| {
+            let filter = FilterSettings { package_type, ..Default::default() };
+            let packages = rf
+                .list_packages(
+                    SortSettings::default(),
+                    filter,
+                    PagationSettings::unlimited(),
+                    FieldSettings::default(),
+                )
+                .await;
+            packages.into_iter().filter_map(|dp| dp.name.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(list(PackageType::Model).await, vec!["mobilenet_v3_tf2"]);
+        assert_eq!(list(PackageType::Dataset).await, vec!["cifar-10-binary", "iris-classical"]);
+        assert_eq!(list(PackageType::Both).await.len(), 3);
+
+        let filter = FilterSettings { package_type: PackageType::Dataset, ..Default::default() };
+        assert!(rf.get_package("mobilenet_v3_tf2", filter).await.is_none());
+    }
+
+    #[test]
+    pub fn test_rescan_incremental() {
+        let root = PathBuf::from_str("tmp_rescan").unwrap();
+        let _ = std::fs::remove_dir_all(&root);
+        let folder = root.join("iris");
+        create_dir_all(&folder).unwrap();
+        let dp_path = folder.join("datapackage.json");
+        let write = |package: &DataPackageNotValidated| {
+            std::fs::write(&dp_path, serde_json::to_string(package).unwrap()).unwrap();
+            // ensure a different modification time for consecutive writes
+            std::thread::sleep(Duration::from_millis(20));
+        };
+
+        let mut package = generate_example_dp();
+        write(&package);
+        let rf = RootFolderSource::new_from_folder(root.clone());
+        assert_eq!(rf.read().packages.len(), 1);
+
+        // nothing changed
+        assert!(rf.rescan().is_empty());
+
+        // changed descriptor is reloaded
+        package.title = Some("Iris flower dataset".into());
+        write(&package);
+        let report = rf.rescan();
+        assert_eq!(report.updated, vec![dp_path.clone()]);
+        assert_eq!(rf.read().packages[&dp_path].package.title, package.title);
+
+        // invalid descriptor is reported once and the previous version is kept
+        std::fs::write(&dp_path, "{ invalid").unwrap();
+        let report = rf.rescan();
+        assert_eq!(report.failed, vec![dp_path.clone()]);
+        assert!(rf.rescan().is_empty());
+        assert_eq!(rf.read().packages[&dp_path].package.title, package.title);
+
+        // deleted descriptor is removed
+        std::fs::remove_file(&dp_path).unwrap();
+        let report = rf.rescan();
+        assert_eq!(report.removed, vec![dp_path.clone()]);
+        assert!(rf.read().packages.is_empty());
+
+        std::fs::remove_dir_all(root).unwrap();
     }
 }
@@ -1,3 +1,3 @@
 //! not implemented yet
-//! 
-//! [sqlx](https://github.com/launchbadge/sqlx) with query builder
\ No newline at end of file
+//!
+//! [sqlx](https://github.com/launchbadge/sqlx) with query builder
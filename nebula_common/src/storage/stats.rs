@@ -0,0 +1,189 @@
+//! Download statistics of the registry
+//!
+//! Every download is appended as one json line to a log file, such that no event is lost if the
+//! registry crashes. On startup the log is replayed into per-day counters, which answer the
+//! queries for totals and time-bucketed counts without touching the log again.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use color_eyre::eyre::Report;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::model::DateRange;
+
+/// One download of a package version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadEvent {
+    pub name: String,
+
+    pub version: String,
+
+    /// unix timestamp in seconds
+    pub timestamp: i64,
+}
+
+/// downloads per version and day of one package
+type PackageCounts = HashMap<String, BTreeMap<NaiveDate, u64>>;
+
+/// Records download events and aggregates them per package, version and day
+#[derive(Debug)]
+pub struct DownloadStats {
+    log: Mutex<File>,
+
+    counts: RwLock<HashMap<String, PackageCounts>>,
+}
+
+impl DownloadStats {
+    /// Opens the log at the given path and replays the recorded events
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, Report> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut counts: HashMap<String, PackageCounts> = HashMap::new();
+        if path.exists() {
+            for (nr, line) in BufReader::new(File::open(&path)?).lines().enumerate() {
+                let line = line?;
+                // a crash may leave a partially written last line
+                match serde_json::from_str::<DownloadEvent>(&line) {
+                    Ok(event) => count(&mut counts, &event),
+                    Err(err) => warn!("Skipping line {} of '{}': {}", nr + 1, path.display(), err),
+                }
+            }
+        }
+        info!("Download statistics '{}' contain {} packages", path.display(), counts.len());
+
+        let mut log = OpenOptions::new().create(true).append(true).open(&path)?;
+        // terminate a partial line, otherwise it would swallow the next event
+        if ends_partial(&path)? {
+            writeln!(log)?;
+        }
+        Ok(DownloadStats { log: Mutex::new(log), counts: RwLock::new(counts) })
+    }
+
+    /// Records a download that happens now
+    pub fn record(&self, name: &str, version: &str) -> Result<(), Report> {
+        self.record_at(name, version, Utc::now())
+    }
+
+    pub fn record_at(&self, name: &str, version: &str, at: DateTime<Utc>) -> Result<(), Report> {
+        let event = DownloadEvent {
+            name: name.to_string(),
+            version: version.to_string(),
+            timestamp: at.timestamp(),
+        };
+        {
+            let mut log = self.log.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            writeln!(log, "{}", serde_json::to_string(&event)?)?;
+        }
+        count(&mut self.counts.write().unwrap_or_else(|poisoned| poisoned.into_inner()), &event);
+        Ok(())
+    }
+
+    /// Counts the downloads of a package in the range, of one version or of all versions
+    pub fn downloads(&self, name: &str, version: Option<&str>, range: &DateRange) -> u64 {
+        let counts = self.counts.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(versions) = counts.get(name) else {
+            return 0;
+        };
+        versions
+            .iter()
+            .filter(|(v, _)| version.is_none_or(|version| version == v.as_str()))
+            .flat_map(|(_, days)| days.iter())
+            .filter(|(day, _)| range.contains(day))
+            .map(|(_, n)| n)
+            .sum()
+    }
+
+    /// The downloads of all versions of a package per day, ordered by day
+    pub fn history(&self, name: &str, range: &DateRange) -> Vec<(NaiveDate, u64)> {
+        let counts = self.counts.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut reval: BTreeMap<NaiveDate, u64> = BTreeMap::new();
+        for days in counts.get(name).into_iter().flat_map(|versions| versions.values()) {
+            for (day, n) in days.iter().filter(|(day, _)| range.contains(day)) {
+                *reval.entry(*day).or_default() += n;
+            }
+        }
+        reval.into_iter().collect()
+    }
+}
+
+fn ends_partial(path: &Path) -> Result<bool, std::io::Error> {
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() == 0 {
+        return Ok(false);
+    }
+    let mut last = [0u8; 1];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    Ok(last[0] != b'\n')
+}
+
+fn count(counts: &mut HashMap<String, PackageCounts>, event: &DownloadEvent) {
+    let Some(day) = DateTime::from_timestamp(event.timestamp, 0).map(|at| at.date_naive()) else {
+        warn!("Ignoring download of '{}' with invalid timestamp", event.name);
+        return;
+    };
+    *counts
+        .entry(event.name.clone())
+        .or_default()
+        .entry(event.version.clone())
+        .or_default()
+        .entry(day)
+        .or_default() += 1;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(day: &str) -> DateTime<Utc> {
+        let date = NaiveDate::parse_from_str(day, "%Y%m%d").unwrap();
+        date.and_hms_opt(12, 0, 0).unwrap().and_utc()
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = PathBuf::from("tmp_stats/downloads.jsonl");
+        let _ = std::fs::remove_dir_all("tmp_stats");
+
+        let stats = DownloadStats::open(&path).unwrap();
+        stats.record_at("iris", "1.0.0", at("20250101")).unwrap();
+        stats.record_at("iris", "1.0.0", at("20250101")).unwrap();
+        stats.record_at("iris", "2.0.0", at("20250103")).unwrap();
+        stats.record_at("cifar", "1.0.0", at("20250102")).unwrap();
+
+        let all = DateRange::default();
+        assert_eq!(stats.downloads("iris", None, &all), 3);
+        assert_eq!(stats.downloads("iris", Some("1.0.0"), &all), 2);
+        assert_eq!(stats.downloads("unknown", None, &all), 0);
+
+        let since = DateRange::from_strs(Some("20250102"), None).unwrap();
+        assert_eq!(stats.downloads("iris", None, &since), 1);
+
+        // a crash may leave a partial line that is skipped on replay
+        drop(stats);
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"name\":\"ir")
+            .unwrap();
+        let stats = DownloadStats::open(&path).unwrap();
+        stats.record_at("iris", "2.0.0", at("20250103")).unwrap();
+        let stats = DownloadStats::open(&path).unwrap();
+        let day = |s: &str| NaiveDate::parse_from_str(s, "%Y%m%d").unwrap();
+        assert_eq!(stats.history("iris", &all), vec![(day("20250101"), 2), (day("20250103"), 2)]);
+
+        std::fs::remove_dir_all("tmp_stats").unwrap();
+    }
+}
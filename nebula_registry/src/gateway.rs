@@ -0,0 +1,1158 @@
+//! A REST/JSON gateway in front of the gRPC endpoints
+//!
+//! The handlers translate query parameters into the protobuf requests and call the same
+//! [NebulaPackageQuery] implementation that serves gRPC, such that both interfaces share their
+//! semantics. The OpenAPI description is generated from the annotated routes via [utoipa].
+
+use std::{
+    collections::BTreeMap,
+    io::SeekFrom,
+    path::{Component, Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::{Path as UrlPath, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncSeekExt as _, ReadBuf};
+use tokio_util::io::ReaderStream;
+use tonic::{Code, Request};
+use tracing::info;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use nebula_common::{
+    datapackage::DataPackage,
+    model::FilterSettings,
+    nebula_proto::{
+        Author, DateRange, DeltaMetadata, DownloadCount, FieldOptions, License,
+        ListPackagesRequest, ModelMetadata, PackageInfo, PackageList, PackageRequest,
+        PackageType as PbPackageType, SearchKind, SearchPackagesRequest, SortOption, TensorSpec,
+        nebula_package_query_server::NebulaPackageQuery,
+    },
+    registry::NebulaPackageQueryMockImpl,
+    storage::{
+        MetaDataSource,
+        blob::{BlobDigest, mirror::Mirrored},
+        federated::FederatedSource,
+    },
+};
+
+pub type GatewayState = Arc<NebulaPackageQueryMockImpl<FederatedSource>>;
+
+#[derive(OpenApi)]
+#[openapi(info(
+    title = "Nebula Registry",
+    description = "REST/JSON gateway of the Nebula registry, mirrors the gRPC interface"
+))]
+struct ApiDoc;
+
+fn api_router() -> OpenApiRouter<GatewayState> {
+    OpenApiRouter::with_openapi(ApiDoc::openapi())
+        .routes(routes!(list_packages))
+        .routes(routes!(get_package))
+        .routes(routes!(search_packages))
+        .routes(routes!(download_resource))
+}
+
+/// Creates the router of the gateway including the route `/openapi.json`
+pub fn router(state: GatewayState) -> Router {
+    let (router, api) = api_router().with_state(state).split_for_parts();
+
+    router.route("/openapi.json", get(move || async move { Json(api) }))
+}
+
+/// Serves the gateway on the given address until the server fails
+pub async fn serve(addr: std::net::SocketAddr, state: GatewayState) -> Result<(), std::io::Error> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("REST/JSON gateway - running on: '{}'", addr);
+    axum::serve(listener, router(state)).await
+}
+
+/// An error response with a json body
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorJson {
+    pub code: u16,
+
+    pub message: String,
+}
+
+#[derive(Debug)]
+struct GatewayError(StatusCode, String);
+
+impl From<tonic::Status> for GatewayError {
+    fn from(status: tonic::Status) -> Self {
+        let code = match status.code() {
+            Code::Ok => StatusCode::OK,
+            Code::InvalidArgument | Code::OutOfRange => StatusCode::BAD_REQUEST,
+            Code::NotFound => StatusCode::NOT_FOUND,
+            Code::AlreadyExists => StatusCode::CONFLICT,
+            Code::PermissionDenied => StatusCode::FORBIDDEN,
+            Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+            Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+            Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+            Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        GatewayError(code, status.message().to_string())
+    }
+}
+
+impl IntoResponse for GatewayError {
+    fn into_response(self) -> Response {
+        let body = ErrorJson { code: self.0.as_u16(), message: self.1 };
+        (self.0, Json(body)).into_response()
+    }
+}
+
+/// Type of packages, dataset, model or both
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageTypeParam {
+    #[default]
+    Both,
+
+    Dataset,
+
+    Model,
+}
+
+impl From<PackageTypeParam> for PbPackageType {
+    fn from(value: PackageTypeParam) -> Self {
+        match value {
+            PackageTypeParam::Both => PbPackageType::Both,
+            PackageTypeParam::Dataset => PbPackageType::Dataset,
+            PackageTypeParam::Model => PbPackageType::Model,
+        }
+    }
+}
+
+impl From<PbPackageType> for PackageTypeParam {
+    fn from(value: PbPackageType) -> Self {
+        match value {
+            PbPackageType::Both => PackageTypeParam::Both,
+            PbPackageType::Dataset => PackageTypeParam::Dataset,
+            PbPackageType::Model => PackageTypeParam::Model,
+        }
+    }
+}
+
+/// Search method
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKindParam {
+    Relaxed,
+
+    Name,
+
+    Author,
+}
+
+impl From<SearchKindParam> for SearchKind {
+    fn from(value: SearchKindParam) -> Self {
+        match value {
+            SearchKindParam::Relaxed => SearchKind::Relaxed,
+            SearchKindParam::Name => SearchKind::SubstrPackageName,
+            SearchKindParam::Author => SearchKind::SubstrAuthor,
+        }
+    }
+}
+
+/// Sort order of packages, downloads are sorted descending
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortParam {
+    CreationDate,
+
+    Downloads,
+
+    Name,
+
+    Author,
+}
+
+impl From<SortParam> for SortOption {
+    fn from(value: SortParam) -> Self {
+        match value {
+            SortParam::CreationDate => SortOption::CreationDate,
+            SortParam::Downloads => SortOption::Downloads,
+            SortParam::Name => SortOption::Name,
+            SortParam::Author => SortOption::Author,
+        }
+    }
+}
+
+/// The range of days in which downloads are counted, None if both ends are open
+fn download_range(since: Option<String>, until: Option<String>) -> Option<DateRange> {
+    (since.is_some() || until.is_some()).then_some(DateRange { start: since, end: until })
+}
+
+/// Downloads of a package on one day
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DownloadCountJson {
+    /// YYYYMMDD
+    pub date: String,
+
+    pub count: u64,
+}
+
+impl From<DownloadCount> for DownloadCountJson {
+    fn from(value: DownloadCount) -> Self {
+        DownloadCountJson { date: value.date, count: value.count }
+    }
+}
+
+/// License of a package
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LicenseJson {
+    pub name: String,
+
+    pub path: String,
+
+    pub title: Option<String>,
+}
+
+impl From<License> for LicenseJson {
+    fn from(value: License) -> Self {
+        LicenseJson { name: value.name, path: value.path, title: value.title }
+    }
+}
+
+/// Author of a package
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuthorJson {
+    pub title: Option<String>,
+
+    pub given_name: Option<String>,
+
+    pub family_name: Option<String>,
+
+    pub path: Option<String>,
+
+    pub email: Option<String>,
+
+    pub roles: Vec<String>,
+
+    pub organization: Option<String>,
+}
+
+impl From<Author> for AuthorJson {
+    fn from(value: Author) -> Self {
+        AuthorJson {
+            title: value.title,
+            given_name: value.given_name,
+            family_name: value.family_name,
+            path: value.path,
+            email: value.email,
+            roles: value.roles,
+            organization: value.organization,
+        }
+    }
+}
+
+/// Delta extension of a package
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeltaJson {
+    pub category: String,
+
+    pub classes: Option<u32>,
+
+    pub input_shape: String,
+
+    pub training_count: Option<u32>,
+
+    pub validation_count: Option<u32>,
+
+    pub test_count: Option<u32>,
+
+    pub model: Option<ModelJson>,
+
+    /// package names with their semver requirement
+    pub dependencies: BTreeMap<String, String>,
+}
+
+impl From<DeltaMetadata> for DeltaJson {
+    fn from(value: DeltaMetadata) -> Self {
+        DeltaJson {
+            category: value.category,
+            classes: value.classes,
+            input_shape: value.input_shape,
+            training_count: value.training_count,
+            validation_count: value.validation_count,
+            test_count: value.test_count,
+            model: value.model.map(ModelJson::from),
+            dependencies: value.dependencies.into_iter().collect(),
+        }
+    }
+}
+
+/// Metadata of a model
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ModelJson {
+    pub framework: Option<String>,
+
+    pub format: Option<String>,
+
+    pub inputs: Vec<TensorSpecJson>,
+
+    pub outputs: Vec<TensorSpecJson>,
+
+    /// name of the dataset package, optionally as name@version
+    pub training_dataset: Option<String>,
+}
+
+impl From<ModelMetadata> for ModelJson {
+    fn from(value: ModelMetadata) -> Self {
+        ModelJson {
+            framework: value.framework,
+            format: value.format,
+            inputs: value.inputs.into_iter().map(TensorSpecJson::from).collect(),
+            outputs: value.outputs.into_iter().map(TensorSpecJson::from).collect(),
+            training_dataset: value.training_dataset,
+        }
+    }
+}
+
+/// Input or output tensor of a model, -1 marks a dynamic dimension
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TensorSpecJson {
+    pub name: Option<String>,
+
+    pub dtype: String,
+
+    pub shape: Vec<i64>,
+}
+
+impl From<TensorSpec> for TensorSpecJson {
+    fn from(value: TensorSpec) -> Self {
+        TensorSpecJson { name: value.name, dtype: value.dtype, shape: value.shape }
+    }
+}
+
+/// Package information as json, the datapackage descriptor is embedded as json object
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PackageInfoJson {
+    pub name: String,
+
+    pub version: String,
+
+    pub title: Option<String>,
+
+    pub description: String,
+
+    pub package_type: PackageTypeParam,
+
+    pub download_size: u64,
+
+    pub installed_size: u64,
+
+    pub licenses: Vec<LicenseJson>,
+
+    pub authors: Vec<AuthorJson>,
+
+    pub keywords: Vec<String>,
+
+    /// ISO 8601 timestamp of the descriptor
+    pub created: Option<String>,
+
+    pub delta: Option<DeltaJson>,
+
+    #[schema(value_type = Option<Object>)]
+    pub datapackage: Option<serde_json::Value>,
+
+    pub preview_images: Vec<String>,
+
+    pub downloads: u64,
+
+    pub version_downloads: u64,
+
+    pub download_history: Vec<DownloadCountJson>,
+}
+
+impl From<PackageInfo> for PackageInfoJson {
+    fn from(value: PackageInfo) -> Self {
+        PackageInfoJson {
+            package_type: PackageTypeParam::from(value.package_type()),
+            datapackage: value.datapackage_json.and_then(|json| serde_json::from_str(&json).ok()),
+            name: value.name,
+            version: value.version,
+            title: value.title,
+            description: value.description,
+            download_size: value.download_size,
+            installed_size: value.installed_size,
+            licenses: value.licenses.into_iter().map(LicenseJson::from).collect(),
+            authors: value.authors.into_iter().map(AuthorJson::from).collect(),
+            keywords: value.keywords,
+            created: value.created,
+            delta: value.delta.map(DeltaJson::from),
+            preview_images: value.preview_images,
+            downloads: value.downloads,
+            version_downloads: value.version_downloads,
+            download_history: value
+                .download_history
+                .into_iter()
+                .map(DownloadCountJson::from)
+                .collect(),
+        }
+    }
+}
+
+/// A list of packages as json
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PackageListJson {
+    pub packages: Vec<PackageInfoJson>,
+
+    pub total_count: i32,
+
+    pub limit: Option<i32>,
+
+    pub offset: Option<i32>,
+}
+
+impl From<PackageList> for PackageListJson {
+    fn from(value: PackageList) -> Self {
+        PackageListJson {
+            packages: value.packages.into_iter().map(PackageInfoJson::from).collect(),
+            total_count: value.total_count,
+            limit: value.limit,
+            offset: value.offset,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListParams {
+    /// filters by dataset, model or both
+    package_type: Option<PackageTypeParam>,
+
+    /// limit the number of results
+    limit: Option<i32>,
+
+    /// offset for pagination
+    offset: Option<i32>,
+
+    /// sort order of the packages
+    sort: Option<SortParam>,
+
+    /// counts downloads since this day, YYYYMMDD
+    downloads_since: Option<String>,
+
+    /// counts downloads until this day, YYYYMMDD
+    downloads_until: Option<String>,
+
+    /// embeds the datapackage descriptor
+    #[serde(default)]
+    include_datapackage: bool,
+
+    /// adds the downloads per day
+    #[serde(default)]
+    include_download_history: bool,
+
+    /// adds the preview images as urls or base64 encoded thumbnails
+    #[serde(default)]
+    include_preview_images: bool,
+}
+
+/// List all packages with very simple search criteria
+#[utoipa::path(
+    get,
+    path = "/v1/packages",
+    params(ListParams),
+    responses(
+        (status = OK, body = PackageListJson),
+        (status = BAD_REQUEST, body = ErrorJson),
+    )
+)]
+async fn list_packages(
+    State(state): State<GatewayState>,
+    Query(params): Query<ListParams>,
+) -> Result<Json<PackageListJson>, GatewayError> {
+    let request = ListPackagesRequest {
+        field_options: Some(FieldOptions {
+            include_datapackage_json: params.include_datapackage,
+            include_preview_images: params.include_preview_images,
+            include_download_history: params.include_download_history,
+        }),
+        package_type: PbPackageType::from(params.package_type.unwrap_or_default()) as i32,
+        sort: params.sort.map(|sort| SortOption::from(sort) as i32),
+        limit: params.limit,
+        offset: params.offset,
+        download_range: download_range(params.downloads_since, params.downloads_until),
+    };
+    let response = state.list_packages(Request::new(request)).await?;
+    Ok(Json(response.into_inner().into()))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GetParams {
+    /// filters by dataset, model or both
+    package_type: Option<PackageTypeParam>,
+}
+
+/// Gets detailed information for one specific package
+#[utoipa::path(
+    get,
+    path = "/v1/packages/{name}",
+    params(("name" = String, Path, description = "exact name of the package"), GetParams),
+    responses(
+        (status = OK, body = PackageInfoJson),
+        (status = NOT_FOUND, body = ErrorJson),
+    )
+)]
+async fn get_package(
+    State(state): State<GatewayState>,
+    UrlPath(name): UrlPath<String>,
+    Query(params): Query<GetParams>,
+) -> Result<Json<PackageInfoJson>, GatewayError> {
+    let request = PackageRequest {
+        search_query: name,
+        package_type: params.package_type.map(|pt| PbPackageType::from(pt) as i32),
+    };
+    let response = state.get_package_info(Request::new(request)).await?;
+    Ok(Json(response.into_inner().into()))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchParams {
+    /// general text search across multiple fields
+    q: String,
+
+    /// search method
+    kind: Option<SearchKindParam>,
+
+    /// filters by dataset, model or both
+    package_type: Option<PackageTypeParam>,
+
+    /// limit the number of results
+    limit: Option<i32>,
+
+    /// offset for pagination
+    offset: Option<i32>,
+
+    /// sort order of the packages
+    sort: Option<SortParam>,
+
+    /// minimum number of downloads
+    min_downloads: Option<i32>,
+
+    /// maximum number of downloads
+    max_downloads: Option<i32>,
+
+    /// counts downloads since this day, YYYYMMDD
+    downloads_since: Option<String>,
+
+    /// counts downloads until this day, YYYYMMDD
+    downloads_until: Option<String>,
+
+    /// embeds the datapackage descriptor
+    #[serde(default)]
+    include_datapackage: bool,
+
+    /// adds the downloads per day
+    #[serde(default)]
+    include_download_history: bool,
+
+    /// adds the preview images as urls or base64 encoded thumbnails
+    #[serde(default)]
+    include_preview_images: bool,
+}
+
+/// Search packages applying several filters
+#[utoipa::path(
+    get,
+    path = "/v1/search",
+    params(SearchParams),
+    responses(
+        (status = OK, body = PackageListJson),
+        (status = BAD_REQUEST, body = ErrorJson),
+    )
+)]
+async fn search_packages(
+    State(state): State<GatewayState>,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<PackageListJson>, GatewayError> {
+    let request = SearchPackagesRequest {
+        field_options: Some(FieldOptions {
+            include_datapackage_json: params.include_datapackage,
+            include_preview_images: params.include_preview_images,
+            include_download_history: params.include_download_history,
+        }),
+        search_query: params.q,
+        package_type: PbPackageType::from(params.package_type.unwrap_or_default()) as i32,
+        sort: params.sort.map(|sort| SortOption::from(sort) as i32).into_iter().collect(),
+        limit: params.limit,
+        offset: params.offset,
+        kind: params.kind.map(|kind| SearchKind::from(kind) as i32),
+        min_downloads: params.min_downloads,
+        max_downloads: params.max_downloads,
+        download_range: download_range(params.downloads_since, params.downloads_until),
+        ..Default::default()
+    };
+    let response = state.search_packages(Request::new(request)).await?;
+    Ok(Json(response.into_inner().into()))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DownloadParams {
+    /// index of the file for resources that consist of several files
+    #[serde(default)]
+    index: usize,
+
+    /// version of the package, the newest version if not given
+    version: Option<String>,
+}
+
+/// Downloads a resource of a package
+///
+/// Files and blobs hosted by the registry are streamed, remote resources are redirected to their url
+/// or served through the mirror if it is enabled. An install requests every file of the package, only
+/// the first file of the first downloaded resource counts as a download of the package. It is counted
+/// once its last byte has been sent, or when it is redirected, such that interrupted and resumed
+/// downloads count once. A single byte range is answered with the partial content, several ranges are
+/// not supported and answered with the complete content.
+#[utoipa::path(
+    get,
+    path = "/v1/packages/{name}/resources/{resource}",
+    params(
+        ("name" = String, Path, description = "exact name of the package"),
+        ("resource" = String, Path, description = "name of the resource"),
+        DownloadParams
+    ),
+    responses(
+        (status = OK, description = "content of the resource", content_type = "application/octet-stream"),
+        (status = PARTIAL_CONTENT, description = "requested range of the resource", content_type = "application/octet-stream"),
+        (status = RANGE_NOT_SATISFIABLE, description = "requested range starts behind the end of the resource"),
+        (status = TEMPORARY_REDIRECT, description = "resource is hosted remotely"),
+        (status = BAD_GATEWAY, body = ErrorJson, description = "mirroring the remote resource failed"),
+        (status = NOT_FOUND, body = ErrorJson),
+    )
+)]
+async fn download_resource(
+    State(state): State<GatewayState>,
+    UrlPath((name, resource)): UrlPath<(String, String)>,
+    Query(params): Query<DownloadParams>,
+    headers: HeaderMap,
+) -> Result<Response, GatewayError> {
+    let ds = state.data_source();
+    let package: Option<DataPackage> = match params.version.as_deref() {
+        Some(version) => ds.get_package_version(&name, version).await,
+        None => ds.get_package_by_name(&name, FilterSettings::default()).await,
+    };
+    let package = package.ok_or_else(|| {
+        let spec = params.version.as_ref().map(|v| format!("{}@{}", name, v)).unwrap_or(name);
+        GatewayError(StatusCode::NOT_FOUND, format!("Package '{}' not found", spec))
+    })?;
+    let on_complete: OnComplete =
+        counts_as_download(&package, &resource, params.index).then(|| {
+            let (state, package) = (state.clone(), package.clone());
+            Box::new(move || state.record_download(&package)) as Box<dyn FnOnce() + Send>
+        });
+    resource_response(&state, &package, &resource, params.index, &headers, on_complete).await
+}
+
+/// Called once the content has been transferred completely
+type OnComplete = Option<Box<dyn FnOnce() + Send>>;
+
+/// True for the file that every install downloads once, such that every install counts once
+///
+/// Resources that are extracted from an archive are not downloaded by themselves and skipped.
+fn counts_as_download(package: &DataPackage, resource: &str, index: usize) -> bool {
+    let first = package.resources.iter().find(|dr| dr.is_downloaded());
+    first.is_some_and(|dr| dr.name == resource) && index == 0
+}
+
+async fn resource_response(
+    state: &GatewayState,
+    package: &DataPackage,
+    resource: &str,
+    index: usize,
+    headers: &HeaderMap,
+    on_complete: OnComplete,
+) -> Result<Response, GatewayError> {
+    let not_found = |msg: String| GatewayError(StatusCode::NOT_FOUND, msg);
+    let name = package.name.as_deref().unwrap_or_default();
+    let version = package.version.as_deref().unwrap_or_default();
+
+    let ds = state.data_source();
+    let dr = package
+        .resources
+        .iter()
+        .find(|el| el.name == resource)
+        .ok_or_else(|| not_found(format!("Resource '{}' not found in '{}'", resource, name)))?;
+    let path = dr.path.as_ref().and_then(|p| p.nth(index)).ok_or_else(|| {
+        not_found(format!("Resource '{}' has no file at index {}", resource, index))
+    })?;
+
+    let mediatype = dr.mediatype.clone().unwrap_or("application/octet-stream".into());
+
+    if path.starts_with("http://") || path.starts_with("https://") {
+        let Some(mirror) = state.mirror() else {
+            return Ok(redirect(path, on_complete));
+        };
+        return match mirror.fetch(path, dr).await {
+            Ok(Mirrored::Blob(meta)) => {
+                blob_response(state, &meta.digest, mediatype, headers, on_complete).await
+            }
+            Ok(Mirrored::TooLarge(_)) => Ok(redirect(path, on_complete)),
+            Err(err) => Err(GatewayError(
+                StatusCode::BAD_GATEWAY,
+                format!("Mirroring resource '{}' failed: {}", resource, err),
+            )),
+        };
+    }
+
+    if let Some(digest) = BlobDigest::from_reference(path) {
+        return blob_response(state, &digest, mediatype, headers, on_complete).await;
+    }
+
+    // the folder of the descriptor that names the resource, not of another version
+    let folder = ds
+        .package_folder(name, version)
+        .await
+        .ok_or_else(|| not_found(format!("Package '{}' is not hosted by this registry", name)))?;
+    let file_path = resolve_relative(&folder, path)
+        .ok_or_else(|| GatewayError(StatusCode::BAD_REQUEST, format!("Invalid path '{}'", path)))?;
+
+    let mut file = tokio::fs::File::open(&file_path)
+        .await
+        .map_err(|_| not_found(format!("Resource file '{}' is not available", path)))?;
+    let io_error = |err: std::io::Error| {
+        GatewayError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Reading '{}' failed: {}", path, err),
+        )
+    };
+    let size = file.metadata().await.map_err(io_error)?.len();
+    let range = ByteRange::from_headers(headers, size);
+    if let ByteRange::Partial(first, _) = range {
+        file.seek(SeekFrom::Start(first)).await.map_err(io_error)?;
+    }
+    Ok(content_response(file, size, range, mediatype, on_complete))
+}
+
+/// Redirects to a remote resource, the transfer is out of sight and counts right away
+fn redirect(url: &str, on_complete: OnComplete) -> Response {
+    if let Some(on_complete) = on_complete {
+        on_complete();
+    }
+    Redirect::temporary(url).into_response()
+}
+
+/// Streams a blob from the blob store of the registry
+async fn blob_response(
+    state: &GatewayState,
+    digest: &BlobDigest,
+    mediatype: String,
+    headers: &HeaderMap,
+    on_complete: OnComplete,
+) -> Result<Response, GatewayError> {
+    let not_found = |msg: String| GatewayError(StatusCode::NOT_FOUND, msg);
+    let internal = |err: color_eyre::Report| {
+        GatewayError(StatusCode::INTERNAL_SERVER_ERROR, format!("Blob store failed: {}", err))
+    };
+
+    let blobs = state.blob_store().ok_or_else(|| {
+        not_found(format!("Blob {} is not hosted, no blob store configured", digest))
+    })?;
+    let unavailable = || not_found(format!("Blob {} is not available", digest));
+    let size = blobs.stat(digest).await.map_err(internal)?.ok_or_else(unavailable)?.size;
+    let range = ByteRange::from_headers(headers, size);
+    let offset = match range {
+        ByteRange::Partial(first, _) => first,
+        _ => 0,
+    };
+    let reader = blobs.get_from(digest, offset).await.map_err(internal)?.ok_or_else(unavailable)?;
+    Ok(content_response(reader, size, range, mediatype, on_complete))
+}
+
+/// The byte range requested by a `Range` header, resolved against the size of the content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteRange {
+    /// No range was requested, or one that is ignored such as several ranges or invalid syntax
+    Full,
+
+    /// The first and the last byte, both inclusive
+    Partial(u64, u64),
+
+    /// The range starts behind the end of the content
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Parses a single range of the forms `bytes=<first>-<last>`, `bytes=<first>-` and `bytes=-<n>`
+    fn from_headers(headers: &HeaderMap, size: u64) -> Self {
+        let Some(spec) = headers
+            .get(header::RANGE)
+            .and_then(|el| el.to_str().ok())
+            .and_then(|el| el.trim().strip_prefix("bytes="))
+            .filter(|el| !el.contains(','))
+        else {
+            return ByteRange::Full;
+        };
+        let Some((first, last)) = spec.split_once('-') else {
+            return ByteRange::Full;
+        };
+        let (first, last) = (first.trim(), last.trim());
+
+        if first.is_empty() {
+            // the suffix of the last n bytes
+            return match last.parse::<u64>() {
+                Ok(0) => ByteRange::Unsatisfiable,
+                Ok(_) if size == 0 => ByteRange::Unsatisfiable,
+                Ok(n) => ByteRange::Partial(size.saturating_sub(n), size - 1),
+                Err(_) => ByteRange::Full,
+            };
+        }
+        let Ok(first) = first.parse::<u64>() else {
+            return ByteRange::Full;
+        };
+        let last = match last {
+            "" => u64::MAX,
+            last => match last.parse::<u64>() {
+                Ok(last) if last >= first => last,
+                _ => return ByteRange::Full,
+            },
+        };
+        if first >= size {
+            ByteRange::Unsatisfiable
+        } else {
+            ByteRange::Partial(first, last.min(size - 1))
+        }
+    }
+}
+
+/// Streams a content of `size` bytes, the reader is already positioned at the start of the range
+///
+/// `on_complete` is called once the last byte of the content has been read, a range that ends
+/// before the last byte does not complete the content.
+fn content_response(
+    reader: impl AsyncRead + Send + 'static,
+    size: u64,
+    range: ByteRange,
+    mediatype: String,
+    on_complete: OnComplete,
+) -> Response {
+    let headers = [(header::CONTENT_TYPE, mediatype), (header::ACCEPT_RANGES, "bytes".into())];
+    match range {
+        ByteRange::Full => {
+            let reader = CompletionReader { inner: Box::pin(reader), remaining: size, on_complete };
+            let body = Body::from_stream(ReaderStream::new(reader));
+            (headers, [(header::CONTENT_LENGTH, size.to_string())], body).into_response()
+        }
+        ByteRange::Partial(first, last) => {
+            let on_complete = on_complete.filter(|_| last + 1 == size);
+            let reader = CompletionReader {
+                inner: Box::pin(reader.take(last - first + 1)),
+                remaining: last - first + 1,
+                on_complete,
+            };
+            let body = Body::from_stream(ReaderStream::new(reader));
+            let range_headers = [
+                (header::CONTENT_LENGTH, (last - first + 1).to_string()),
+                (header::CONTENT_RANGE, format!("bytes {}-{}/{}", first, last, size)),
+            ];
+            (StatusCode::PARTIAL_CONTENT, headers, range_headers, body).into_response()
+        }
+        ByteRange::Unsatisfiable => {
+            let content_range = format!("bytes */{}", size);
+            (StatusCode::RANGE_NOT_SATISFIABLE, [(header::CONTENT_RANGE, content_range)])
+                .into_response()
+        }
+    }
+}
+
+/// Calls its callback once the expected number of bytes has been read
+///
+/// The server stops to poll the body once `Content-Length` bytes are sent, so the end of the
+/// inner reader is never seen. A response that is dropped before does not call it.
+struct CompletionReader<R> {
+    inner: Pin<Box<R>>,
+
+    remaining: u64,
+
+    on_complete: OnComplete,
+}
+
+impl<R: AsyncRead> AsyncRead for CompletionReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = self.inner.as_mut().poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            let read = (buf.filled().len() - filled) as u64;
+            self.remaining = self.remaining.saturating_sub(read);
+            if self.remaining == 0
+                && let Some(on_complete) = self.on_complete.take()
+            {
+                on_complete();
+            }
+        }
+        poll
+    }
+}
+
+/// Joins a relative path onto a folder and rejects absolute paths or paths leaving the folder
+fn resolve_relative(folder: &Path, relative: &str) -> Option<PathBuf> {
+    let relative = Path::new(relative);
+    if relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        Some(folder.join(relative))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nebula_common::{
+        client::download::{DownloadManager, DownloadRequest, part_path},
+        configuration::cli::DownloadSettings,
+        datapackage::{
+            DataPackageNotValidated, DataResourceNotValidated, PathSingleOrVec, ValidateData as _,
+        },
+        nebula_proto::ServerInfo,
+        storage::{
+            blob::{BlobStore, local::LocalBlobStore},
+            root_folder::RootFolderSource,
+            stats::DownloadStats,
+        },
+    };
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    use super::*;
+
+    /// Hosts `iris` in the versions 1.0.0 and 2.0.0, each with its own `data.csv` and a shared blob
+    async fn hosted_registry(root: &str) -> (std::net::SocketAddr, Arc<DownloadStats>) {
+        let _ = std::fs::remove_dir_all(root);
+        let blobs = LocalBlobStore::new(Path::new(root).join("blobs")).unwrap();
+        let blob = blobs.put(Box::pin(&b"abcdefghij"[..])).await.unwrap();
+        let stats = Arc::new(DownloadStats::open(Path::new(root).join("downloads.log")).unwrap());
+        let mut ds = RootFolderSource::new_from_folder(Path::new(root).join("packages"));
+        for (version, content) in [("1.0.0", "old"), ("2.0.0", "0123456789")] {
+            let package = DataPackageNotValidated {
+                name: Some("iris".into()),
+                version: Some(version.into()),
+                id: Some(format!("00000000-0000-0000-0000-00000000000{}", &version[..1])),
+                resources: vec![
+                    DataResourceNotValidated {
+                        name: "data".into(),
+                        path: Some(PathSingleOrVec::Single("data.csv".into())),
+                        ..Default::default()
+                    },
+                    DataResourceNotValidated {
+                        name: "blob".into(),
+                        path: Some(PathSingleOrVec::Single(blob.digest.reference())),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            };
+            ds.put_package_metadata(&package.validate().unwrap()).await.unwrap();
+            let folder = Path::new(root).join("packages").join("iris").join(version);
+            std::fs::write(folder.join("data.csv"), content).unwrap();
+        }
+        let registry =
+            NebulaPackageQueryMockImpl::new(FederatedSource::local(ds), ServerInfo::default())
+                .with_blob_store(Arc::new(blobs))
+                .with_download_stats(stats.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router(Arc::new(registry))).into_future());
+        (addr, stats)
+    }
+
+    /// Sends a GET request and returns the status line, the headers and the body as text
+    async fn get(addr: std::net::SocketAddr, path: &str, headers: &[&str]) -> String {
+        let mut socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut request =
+            format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n", path);
+        for header in headers {
+            request.push_str(&format!("{}\r\n", header));
+        }
+        request.push_str("\r\n");
+        socket.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).await.unwrap();
+        response.to_lowercase()
+    }
+
+    #[tokio::test]
+    async fn test_download_version() {
+        let (addr, _) = hosted_registry("tmp_gateway_version").await;
+
+        let newest = get(addr, "/v1/packages/iris/resources/data", &[]).await;
+        assert!(
+            newest.starts_with("http/1.1 200") && newest.ends_with("\r\n\r\n0123456789"),
+            "{}",
+            newest
+        );
+        let old = get(addr, "/v1/packages/iris/resources/data?version=1.0.0", &[]).await;
+        assert!(old.starts_with("http/1.1 200") && old.ends_with("\r\n\r\nold"), "{}", old);
+        let missing = get(addr, "/v1/packages/iris/resources/data?version=3.0.0", &[]).await;
+        assert!(missing.starts_with("http/1.1 404") && missing.contains("iris@3.0.0"));
+
+        std::fs::remove_dir_all("tmp_gateway_version").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_range() {
+        let (addr, _) = hosted_registry("tmp_gateway_range").await;
+
+        for (resource, content) in [("data", "0123456789"), ("blob", "abcdefghij")] {
+            let path = format!("/v1/packages/iris/resources/{}", resource);
+            let full = get(addr, &path, &[]).await;
+            assert!(full.starts_with("http/1.1 200") && full.contains("accept-ranges: bytes"));
+            assert!(full.contains("content-length: 10"), "{}", full);
+
+            let tail = get(addr, &path, &["Range: bytes=4-"]).await;
+            assert!(tail.starts_with("http/1.1 206"), "{}", tail);
+            assert!(
+                tail.contains("content-range: bytes 4-9/10") && tail.contains("content-length: 6")
+            );
+            assert!(tail.ends_with(&format!("\r\n\r\n{}", &content[4..])), "{}", tail);
+            let middle = get(addr, &path, &["Range: bytes=2-3"]).await;
+            assert!(middle.contains("content-range: bytes 2-3/10"), "{}", middle);
+            assert!(middle.ends_with(&format!("\r\n\r\n{}", &content[2..4])), "{}", middle);
+            let suffix = get(addr, &path, &["Range: bytes=-3"]).await;
+            assert!(suffix.contains("content-range: bytes 7-9/10"), "{}", suffix);
+
+            let behind = get(addr, &path, &["Range: bytes=10-"]).await;
+            assert!(behind.starts_with("http/1.1 416"), "{}", behind);
+            assert!(behind.contains("content-range: bytes */10"));
+        }
+
+        std::fs::remove_dir_all("tmp_gateway_range").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_counts() {
+        let (addr, stats) = hosted_registry("tmp_gateway_counts").await;
+        let downloads = || stats.downloads("iris", Some("2.0.0"), &Default::default());
+
+        get(addr, "/v1/packages/iris/resources/data", &["Range: bytes=0-3"]).await;
+        get(addr, "/v1/packages/iris/resources/blob", &[]).await;
+        assert_eq!(downloads(), 0, "an interrupted download and other files do not count");
+        get(addr, "/v1/packages/iris/resources/data", &["Range: bytes=4-"]).await;
+        assert_eq!(downloads(), 1, "the resumed download completes the file");
+        get(addr, "/v1/packages/iris/resources/data", &[]).await;
+        assert_eq!(downloads(), 2);
+        get(addr, "/v1/packages/iris/resources/data?version=1.0.0", &[]).await;
+        assert_eq!(stats.downloads("iris", None, &Default::default()), 3);
+
+        std::fs::remove_dir_all("tmp_gateway_counts").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_download() {
+        let (addr, _) = hosted_registry("tmp_gateway_resume").await;
+        let folder = Path::new("tmp_gateway_resume").join("download");
+        std::fs::create_dir_all(&folder).unwrap();
+
+        let request = |resource: &str| DownloadRequest {
+            name: resource.into(),
+            url: format!("http://{}/v1/packages/iris/resources/{}", addr, resource),
+            target: folder.join(resource),
+            size: Some(10),
+        };
+        // previous attempts stopped after 4 bytes
+        std::fs::write(part_path(&folder.join("data")), "0123").unwrap();
+        std::fs::write(part_path(&folder.join("blob")), "abcd").unwrap();
+
+        let manager = DownloadManager::new(DownloadSettings::default()).unwrap();
+        let results = manager.download_all(&[request("data"), request("blob")]).await;
+        assert!(results.iter().all(|res| res.as_ref().is_ok_and(|bytes| *bytes == 10)));
+        assert_eq!(std::fs::read_to_string(folder.join("data")).unwrap(), "0123456789");
+        assert_eq!(std::fs::read_to_string(folder.join("blob")).unwrap(), "abcdefghij");
+
+        std::fs::remove_dir_all("tmp_gateway_resume").unwrap();
+    }
+
+    #[test]
+    fn test_byte_range() {
+        let range = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::RANGE, value.parse().unwrap());
+            ByteRange::from_headers(&headers, 10)
+        };
+        assert_eq!(ByteRange::from_headers(&HeaderMap::new(), 10), ByteRange::Full);
+        assert_eq!(range("bytes=0-"), ByteRange::Partial(0, 9));
+        assert_eq!(range("bytes=4-5"), ByteRange::Partial(4, 5));
+        assert_eq!(range("bytes=4-100"), ByteRange::Partial(4, 9));
+        assert_eq!(range("bytes=-3"), ByteRange::Partial(7, 9));
+        assert_eq!(range("bytes=-30"), ByteRange::Partial(0, 9));
+        assert_eq!(range("bytes=10-"), ByteRange::Unsatisfiable);
+        assert_eq!(range("bytes=-0"), ByteRange::Unsatisfiable);
+        assert_eq!(range("bytes=0-1,4-5"), ByteRange::Full);
+        assert_eq!(range("bytes=5-4"), ByteRange::Full);
+        assert_eq!(range("items=0-1"), ByteRange::Full);
+    }
+
+    #[test]
+    fn test_resolve_relative() {
+        let folder = Path::new("data/iris");
+        assert_eq!(resolve_relative(folder, "iris.csv"), Some(folder.join("iris.csv")));
+        assert_eq!(
+            resolve_relative(folder, "variables/v.index"),
+            Some(folder.join("variables/v.index"))
+        );
+        assert_eq!(resolve_relative(folder, "../cifar10/datapackage.json"), None);
+        assert_eq!(resolve_relative(folder, "/etc/passwd"), None);
+    }
+
+    #[test]
+    fn test_counts_as_download() {
+        use nebula_common::datapackage::DeltaDataResourceNotValidated;
+
+        let resource = |name: &str| DataResourceNotValidated {
+            name: name.into(),
+            path: Some(PathSingleOrVec::Single(format!("{}.bin", name))),
+            ..Default::default()
+        };
+        let member = DataResourceNotValidated {
+            delta: Some(DeltaDataResourceNotValidated {
+                origin: "local-archive".into(),
+                format: None,
+                local_storage: "installed".into(),
+                archive: None,
+            }),
+            ..resource("meta")
+        };
+        let package = DataPackageNotValidated {
+            name: Some("cifar-10-binary".into()),
+            resources: vec![member, resource("train"), resource("test")],
+            ..Default::default()
+        }
+        .validate()
+        .unwrap();
+
+        assert!(counts_as_download(&package, "train", 0));
+        assert!(!counts_as_download(&package, "train", 1));
+        assert!(!counts_as_download(&package, "test", 0));
+        assert!(!counts_as_download(&package, "meta", 0), "archive members are not downloaded");
+    }
+
+    #[test]
+    fn test_openapi_contains_routes() {
+        let (_, api) = api_router().split_for_parts();
+        let paths: Vec<_> = api.paths.paths.keys().cloned().collect();
+        assert!(paths.contains(&"/v1/packages".to_string()));
+        assert!(paths.contains(&"/v1/packages/{name}".to_string()));
+        assert!(paths.contains(&"/v1/search".to_string()));
+        assert!(paths.contains(&"/v1/packages/{name}/resources/{resource}".to_string()));
+    }
+}
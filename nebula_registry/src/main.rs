@@ -1,21 +1,38 @@
-use std::{env, path::PathBuf, str::FromStr};
+use std::{env, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
 
-use color_eyre::eyre::Report;
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{Report, eyre};
 use directories::ProjectDirs;
 use tonic::transport::Server;
 
 use lazy_static::lazy_static;
-use tracing::{info, level_filters::LevelFilter};
+use tracing::{error, info, level_filters::LevelFilter};
 
 use nebula_common::{
+    PROTOCOL_VERSIONS,
     configuration::{
-        registry::get_configuration,
+        registry::{Settings, get_configuration},
         tracing::{AppDefaultValuesFromEnv, initialize_logging, tracing_span_for_request},
     },
-    registry::{NebulaPackageQueryMockImpl, NebulaPackageQueryServer},
-    storage::root_folder::RootFolderSource,
+    model::{FieldSettings, FilterSettings, PagationSettings, SortSettings},
+    nebula_proto::{FILE_DESCRIPTOR_SET, RegistryFeature, ServerInfo},
+    registry::{
+        NebulaPackageQueryMockImpl, NebulaPackageQueryServer,
+        replication::{Replicator, spawn_replication},
+    },
+    storage::{
+        MetaDataSource,
+        blob::{
+            BlobStore, GcReport, collect_garbage, import_package, mirror::Mirror, referenced_blobs,
+        },
+        federated::FederatedSource,
+        root_folder::{RootFolderSource, WatchSettings, spawn_watcher},
+        stats::DownloadStats,
+    },
 };
 
+mod gateway;
+
 #[tokio::main]
 async fn main() -> Result<(), Report> {
     color_eyre::install()?;
@@ -34,32 +51,298 @@ async fn main() -> Result<(), Report> {
         initialize_logging(Some(LevelFilter::INFO), env_vars)?;
     }
 
+    let args = Args::parse();
     let config = get_configuration()?;
-    let app_conf = config.application;
 
-    let addr = format!("{}:{}", app_conf.host, app_conf.port).parse()?;
+    match args.cmd.unwrap_or(RegistryCommand::Serve) {
+        RegistryCommand::Serve => serve(config).await,
+        RegistryCommand::Import { folder, remove } => import(config, folder, remove).await,
+        RegistryCommand::Gc { dry_run } => gc(config, dry_run).await,
+        RegistryCommand::Replicate => replicate(config).await,
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(version = VERSION_MESSAGE, about)]
+struct Args {
+    #[command(subcommand)]
+    cmd: Option<RegistryCommand>,
+}
+
+#[derive(Debug, Subcommand)]
+enum RegistryCommand {
+    /// Serves the registry, the default if no command is given
+    Serve,
 
+    /// Moves the files of a package folder into the blob store and references them in the descriptor
+    Import {
+        /// folder that contains the datapackage.json
+        folder: PathBuf,
+
+        /// delete the imported files afterwards
+        #[arg(long)]
+        remove: bool,
+    },
+
+    /// Deletes blobs that are not referenced by any descriptor in the root folder
+    Gc {
+        /// only report the blobs that would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Replicates the packages of the upstream registry once and exits
+    Replicate,
+}
+
+fn root_folder_path(config: &Settings) -> Result<PathBuf, Report> {
     // todo: use one source for data path
-    let p = if let Some(root_folder) = config.root_folder {
+    Ok(if let Some(root_folder) = config.root_folder.as_ref() {
         PathBuf::from_str(&root_folder.path)?
     } else {
         get_data_dir().join("registry")
+    })
+}
+
+fn open_blob_store(config: &Settings) -> Result<Arc<dyn BlobStore>, Report> {
+    config
+        .blob_store
+        .as_ref()
+        .ok_or_else(|| eyre!("No blob store configured, add a 'blob_store' section"))?
+        .backend
+        .open()
+}
+
+/// The folder of the replicated packages, None if replication is not configured
+fn open_replica(config: &Settings) -> Result<Option<RootFolderSource>, Report> {
+    let Some(replication) = config.replication.as_ref() else {
+        return Ok(None);
+    };
+    std::fs::create_dir_all(&replication.path)?;
+    Ok(Some(RootFolderSource::new_from_folder(PathBuf::from_str(&replication.path)?)))
+}
+
+fn open_mirror(
+    config: &Settings,
+    blobs: &Arc<dyn BlobStore>,
+) -> Result<Option<Arc<Mirror>>, Report> {
+    config
+        .mirror
+        .as_ref()
+        .map(|settings| Ok(Arc::new(Mirror::new(blobs.clone(), settings.clone())?)))
+        .transpose()
+}
+
+async fn import(config: Settings, folder: PathBuf, remove: bool) -> Result<(), Report> {
+    let blobs = open_blob_store(&config)?;
+    let imported = import_package(blobs.as_ref(), &folder).await?;
+    let bytes: u64 = imported.iter().map(|(_, blob)| blob.size).sum();
+    println!("Imported {} files with {} bytes from '{}'", imported.len(), bytes, folder.display());
+    if remove {
+        for (file, _) in imported.iter() {
+            std::fs::remove_file(file)?;
+        }
+        println!("Removed the imported files");
+    }
+    Ok(())
+}
+
+async fn gc(config: Settings, dry_run: bool) -> Result<(), Report> {
+    let blobs = open_blob_store(&config)?;
+    let min_age = config.blob_store.as_ref().map(|el| el.gc_min_age_secs).unwrap_or_default();
+    let mirror = open_mirror(&config, &blobs)?;
+    let mut sources = vec![RootFolderSource::new_from_folder(root_folder_path(&config)?)];
+    sources.extend(open_replica(&config)?);
+    let min_age = Duration::from_secs(min_age);
+    let report =
+        collect_blobs(&sources, blobs.as_ref(), mirror.as_deref(), min_age, dry_run).await?;
+    let verb = if dry_run { "Would delete" } else { "Deleted" };
+    println!(
+        "{} {} blobs with {} bytes, kept {} blobs",
+        verb,
+        report.deleted.len(),
+        report.freed_bytes,
+        report.kept
+    );
+    Ok(())
+}
+
+async fn replicate(config: Settings) -> Result<(), Report> {
+    let settings =
+        config.replication.clone().ok_or_else(|| eyre!("No 'replication' section configured"))?;
+    let blobs = if settings.blobs { Some(open_blob_store(&config)?) } else { None };
+    let replica = open_replica(&config)?.expect("replication is configured");
+    let report = Replicator::new(settings, replica, blobs)?.replicate().await?;
+    println!(
+        "Replicated {} new and {} updated packages, removed {}, {} unchanged",
+        report.added.len(),
+        report.updated.len(),
+        report.removed.len(),
+        report.unchanged
+    );
+    for (package, reason) in report.failed.iter() {
+        println!("Failed to replicate '{}': {}", package, reason);
+    }
+    Ok(())
+}
+
+/// Collects the blobs that are not referenced by the packages of any source, shadowed packages
+/// of a replica keep their blobs
+async fn collect_blobs(
+    sources: &[RootFolderSource],
+    blobs: &dyn BlobStore,
+    mirror: Option<&Mirror>,
+    min_age: Duration,
+    dry_run: bool,
+) -> Result<GcReport, Report> {
+    let mut packages = vec![];
+    for ds in sources {
+        let listed = ds
+            .list_packages(
+                SortSettings::default(),
+                FilterSettings::default(),
+                PagationSettings::unlimited(),
+                FieldSettings::default(),
+            )
+            .await;
+        packages.extend(listed);
+    }
+    let mut referenced = referenced_blobs(&packages);
+    if let Some(mirror) = mirror {
+        referenced.extend(mirror.referenced_blobs(&packages));
+    }
+    collect_garbage(blobs, &referenced, min_age, dry_run).await
+}
+
+/// The features of the enabled subsystems, searching is always served by the gRPC interface
+fn registry_features(config: &Settings) -> Vec<RegistryFeature> {
+    let mut features = vec![RegistryFeature::Search];
+    // resources are downloaded through the gateway only
+    if config.gateway.is_some() {
+        features.push(RegistryFeature::Download);
+    }
+    features
+}
+
+async fn serve(config: Settings) -> Result<(), Report> {
+    let app_conf = config.application.clone();
+
+    let addr = format!("{}:{}", app_conf.host, app_conf.port).parse()?;
+
+    let mut watch_settings = WatchSettings::default();
+    if let Some(root_folder) = config.root_folder.as_ref() {
+        watch_settings.watch = root_folder.watch;
+        watch_settings.rescan_interval = root_folder.rescan_interval_secs.map(Duration::from_secs);
+    }
+    let p = root_folder_path(&config)?;
+
+    let server_info = ServerInfo {
+        name: app_conf.name.clone(),
+        version: VERSION_MESSAGE.to_string(),
+        protocol_versions: PROTOCOL_VERSIONS.iter().map(|v| v.to_string()).collect(),
+        features: registry_features(&config).into_iter().map(|el| el as i32).collect(),
     };
 
     let ds = RootFolderSource::new_from_folder(p);
-    let registry = NebulaPackageQueryMockImpl::new(ds);
+    let _watcher = spawn_watcher(ds.clone(), watch_settings);
+    let mut sources = vec![ds.clone()];
+    let mut federated = FederatedSource::local(ds);
+    let replica = open_replica(&config)?;
+    if let (Some(replication), Some(replica)) = (config.replication.as_ref(), replica) {
+        info!(
+            "Replicating '{}:{}' into '{}'",
+            replication.host, replication.port, replication.path
+        );
+        federated = federated.with_replica(replica.clone(), replication.conflict);
+        sources.push(replica);
+    }
+
+    let mut registry = NebulaPackageQueryMockImpl::new(federated.clone(), server_info);
+    if let Some(statistics) = config.statistics.as_ref() {
+        registry = registry.with_download_stats(Arc::new(DownloadStats::open(&statistics.path)?));
+    }
+    let mut replication_blobs = None;
+    if let Some(blob_conf) = config.blob_store.as_ref() {
+        let blobs = blob_conf.backend.open()?;
+        let mirror = open_mirror(&config, &blobs)?;
+        if let Some(interval) = blob_conf.gc_interval_secs {
+            spawn_gc(sources, blobs.clone(), mirror.clone(), interval, blob_conf.gc_min_age_secs);
+        }
+        replication_blobs = Some(blobs.clone());
+        if let Some(mirror) = mirror {
+            info!("Mirroring remote resources");
+            registry = registry.with_mirror(mirror);
+        }
+        registry = registry.with_blob_store(blobs);
+    } else if config.mirror.is_some() {
+        return Err(eyre!("The mirror needs a blob store, add a 'blob_store' section"));
+    }
+    let registry = Arc::new(registry);
+
+    if let (Some(settings), Some(replica)) =
+        (config.replication.clone(), federated.replica_source())
+    {
+        let blobs = if settings.blobs { replication_blobs } else { None };
+        let interval = Duration::from_secs(settings.interval_secs);
+        spawn_replication(Replicator::new(settings, replica.clone(), blobs)?, interval);
+    }
+
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    health_reporter
+        .set_serving::<NebulaPackageQueryServer<NebulaPackageQueryMockImpl<FederatedSource>>>()
+        .await;
+
+    let reflection_service = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .build_v1()?;
 
     info!("{}", version());
     info!("Nebula Registry v0.1.0 - running on: '{}'", addr);
-    Server::builder()
+    let grpc = Server::builder()
         .trace_fn(tracing_span_for_request)
-        .add_service(NebulaPackageQueryServer::new(registry))
-        .serve(addr)
-        .await?;
+        .add_service(health_service)
+        .add_service(reflection_service)
+        .add_service(NebulaPackageQueryServer::from_arc(registry.clone()))
+        .serve(addr);
+
+    if let Some(gateway_conf) = config.gateway {
+        let gateway_addr = format!("{}:{}", app_conf.host, gateway_conf.port).parse()?;
+        tokio::try_join!(async { grpc.await.map_err(Report::from) }, async {
+            gateway::serve(gateway_addr, registry).await.map_err(Report::from)
+        },)?;
+    } else {
+        grpc.await?;
+    }
 
     Ok(())
 }
 
+/// Runs the garbage collection of blobs periodically
+fn spawn_gc(
+    sources: Vec<RootFolderSource>,
+    blobs: Arc<dyn BlobStore>,
+    mirror: Option<Arc<Mirror>>,
+    interval_secs: u64,
+    min_age_secs: u64,
+) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            ticker.tick().await;
+            let min_age = Duration::from_secs(min_age_secs);
+            match collect_blobs(&sources, blobs.as_ref(), mirror.as_deref(), min_age, false).await {
+                Ok(report) => info!(
+                    "Garbage collection deleted {} blobs with {} bytes",
+                    report.deleted.len(),
+                    report.freed_bytes
+                ),
+                Err(err) => error!("Garbage collection of blobs failed: {}", err),
+            }
+        }
+    });
+}
+
 lazy_static! {
     pub static ref PROJECT_NAME: String = env!("CARGO_CRATE_NAME").to_uppercase().to_string();
     pub static ref DATA_FOLDER: Option<PathBuf> =
@@ -71,25 +354,23 @@ lazy_static! {
 }
 
 pub fn get_data_dir() -> PathBuf {
-    let directory = if let Some(folder) = DATA_FOLDER.clone() {
+    if let Some(folder) = DATA_FOLDER.clone() {
         folder
     } else if let Some(proj_dirs) = project_directory() {
         proj_dirs.data_local_dir().to_path_buf()
     } else {
         PathBuf::from(".").join(".data")
-    };
-    directory
+    }
 }
 
 pub fn get_config_dir() -> PathBuf {
-    let directory = if let Some(folder) = CONFIG_FOLDER.clone() {
+    if let Some(folder) = CONFIG_FOLDER.clone() {
         folder
     } else if let Some(proj_dirs) = project_directory() {
         proj_dirs.config_local_dir().to_path_buf()
     } else {
         PathBuf::from(".").join(".config")
-    };
-    directory
+    }
 }
 
 fn project_directory() -> Option<ProjectDirs> {
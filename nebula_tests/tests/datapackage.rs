@@ -47,3 +47,15 @@ fn test_load_mobilenetv3_tf2() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_sizes_mobilenetv3_tf2() -> Result<(), Box<dyn std::error::Error>> {
+    let filepath = "../nebula_registry/data/mobilenetv3_tf2/datapackage.json";
+    let dp = datapackage_meta_from_file_not_validated(Path::new(filepath))?;
+
+    // only the archive is downloaded, its extracted members are kept
+    assert_eq!(dp.download_size(), 17_528_126);
+    assert_eq!(dp.installed_size(), 1_413_777 + 153_650_000 + 16_090_768);
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+//! Integration tests for the replication of an upstream registry
+
+use std::{fs, path::Path};
+
+use nebula_common::{
+    configuration::{cli::ConnectionSettings, registry::ReplicationSettings},
+    model::{FieldSettings, FilterSettings, PagationSettings, SortSettings},
+    nebula_proto::ServerInfo,
+    registry::{NebulaPackageQueryMockImpl, NebulaPackageQueryServer, replication::Replicator},
+    storage::{MetaDataSource, root_folder::RootFolderSource},
+};
+use tonic::transport::{Server, server::TcpIncoming};
+
+/// Copies the descriptors of the sample data into a folder that the test may modify
+fn copy_sample_data(target: &Path) {
+    for name in ["cifar10", "iris", "mobilenetv3_tf2"] {
+        let folder = target.join(name);
+        fs::create_dir_all(&folder).unwrap();
+        let source = Path::new("../nebula_registry/data").join(name).join("datapackage.json");
+        fs::copy(source, folder.join("datapackage.json")).unwrap();
+    }
+}
+
+/// Serves the root folder on a free port and returns the port
+async fn serve(upstream: RootFolderSource) -> u16 {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+    let server_info = ServerInfo {
+        name: "upstream".into(),
+        version: "test".into(),
+        protocol_versions: vec!["v1".into()],
+        features: vec![],
+    };
+    let registry = NebulaPackageQueryMockImpl::new(upstream, server_info);
+    tokio::spawn(
+        Server::builder()
+            .add_service(NebulaPackageQueryServer::new(registry))
+            .serve_with_incoming(incoming),
+    );
+    port
+}
+
+async fn names(source: &RootFolderSource) -> Vec<String> {
+    source
+        .list_packages(
+            SortSettings::default(),
+            FilterSettings::default(),
+            PagationSettings::unlimited(),
+            FieldSettings::default(),
+        )
+        .await
+        .into_iter()
+        .filter_map(|dp| dp.name.clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_replicate_upstream() -> Result<(), Box<dyn std::error::Error>> {
+    let root = Path::new("tmp_replication");
+    let _ = fs::remove_dir_all(root);
+    copy_sample_data(&root.join("upstream"));
+    fs::create_dir_all(root.join("replica"))?;
+
+    let upstream = RootFolderSource::new_from_folder(root.join("upstream"));
+    let port = serve(upstream.clone()).await;
+    let settings = ReplicationSettings {
+        host: "127.0.0.1".into(),
+        port,
+        connection: ConnectionSettings::default(),
+        gateway_url: Some("http://upstream:12346/".into()),
+        path: root.join("replica").display().to_string(),
+        interval_secs: 300,
+        blobs: false,
+        conflict: Default::default(),
+    };
+    let replica = RootFolderSource::new_from_folder(root.join("replica"));
+    let mut replicator = Replicator::new(settings.clone(), replica.clone(), None)?;
+
+    let report = replicator.replicate().await?;
+    assert_eq!(report.added.len(), 3);
+    assert!(report.failed.is_empty());
+    assert_eq!(names(&replica).await, names(&upstream).await);
+
+    // files next to the upstream descriptor are referenced at the upstream gateway
+    let iris = replica.get_package("iris-classical", FilterSettings::default()).await.unwrap();
+    let path = iris.resources[0].path.as_ref().unwrap().nth(0).unwrap();
+    assert_eq!(
+        path,
+        "http://upstream:12346/v1/packages/iris-classical/resources/iris-table?index=0"
+    );
+
+    // the state survives a restart, unchanged packages are skipped
+    let mut replicator = Replicator::new(settings, replica.clone(), None)?;
+    let report = replicator.replicate().await?;
+    assert_eq!(report.unchanged, 3);
+
+    // packages that vanish upstream are removed from the replica
+    fs::remove_dir_all(root.join("upstream").join("iris"))?;
+    upstream.rescan();
+    let report = replicator.replicate().await?;
+    assert_eq!(report.removed, vec!["iris-classical@1.0.0".to_string()]);
+    assert!(replica.get_package("iris-classical", FilterSettings::default()).await.is_none());
+    assert!(!root.join("replica").join("iris-classical").exists());
+
+    // a new version upstream is added next to the replicated versions
+    let descriptor =
+        fs::read_to_string(root.join("upstream").join("cifar10").join("datapackage.json"))?
+            .replace(r#""version": "1.0.0""#, r#""version": "2.0.0""#)
+            .replace(
+                "19c5b9ae-2478-4973-857f-bf2568df537d",
+                "19c5b9ae-2478-4973-857f-bf2568df5372",
+            );
+    fs::create_dir_all(root.join("upstream").join("cifar10-v2"))?;
+    fs::write(root.join("upstream").join("cifar10-v2").join("datapackage.json"), descriptor)?;
+    upstream.rescan();
+    let report = replicator.replicate().await?;
+    assert_eq!(report.added, vec!["cifar-10-binary@2.0.0".to_string()]);
+    assert!(report.updated.is_empty());
+    assert_eq!(report.unchanged, 2);
+
+    fs::remove_dir_all(root)?;
+    Ok(())
+}